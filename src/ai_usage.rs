@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::TimeZone;
+use chrono_tz::Europe::Amsterdam;
+use serde::{Deserialize, Serialize};
+
+use crate::settings::{config_dir, ServerProfile};
+
+const USAGE_FILE: &str = "ai-usage.json";
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+/// Records older than this are dropped when a new one is added.
+const RETENTION_DAYS: u64 = 90;
+
+/// One finished answer to an `/ai` this client sent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AiUsageRecord {
+    /// Id of the server profile the question was sent from. Empty in
+    /// records written before it was stored.
+    #[serde(default)]
+    pub profile_id: String,
+    /// That profile's name at the time, shown once the profile is gone.
+    #[serde(default)]
+    pub profile: String,
+    pub server_url: String,
+    pub at: u64,
+    pub response_ms: u64,
+    #[serde(default)]
+    pub tokens: Option<u32>,
+    #[serde(default)]
+    pub cost: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct UsageTotals {
+    pub answers: usize,
+    pub tokens: u64,
+    pub cost: f64,
+}
+
+impl UsageTotals {
+    pub fn sum<'a>(records: impl IntoIterator<Item = &'a AiUsageRecord>) -> Self {
+        let mut totals = Self::default();
+        for record in records {
+            totals.add(record);
+        }
+        totals
+    }
+
+    fn add(&mut self, record: &AiUsageRecord) {
+        self.answers += 1;
+        self.tokens += u64::from(record.tokens.unwrap_or(0));
+        self.cost += record.cost.unwrap_or(0.0);
+    }
+
+    fn merge(&mut self, other: &UsageTotals) {
+        self.answers += other.answers;
+        self.tokens += other.tokens;
+        self.cost += other.cost;
+    }
+
+    pub fn describe(&self) -> String {
+        format!("{}× • {} tokens • ${:.4}", self.answers, self.tokens, self.cost)
+    }
+}
+
+/// Calendar day of `unix_ms` in Dutch time, as `YYYY-MM-DD`.
+pub fn day_key(unix_ms: u64) -> String {
+    match Amsterdam.timestamp_millis_opt(unix_ms as i64).single() {
+        Some(dt) => dt.format("%Y-%m-%d").to_string(),
+        None => "????-??-??".to_string(),
+    }
+}
+
+/// Nearest-rank percentile `p` (0-100) of `values`.
+pub fn percentile(values: &[u64], p: u32) -> Option<u64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    let rank = (sorted.len() * p as usize).div_ceil(100);
+    sorted.get(rank.saturating_sub(1)).copied()
+}
+
+/// Warning shown before another `/ai` once today's spending reached `limit`.
+pub fn limit_warning(spent_today: f64, limit: f64) -> Option<String> {
+    (spent_today >= limit).then(|| {
+        format!(
+            "AI-daglimiet bereikt: vandaag ${:.4} uitgegeven, limiet ${:.2}.",
+            spent_today, limit
+        )
+    })
+}
+
+/// Own AI answers of every session. Persisted so day and profile totals
+/// survive a restart.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AiUsageLog {
+    pub records: Vec<AiUsageRecord>,
+    // File this log was loaded from; `None` keeps it in memory only
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+fn usage_path() -> PathBuf {
+    match config_dir() {
+        Some(dir) => dir.join(USAGE_FILE),
+        None => PathBuf::from(".cybox-chat-gui-ai-usage.json"),
+    }
+}
+
+impl AiUsageLog {
+    pub fn load() -> Self {
+        let path = usage_path();
+        let records = fs::read_to_string(&path)
+            .ok()
+            .and_then(|raw| serde_json::from_str::<AiUsageLog>(&raw).ok())
+            .map(|log| log.records)
+            .unwrap_or_default();
+        Self {
+            records,
+            path: Some(path),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|err| format!("Failed to create AI usage directory: {}", err))?;
+        }
+        let json = serde_json::to_string(self)
+            .map_err(|err| format!("Failed to serialize AI usage: {}", err))?;
+
+        fs::write(path, json).map_err(|err| format!("Failed to write AI usage file: {}", err))
+    }
+
+    pub fn push(&mut self, record: AiUsageRecord) {
+        let cutoff = record.at.saturating_sub(RETENTION_DAYS * DAY_MS);
+        self.records.retain(|existing| existing.at >= cutoff);
+        self.records.push(record);
+    }
+
+    pub fn day_totals(&self, day: &str) -> UsageTotals {
+        UsageTotals::sum(self.records.iter().filter(|record| day_key(record.at) == day))
+    }
+
+    /// Totals per day, oldest first.
+    pub fn per_day(&self) -> BTreeMap<String, UsageTotals> {
+        let mut days = BTreeMap::<String, UsageTotals>::new();
+        for record in &self.records {
+            days.entry(day_key(record.at)).or_default().add(record);
+        }
+        days
+    }
+
+    /// Totals per profile, labelled with the profile's current name in
+    /// `profiles`. Deleted profiles keep their last known name; older records
+    /// without a profile id fall under their name or server URL.
+    pub fn per_profile(&self, profiles: &[ServerProfile]) -> BTreeMap<String, UsageTotals> {
+        // Records are oldest first, so the last name seen per id is the newest
+        let mut by_id = BTreeMap::<&str, (&str, UsageTotals)>::new();
+        for record in &self.records {
+            let label = if record.profile.is_empty() {
+                record.server_url.as_str()
+            } else {
+                record.profile.as_str()
+            };
+            let key = if record.profile_id.is_empty() {
+                label
+            } else {
+                record.profile_id.as_str()
+            };
+            let group = by_id.entry(key).or_default();
+            group.0 = label;
+            group.1.add(record);
+        }
+        let mut labelled = BTreeMap::<String, UsageTotals>::new();
+        for (key, (label, totals)) in by_id {
+            let name = profiles
+                .iter()
+                .find(|profile| profile.id == key)
+                .map_or(label, |profile| profile.name.as_str());
+            labelled.entry(name.to_string()).or_default().merge(&totals);
+        }
+        labelled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{day_key, limit_warning, percentile, AiUsageLog, AiUsageRecord, DAY_MS};
+    use crate::settings::ServerProfile;
+
+    fn record(profile: &str, at: u64, tokens: u32, cost: f64) -> AiUsageRecord {
+        AiUsageRecord {
+            profile_id: format!("id-{}", profile),
+            profile: profile.to_string(),
+            server_url: "ws://localhost:8080".to_string(),
+            at,
+            response_ms: 100,
+            tokens: Some(tokens),
+            cost: Some(cost),
+        }
+    }
+
+    #[test]
+    fn sums_per_day_and_profile_and_prunes_old_records() {
+        // 2025-01-01 12:00 UTC, 13:00 in Amsterdam
+        let noon = 1_735_732_800_000;
+        let mut log = AiUsageLog::default();
+        log.push(record("Thuis", noon - 200 * DAY_MS, 1, 1.0));
+        log.push(record("Thuis", noon - DAY_MS, 10, 0.01));
+        log.push(record("Thuis", noon, 20, 0.02));
+        log.push(record("Werk", noon + 1000, 30, 0.03));
+        assert_eq!(log.records.len(), 3);
+
+        let today = log.day_totals(&day_key(noon));
+        assert_eq!((today.answers, today.tokens), (2, 50));
+        assert!((today.cost - 0.05).abs() < 1e-9);
+        assert_eq!(
+            log.per_day().keys().cloned().collect::<Vec<_>>(),
+            vec!["2024-12-31", "2025-01-01"]
+        );
+        // "Thuis" was renamed since; its usage follows the id
+        let mut thuis = ServerProfile::new("Thuis", "ws://localhost:8080");
+        thuis.id = "id-Thuis".to_string();
+        thuis.name = "Thuiswerk".to_string();
+        let per_profile = log.per_profile(&[thuis]);
+        assert_eq!(per_profile["Thuiswerk"].answers, 2);
+        assert!(!per_profile.contains_key("Thuis"));
+        assert_eq!(per_profile["Werk"].answers, 1);
+        let mut legacy = record("", noon + 2000, 1, 0.0);
+        legacy.profile_id.clear();
+        log.push(legacy);
+        assert_eq!(log.per_profile(&[])["ws://localhost:8080"].answers, 1);
+        assert!(limit_warning(today.cost, 0.05).is_some());
+        assert!(limit_warning(today.cost, 0.10).is_none());
+    }
+
+    #[test]
+    fn percentiles_use_nearest_rank() {
+        let values = (1..=10).map(|v| v * 100).collect::<Vec<u64>>();
+        assert_eq!(percentile(&values, 50), Some(500));
+        assert_eq!(percentile(&values, 90), Some(900));
+        assert_eq!(percentile(&values, 99), Some(1000));
+        assert_eq!(percentile(&[], 50), None);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::protocol::Incoming;
+
+/// Optional handshake header with the server version, read before the first
+/// `status` answer arrives.
+pub const SERVER_VERSION_HEADER: &str = "x-server-version";
+
+/// Server versions this client was tested against: `MIN` inclusive, `MAX`
+/// exclusive.
+const MIN_TESTED_VERSION: (u64, u64, u64) = (0, 1, 0);
+const MAX_TESTED_VERSION: (u64, u64, u64) = (2, 0, 0);
+
+/// What the connected server supports, filled from the handshake and the
+/// `status` sent automatically on connect. Unknown means supported, so an
+/// older server without these fields keeps working as before.
+#[derive(Debug, Clone, Default)]
+pub struct Capabilities {
+    pub version: Option<String>,
+    pub ai_enabled: Option<bool>,
+    pub ai_model: Option<String>,
+}
+
+impl Capabilities {
+    pub fn from_headers(headers: &[(String, String)]) -> Self {
+        let version = headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(SERVER_VERSION_HEADER))
+            .map(|(_, value)| value.clone());
+        Self {
+            version,
+            ..Self::default()
+        }
+    }
+
+    /// Takes over version and AI fields from a `status` answer. Returns the
+    /// version warning when the version is new and outside the tested range.
+    pub fn apply_status(&mut self, status: &Incoming) -> Option<String> {
+        let Incoming::Status {
+            version,
+            ai_enabled,
+            ai_model,
+            ..
+        } = status
+        else {
+            return None;
+        };
+        self.ai_enabled = *ai_enabled;
+        self.ai_model = ai_model.clone();
+        if self.version.as_deref() == Some(version.as_str()) {
+            return None;
+        }
+        self.version = Some(version.clone());
+        version_warning(version)
+    }
+
+    pub fn supports_ai(&self) -> bool {
+        self.ai_enabled != Some(false)
+    }
+}
+
+/// First `major.minor.patch` in `version`, so `v1.4`, `1.4.2-beta` and
+/// `mock-0.1.2` all parse. Missing parts count as 0.
+pub fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let start = version.find(|c: char| c.is_ascii_digit())?;
+    let mut parts = version[start..]
+        .split(|c: char| !c.is_ascii_digit() && c != '.')
+        .next()?
+        .split('.')
+        .map(|part| part.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().flatten().unwrap_or(0);
+    let patch = parts.next().flatten().unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+fn format_version((major, minor, patch): (u64, u64, u64)) -> String {
+    format!("{}.{}.{}", major, minor, patch)
+}
+
+pub fn version_warning(version: &str) -> Option<String> {
+    let range = format!(
+        "{} tot {}",
+        format_version(MIN_TESTED_VERSION),
+        format_version(MAX_TESTED_VERSION)
+    );
+    match parse_version(version) {
+        Some(parsed) if (MIN_TESTED_VERSION..MAX_TESTED_VERSION).contains(&parsed) => None,
+        Some(_) => Some(format!(
+            "Serverversie {} valt buiten het geteste bereik ({}); niet alles werkt mogelijk.",
+            version, range
+        )),
+        None => Some(format!(
+            "Serverversie '{}' is niet te herkennen; getest met {}.",
+            version, range
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_version, version_warning, Capabilities};
+    use crate::protocol::{parse_incoming_text, Incoming, IncomingParse};
+
+    #[test]
+    fn versions_parse_leniently_and_are_range_checked() {
+        assert_eq!(parse_version("mock-0.1.2"), Some((0, 1, 2)));
+        assert_eq!(parse_version("v1.4"), Some((1, 4, 0)));
+        assert_eq!(parse_version("1.4.2-beta"), Some((1, 4, 2)));
+        assert_eq!(parse_version("dev"), None);
+        assert!(version_warning("1.9.9").is_none());
+        assert!(version_warning("2.0.0").is_some());
+        assert!(version_warning("dev").is_some());
+    }
+
+    #[test]
+    fn status_fills_capabilities_and_warns_once() {
+        let json = r#"{"type":"status","version":"3.0.0","uptimeSeconds":1,"userCount":1,"messagesSent":0,"messagesPerSecond":0.0,"memoryMb":1.0,"aiEnabled":false}"#;
+        let IncomingParse::Message(status @ Incoming::Status { .. }) = parse_incoming_text(json) else {
+            panic!("expected status");
+        };
+        let mut caps = Capabilities::from_headers(&[("X-Server-Version".to_string(), "1.0.0".to_string())]);
+        assert_eq!(caps.version.as_deref(), Some("1.0.0"));
+        assert!(caps.supports_ai());
+        assert!(caps.apply_status(&status).is_some());
+        assert!(caps.apply_status(&status).is_none());
+        assert!(!caps.supports_ai());
+    }
+}
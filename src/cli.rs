@@ -0,0 +1,459 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::BufRead;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+
+use crate::network::{start_connection, ReconnectPolicy, UiEvent, WsCommand};
+use crate::protocol::{format_at_prefix, parse_user_input, Incoming, Outgoing, ParsedInput};
+use crate::session::{ai_stats, is_guest_name, status_rows};
+use crate::settings::{load_settings, TlsOptions};
+
+const DEFAULT_LINGER_SECS: f32 = 2.0;
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+const USAGE: &str = "Gebruik: cybox-chat-gui --headless [opties]
+
+Leest commando's (zoals in het invoerveld) regel voor regel van stdin en
+print binnenkomende berichten naar stdout.
+
+Opties:
+  --profile <naam>   Serverprofiel uit de settings gebruiken
+  --url <url>        Server-URL (overschrijft het profiel)
+  --name <naam>      Naam die na het verbinden wordt gezet
+  --linger <sec>     Na einde van stdin nog zo lang wachten op antwoorden (standaard 2)
+  --reconnect        Automatisch herverbinden bij verbindingsverlies
+  --raw              Raw frames naar stderr printen
+  --insecure         Ongeldige TLS-certificaten en hostnames accepteren
+  -h, --help         Deze hulp tonen";
+
+#[derive(Debug, PartialEq)]
+struct CliOptions {
+    profile: Option<String>,
+    url: Option<String>,
+    name: Option<String>,
+    linger: Duration,
+    reconnect: bool,
+    raw: bool,
+    insecure: bool,
+}
+
+fn parse_args(args: &[String]) -> Result<Option<CliOptions>, String> {
+    let mut options = CliOptions {
+        profile: None,
+        url: None,
+        name: None,
+        linger: Duration::from_secs_f32(DEFAULT_LINGER_SECS),
+        reconnect: false,
+        raw: false,
+        insecure: false,
+    };
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = |flag: &str| {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| format!("{} verwacht een waarde", flag))
+        };
+        match arg.as_str() {
+            "--headless" => {}
+            "--profile" => options.profile = Some(value(arg)?),
+            "--url" => options.url = Some(value(arg)?),
+            "--name" => options.name = Some(value(arg)?),
+            "--linger" => {
+                let raw = value(arg)?;
+                let secs = raw
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|secs| secs.is_finite() && *secs >= 0.0)
+                    .ok_or_else(|| format!("Ongeldige waarde voor --linger: {}", raw))?;
+                options.linger = Duration::from_secs_f32(secs);
+            }
+            "--reconnect" => options.reconnect = true,
+            "--raw" => options.raw = true,
+            "--insecure" => options.insecure = true,
+            "-h" | "--help" => return Ok(None),
+            other => return Err(format!("Onbekende optie: {}", other)),
+        }
+    }
+    Ok(Some(options))
+}
+
+/// Runs the client without a window and returns the process exit code:
+/// 0 after a clean run, 1 when the connection failed or dropped, 2 on bad arguments.
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return 0;
+        }
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            return 2;
+        }
+    };
+
+    let settings = load_settings();
+    let profile_name = options
+        .profile
+        .clone()
+        .unwrap_or_else(|| settings.active_profile.clone());
+    let Some(profile) = settings.profiles.iter().find(|p| p.name == profile_name) else {
+        eprintln!("Onbekend profiel: {}", profile_name);
+        return 2;
+    };
+    let url = options
+        .url
+        .clone()
+        .unwrap_or_else(|| profile.server_url.clone());
+    let name = options
+        .name
+        .clone()
+        .unwrap_or_else(|| profile.username.clone());
+    let tls = if options.insecure {
+        TlsOptions {
+            accept_invalid_certs: true,
+            accept_invalid_hostnames: true,
+        }
+    } else {
+        profile.tls.clone()
+    };
+    let reconnect = ReconnectPolicy {
+        enabled: options.reconnect,
+        max_attempts: settings.reconnect_max_attempts,
+        ..ReconnectPolicy::default()
+    };
+
+    eprintln!("Verbinden met {}...", url);
+    let (ui_tx, ui_rx) = channel::<UiEvent>();
+    let ws_tx = start_connection(url, tls, reconnect, ui_tx, egui::Context::default());
+    let stdin_rx = spawn_stdin_reader();
+
+    let mut headless = Headless {
+        ws_tx,
+        name,
+        connected: false,
+        pending_input: VecDeque::new(),
+        pending_pings: HashMap::new(),
+        ai_streams: HashMap::new(),
+        raw: options.raw,
+    };
+    let mut stdin_open = true;
+    let mut linger_until: Option<Instant> = None;
+    let mut disconnect_sent = false;
+
+    loop {
+        while let Ok(line) = stdin_rx.try_recv() {
+            match line {
+                Some(line) => headless.pending_input.push_back(line),
+                None => stdin_open = false,
+            }
+        }
+        if headless.connected {
+            headless.flush_input();
+        }
+        if !stdin_open && headless.pending_input.is_empty() && linger_until.is_none() {
+            linger_until = Some(Instant::now() + options.linger);
+        }
+        if !disconnect_sent && linger_until.is_some_and(|until| Instant::now() >= until) {
+            let _ = headless.ws_tx.send(WsCommand::Disconnect);
+            disconnect_sent = true;
+        }
+
+        match ui_rx.recv_timeout(POLL_INTERVAL) {
+            Ok(UiEvent::Disconnected(reason)) => {
+                return match reason {
+                    Some(reason) => {
+                        eprintln!("error: {}", reason);
+                        1
+                    }
+                    None if disconnect_sent => 0,
+                    None => {
+                        // A failed first connect was already reported as an Error event.
+                        if headless.connected {
+                            eprintln!("Verbinding gesloten door de server.");
+                        }
+                        1
+                    }
+                };
+            }
+            Ok(event) => headless.handle_event(event),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return if disconnect_sent { 0 } else { 1 },
+        }
+    }
+}
+
+/// Reads stdin on its own thread; `None` marks end of input.
+fn spawn_stdin_reader() -> Receiver<Option<String>> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if tx.send(Some(line)).is_err() {
+                return;
+            }
+        }
+        let _ = tx.send(None);
+    });
+    rx
+}
+
+struct Headless {
+    ws_tx: tokio::sync::mpsc::UnboundedSender<WsCommand>,
+    name: String,
+    connected: bool,
+    // Lines typed before the connection was up are sent once it is.
+    pending_input: VecDeque<String>,
+    pending_pings: HashMap<String, Instant>,
+    // Streaming AI answers per stream id: (from, prompt, text so far)
+    ai_streams: HashMap<String, (String, String, String)>,
+    raw: bool,
+}
+
+impl Headless {
+    fn flush_input(&mut self) {
+        while let Some(line) = self.pending_input.pop_front() {
+            self.send_input(&line);
+        }
+    }
+
+    fn send_input(&mut self, line: &str) {
+        let outgoing = match parse_user_input(line) {
+            ParsedInput::Empty => return,
+            ParsedInput::Error(err) => {
+                eprintln!("error: {}", err);
+                return;
+            }
+            ParsedInput::Chat(text) => Outgoing::Chat { text, room: None },
+            ParsedInput::SetName(name) => Outgoing::SetName { name },
+            ParsedInput::Status => Outgoing::Status,
+            ParsedInput::ListUsers => Outgoing::ListUsers,
+            ParsedInput::Ping(token) => {
+                let token = token.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+                self.pending_pings.insert(token.clone(), Instant::now());
+                Outgoing::Ping { token: Some(token) }
+            }
+            ParsedInput::Ai(prompt) => Outgoing::Ai {
+                prompt,
+                thread: None,
+                context: Vec::new(),
+                request_id: None,
+            },
+            ParsedInput::Dm { to, text } => Outgoing::Dm { to, text },
+            ParsedInput::Join(room) => Outgoing::Join { room },
+            ParsedInput::Leave(Some(room)) => Outgoing::Leave { room },
+            ParsedInput::Leave(None) => {
+                eprintln!("error: Usage: /leave <room>");
+                return;
+            }
+            ParsedInput::ListRooms => Outgoing::ListRooms,
+        };
+        let _ = self.ws_tx.send(WsCommand::Send(outgoing));
+    }
+
+    fn handle_event(&mut self, event: UiEvent) {
+        match event {
+            UiEvent::Connected => {
+                self.connected = true;
+                eprintln!("Verbonden.");
+                if !self.name.trim().is_empty() && !is_guest_name(&self.name) {
+                    let name = self.name.clone();
+                    let _ = self.ws_tx.send(WsCommand::Send(Outgoing::SetName { name }));
+                }
+            }
+            UiEvent::Reconnecting {
+                attempt,
+                max_attempts,
+                delay,
+                reason,
+            } => {
+                self.connected = false;
+                if let Some(reason) = reason {
+                    eprintln!("error: {}", reason);
+                }
+                eprintln!(
+                    "Verbinding verloren, opnieuw in {:.1}s ({}/{})",
+                    delay.as_secs_f32(),
+                    attempt,
+                    max_attempts
+                );
+            }
+            UiEvent::Incoming(Incoming::Pong { token, at }) => {
+                let roundtrip = token
+                    .as_ref()
+                    .and_then(|t| self.pending_pings.remove(t))
+                    .map(|start| start.elapsed());
+                match roundtrip {
+                    Some(rtt) => println!(
+                        "{}Pong! roundtrip: {:.2}ms",
+                        format_at_prefix(at),
+                        rtt.as_secs_f64() * 1000.0
+                    ),
+                    None => println!("{}Pong!", format_at_prefix(at)),
+                }
+            }
+            UiEvent::Incoming(Incoming::AiChunk {
+                id,
+                from,
+                prompt,
+                delta,
+                ..
+            }) => {
+                let stream = self
+                    .ai_streams
+                    .entry(id)
+                    .or_insert_with(|| (from, prompt, String::new()));
+                stream.2.push_str(&delta);
+            }
+            UiEvent::Incoming(Incoming::AiDone {
+                id,
+                response_ms,
+                tokens,
+                cost,
+                cancelled,
+                at,
+            }) => {
+                // Printed whole, so stdout stays one message per block
+                if let Some((from, prompt, response)) = self.ai_streams.remove(&id) {
+                    let ai = Incoming::Ai {
+                        from,
+                        prompt,
+                        response,
+                        response_ms,
+                        tokens,
+                        cost,
+                        thread: None,
+                        request_id: None,
+                        at,
+                    };
+                    let suffix = if cancelled { " (geannuleerd)" } else { "" };
+                    println!("{}{}", render_incoming(&ai), suffix);
+                }
+            }
+            UiEvent::Incoming(incoming) => println!("{}", render_incoming(&incoming)),
+            UiEvent::Raw(frame) => {
+                if self.raw {
+                    eprintln!("{}", frame.line);
+                }
+            }
+            UiEvent::Warning(text) => eprintln!("warning: {}", text),
+            UiEvent::Error(text) => eprintln!("error: {}", text),
+            UiEvent::Security(_) | UiEvent::Disconnected(_) => {}
+        }
+    }
+}
+
+/// Plain-text rendering of a server message, one or more lines without a trailing newline.
+fn render_incoming(incoming: &Incoming) -> String {
+    let prefix = format_at_prefix(incoming.at());
+    match incoming {
+        Incoming::Chat {
+            from,
+            text,
+            room: Some(room),
+            ..
+        } => format!("{}[#{}] {}: {}", prefix, room, from, text),
+        Incoming::Chat { from, text, .. } => format!("{}{}: {}", prefix, from, text),
+        Incoming::System { text, .. } => format!("{}* {}", prefix, text),
+        Incoming::AckName { name, .. } => format!("{}* Your name is now: {}", prefix, name),
+        Incoming::Status { .. } => {
+            let mut out = format!("{}Server status", prefix);
+            for (label, value) in status_rows(incoming) {
+                out.push_str(&format!("\n  {}: {}", label, value));
+            }
+            out
+        }
+        Incoming::ListUsers { users, .. } => {
+            let mut out = format!("{}Users ({})", prefix, users.len());
+            for user in users {
+                out.push_str(&format!("\n  {} ({}, {})", user.name, user.ip, user.id));
+            }
+            out
+        }
+        Incoming::Error { message, .. } => format!("{}error: {}", prefix, message),
+        Incoming::Pong { .. } => format!("{}Pong!", prefix),
+        Incoming::Ai {
+            from,
+            prompt,
+            response,
+            response_ms,
+            tokens,
+            cost,
+            ..
+        } => format!(
+            "{}AI voor {}: {}\n  {}\n  ({})",
+            prefix,
+            from,
+            prompt,
+            response.replace('\n', "\n  "),
+            ai_stats(*response_ms, *tokens, *cost)
+        ),
+        Incoming::Dm { from, to, text, .. } => format!("{}[DM] {} → {}: {}", prefix, from, to, text),
+        Incoming::AiChunk { from, delta, .. } => format!("{}AI voor {} … {}", prefix, from, delta),
+        Incoming::AiDone {
+            response_ms,
+            tokens,
+            cost,
+            ..
+        } => format!("{}AI klaar ({})", prefix, ai_stats(*response_ms, *tokens, *cost)),
+        Incoming::Joined { room, .. } => format!("{}* Joined #{}", prefix, room),
+        Incoming::Left { room, .. } => format!("{}* Left #{}", prefix, room),
+        Incoming::Rooms { rooms, .. } => {
+            let mut out = format!("{}Rooms ({})", prefix, rooms.len());
+            for room in rooms {
+                out.push_str(&format!("\n  #{} ({})", room.name, room.users));
+            }
+            out
+        }
+        Incoming::Unknown { payload, .. } => {
+            format!("{}[{}] {}", prefix, incoming.type_name(), payload)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_args, render_incoming};
+    use crate::protocol::Incoming;
+
+    #[test]
+    fn parses_headless_flags() {
+        let args = ["--headless", "--url", "ws://localhost:1", "--linger", "0.5", "--raw"]
+            .map(String::from);
+        let options = parse_args(&args).unwrap().unwrap();
+        assert_eq!(options.url.as_deref(), Some("ws://localhost:1"));
+        assert_eq!(options.linger.as_millis(), 500);
+        assert!(options.raw);
+        assert!(parse_args(&["--url".to_string()]).is_err());
+        assert!(parse_args(&["--help".to_string()]).unwrap().is_none());
+    }
+
+    #[test]
+    fn renders_chat_and_ai() {
+        let chat = Incoming::Chat {
+            from: "Bas".to_string(),
+            text: "Hallo".to_string(),
+            room: None,
+            at: None,
+        };
+        assert_eq!(render_incoming(&chat), "Bas: Hallo");
+
+        let ai = Incoming::Ai {
+            from: "Bas".to_string(),
+            prompt: "2+2?".to_string(),
+            response: "4".to_string(),
+            response_ms: 120,
+            tokens: Some(8),
+            cost: None,
+            thread: None,
+            request_id: None,
+            at: None,
+        };
+        assert_eq!(render_incoming(&ai), "AI voor Bas: 2+2?\n  4\n  (120ms | 8 tokens)");
+    }
+}
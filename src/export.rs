@@ -0,0 +1,807 @@
+use std::fs;
+use std::path::PathBuf;
+
+use serde_json::json;
+
+use crate::network::SecurityInfo;
+use crate::protocol::{format_at_prefix, now_unix_ms};
+use crate::recording::{split_raw_line, Direction, RecordedFrame};
+use crate::session::{ChatLine, RawLine};
+use crate::settings::config_dir;
+
+const EXPORTS_DIR: &str = "exports";
+const REDACTED_IP: &str = "x.x.x.x";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Json,
+    Text,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 4] = [
+        ExportFormat::Markdown,
+        ExportFormat::Html,
+        ExportFormat::Json,
+        ExportFormat::Text,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "Markdown",
+            ExportFormat::Html => "HTML",
+            ExportFormat::Json => "JSON",
+            ExportFormat::Text => "Tekst",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            ExportFormat::Html => "html",
+            ExportFormat::Json => "json",
+            ExportFormat::Text => "txt",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// Replace user IPs in users cards with a placeholder.
+    pub redact_ips: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::Markdown,
+            redact_ips: true,
+        }
+    }
+}
+
+pub fn exports_dir() -> PathBuf {
+    config_dir()
+        .unwrap_or_else(|| PathBuf::from(".cybox-chat-gui"))
+        .join(EXPORTS_DIR)
+}
+
+/// Timestamped file name for an export of `server_url`, e.g. `20250101-120000-chat_cybox_io_ws.md`.
+pub fn export_file_name(server_url: &str, extension: &str) -> String {
+    let host = server_url
+        .trim()
+        .trim_start_matches("wss://")
+        .trim_start_matches("ws://")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    let stamp = chrono::DateTime::from_timestamp_millis(now_unix_ms() as i64)
+        .map(|dt| dt.format("%Y%m%d-%H%M%S").to_string())
+        .unwrap_or_default();
+    format!("{}-{}.{}", stamp, host, extension)
+}
+
+/// Writes the transcript to the exports directory and returns the file path.
+pub fn export_transcript(
+    lines: &[ChatLine],
+    server_url: &str,
+    options: ExportOptions,
+) -> Result<PathBuf, String> {
+    let dir = exports_dir();
+    fs::create_dir_all(&dir)
+        .map_err(|err| format!("Failed to create exports directory: {}", err))?;
+    let path = dir.join(export_file_name(server_url, options.format.extension()));
+    fs::write(&path, render_transcript(lines, server_url, options))
+        .map_err(|err| format!("Failed to write export: {}", err))?;
+    Ok(path)
+}
+
+pub fn render_transcript(lines: &[ChatLine], server_url: &str, options: ExportOptions) -> String {
+    match options.format {
+        ExportFormat::Markdown => render_markdown(lines, server_url, options.redact_ips),
+        ExportFormat::Html => render_html(lines, server_url, options.redact_ips),
+        ExportFormat::Json => render_json(lines, server_url, options.redact_ips),
+        ExportFormat::Text => render_text(lines, server_url, options.redact_ips),
+    }
+}
+
+fn users_rows(users: &[(String, String, String)], redact_ips: bool) -> Vec<[String; 3]> {
+    users
+        .iter()
+        .map(|(name, ip, id)| {
+            let ip = if redact_ips {
+                REDACTED_IP.to_string()
+            } else {
+                ip.clone()
+            };
+            [name.clone(), ip, id.clone()]
+        })
+        .collect()
+}
+
+/// `[#room] ` for chat outside the default room, empty otherwise.
+fn room_prefix(room: &Option<String>) -> String {
+    room.as_ref().map(|room| format!("[#{}] ", room)).unwrap_or_default()
+}
+
+fn markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+fn render_markdown(lines: &[ChatLine], server_url: &str, redact_ips: bool) -> String {
+    let mut out = format!("# Chat transcript\n\nServer: `{}`\n\n", server_url);
+    for line in lines {
+        match line {
+            ChatLine::Chat {
+                from,
+                text,
+                room,
+                at,
+            } => {
+                out.push_str(&format!(
+                    "{}{}**{}**: {}\n\n",
+                    format_at_prefix(*at),
+                    room_prefix(room),
+                    from,
+                    text
+                ));
+            }
+            ChatLine::Dm { from, to, text, at, .. } => {
+                out.push_str(&format!(
+                    "{}**{} → {}** _(privé)_: {}\n\n",
+                    format_at_prefix(*at),
+                    from,
+                    to,
+                    text
+                ));
+            }
+            ChatLine::System { text, at } | ChatLine::Status { text, at } => {
+                out.push_str(&format!("_{}{}_\n\n", format_at_prefix(*at), text));
+            }
+            ChatLine::Error(text) => out.push_str(&format!("**Error:** {}\n\n", text)),
+            ChatLine::StatusCard { at, rows } => {
+                out.push_str(&format!("**{}Server status**\n\n", format_at_prefix(*at)));
+                out.push_str("| Veld | Waarde |\n|---|---|\n");
+                for (label, value) in rows {
+                    out.push_str(&format!("| {} | {} |\n", markdown_cell(label), markdown_cell(value)));
+                }
+                out.push('\n');
+            }
+            ChatLine::UsersCard { at, users } => {
+                out.push_str(&format!(
+                    "**{}Users online ({})**\n\n",
+                    format_at_prefix(*at),
+                    users.len()
+                ));
+                out.push_str("| Naam | IP | ID |\n|---|---|---|\n");
+                for [name, ip, id] in users_rows(users, redact_ips) {
+                    out.push_str(&format!(
+                        "| {} | {} | {} |\n",
+                        markdown_cell(&name),
+                        markdown_cell(&ip),
+                        markdown_cell(&id)
+                    ));
+                }
+                out.push('\n');
+            }
+            ChatLine::RoomsCard { at, rooms } => {
+                out.push_str(&format!("**{}Kanalen ({})**\n\n", format_at_prefix(*at), rooms.len()));
+                out.push_str("| Kanaal | Gebruikers |\n|---|---|\n");
+                for (name, users) in rooms {
+                    out.push_str(&format!("| #{} | {} |\n", markdown_cell(name), users));
+                }
+                out.push('\n');
+            }
+            ChatLine::Ai {
+                from,
+                prompt,
+                response,
+                stats,
+                at,
+                ..
+            } => {
+                out.push_str(&format!("### {}AI voor {}\n\n", format_at_prefix(*at), from));
+                for prompt_line in prompt.lines() {
+                    out.push_str(&format!("> {}\n", prompt_line));
+                }
+                out.push_str(&format!("\n{}\n\n_{}_\n\n", response.trim_end(), stats));
+            }
+            ChatLine::AiLive {
+                from,
+                prompt,
+                response,
+                at,
+                ..
+            } => {
+                out.push_str(&format!("### {}AI voor {}\n\n", format_at_prefix(*at), from));
+                for prompt_line in prompt.lines() {
+                    out.push_str(&format!("> {}\n", prompt_line));
+                }
+                out.push_str(&format!("\n{}\n\n_nog bezig_\n\n", response.trim_end()));
+            }
+            ChatLine::Unknown { msg_type, payload, at } => {
+                out.push_str(&format!(
+                    "**{}{}**\n\n```json\n{}\n```\n\n",
+                    format_at_prefix(*at),
+                    msg_type,
+                    serde_json::to_string_pretty(payload).unwrap_or_default()
+                ));
+            }
+        }
+    }
+    out
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_html(lines: &[ChatLine], server_url: &str, redact_ips: bool) -> String {
+    let mut body = String::new();
+    for line in lines {
+        match line {
+            ChatLine::Chat {
+                from,
+                text,
+                room,
+                at,
+            } => body.push_str(&format!(
+                "<div class=\"line chat\"><span class=\"at\">{}{}</span><b>{}</b>: {}</div>\n",
+                html_escape(&format_at_prefix(*at)),
+                html_escape(&room_prefix(room)),
+                html_escape(from),
+                html_escape(text)
+            )),
+            ChatLine::Dm { from, to, text, at, .. } => body.push_str(&format!(
+                "<div class=\"line chat dm\"><span class=\"at\">{}</span><b>{} → {}</b>: {}</div>\n",
+                html_escape(&format_at_prefix(*at)),
+                html_escape(from),
+                html_escape(to),
+                html_escape(text)
+            )),
+            ChatLine::System { text, at } | ChatLine::Status { text, at } => body.push_str(&format!(
+                "<div class=\"line system\"><span class=\"at\">{}</span>{}</div>\n",
+                html_escape(&format_at_prefix(*at)),
+                html_escape(text)
+            )),
+            ChatLine::Error(text) => body.push_str(&format!(
+                "<div class=\"line error\">{}</div>\n",
+                html_escape(text)
+            )),
+            ChatLine::StatusCard { at, rows } => {
+                body.push_str(&format!(
+                    "<div class=\"card\"><h3>{}Server status</h3><table>\n",
+                    html_escape(&format_at_prefix(*at))
+                ));
+                for (label, value) in rows {
+                    body.push_str(&format!(
+                        "<tr><th>{}</th><td>{}</td></tr>\n",
+                        html_escape(label),
+                        html_escape(value)
+                    ));
+                }
+                body.push_str("</table></div>\n");
+            }
+            ChatLine::UsersCard { at, users } => {
+                body.push_str(&format!(
+                    "<div class=\"card\"><h3>{}Users online ({})</h3><table>\n<tr><th>Naam</th><th>IP</th><th>ID</th></tr>\n",
+                    html_escape(&format_at_prefix(*at)),
+                    users.len()
+                ));
+                for row in users_rows(users, redact_ips) {
+                    body.push_str(&format!(
+                        "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                        html_escape(&row[0]),
+                        html_escape(&row[1]),
+                        html_escape(&row[2])
+                    ));
+                }
+                body.push_str("</table></div>\n");
+            }
+            ChatLine::RoomsCard { at, rooms } => {
+                body.push_str(&format!(
+                    "<div class=\"card\"><h3>{}Kanalen ({})</h3><table>\n<tr><th>Kanaal</th><th>Gebruikers</th></tr>\n",
+                    html_escape(&format_at_prefix(*at)),
+                    rooms.len()
+                ));
+                for (name, users) in rooms {
+                    body.push_str(&format!(
+                        "<tr><td>#{}</td><td>{}</td></tr>\n",
+                        html_escape(name),
+                        users
+                    ));
+                }
+                body.push_str("</table></div>\n");
+            }
+            ChatLine::Ai {
+                from,
+                prompt,
+                response,
+                stats,
+                at,
+                ..
+            } => body.push_str(&format!(
+                "<div class=\"card ai\"><h3>{}AI voor {}</h3><blockquote>{}</blockquote><pre>{}</pre><div class=\"stats\">{}</div></div>\n",
+                html_escape(&format_at_prefix(*at)),
+                html_escape(from),
+                html_escape(prompt),
+                html_escape(response),
+                html_escape(stats)
+            )),
+            ChatLine::AiLive {
+                from,
+                prompt,
+                response,
+                at,
+                ..
+            } => body.push_str(&format!(
+                "<div class=\"card ai\"><h3>{}AI voor {}</h3><blockquote>{}</blockquote><pre>{}</pre><div class=\"stats\">nog bezig</div></div>\n",
+                html_escape(&format_at_prefix(*at)),
+                html_escape(from),
+                html_escape(prompt),
+                html_escape(response)
+            )),
+            ChatLine::Unknown { msg_type, payload, at } => body.push_str(&format!(
+                "<div class=\"card\"><h3>{}{}</h3><pre>{}</pre></div>\n",
+                html_escape(&format_at_prefix(*at)),
+                html_escape(msg_type),
+                html_escape(&serde_json::to_string_pretty(payload).unwrap_or_default())
+            )),
+        }
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="nl">
+<head>
+<meta charset="utf-8">
+<title>Chat transcript - {server}</title>
+<style>
+body {{ background: #0e131b; color: #d7e3f1; font-family: sans-serif; max-width: 860px; margin: 2em auto; }}
+.line {{ margin: 6px 0; }}
+.at {{ color: #8a9bb0; }}
+.system {{ color: #9fb3c8; font-style: italic; }}
+.error {{ color: #f08888; }}
+.dm b {{ color: #c9a8f0; }}
+.card {{ background: #1c232f; border: 1px solid #3d4b60; border-radius: 8px; padding: 8px 12px; margin: 8px 0; }}
+.card h3 {{ margin: 0 0 6px; font-size: 1em; }}
+.ai {{ background: #1b2c28; border-color: #3f6e5f; }}
+.stats {{ color: #8a9bb0; font-size: 0.85em; }}
+pre {{ white-space: pre-wrap; font-family: inherit; }}
+th {{ text-align: left; padding-right: 12px; color: #a7bfd8; }}
+</style>
+</head>
+<body>
+<h1>Chat transcript</h1>
+<p>Server: <code>{server}</code></p>
+{body}</body>
+</html>
+"#,
+        server = html_escape(server_url),
+        body = body
+    )
+}
+
+fn render_json(lines: &[ChatLine], server_url: &str, redact_ips: bool) -> String {
+    let entries = lines
+        .iter()
+        .map(|line| match line {
+            ChatLine::Chat {
+                from,
+                text,
+                room,
+                at,
+            } => {
+                json!({"kind": "chat", "at": at, "room": room, "from": from, "text": text})
+            }
+            ChatLine::Dm { from, to, text, at, .. } => {
+                json!({"kind": "dm", "at": at, "from": from, "to": to, "text": text})
+            }
+            ChatLine::System { text, at } => json!({"kind": "system", "at": at, "text": text}),
+            ChatLine::Status { text, at } => json!({"kind": "status", "at": at, "text": text}),
+            ChatLine::Error(text) => json!({"kind": "error", "text": text}),
+            ChatLine::StatusCard { at, rows } => {
+                let rows = rows
+                    .iter()
+                    .map(|(label, value)| json!({"label": label, "value": value}))
+                    .collect::<Vec<_>>();
+                json!({"kind": "statusCard", "at": at, "rows": rows})
+            }
+            ChatLine::UsersCard { at, users } => {
+                let users = users_rows(users, redact_ips)
+                    .into_iter()
+                    .map(|[name, ip, id]| json!({"name": name, "ip": ip, "id": id}))
+                    .collect::<Vec<_>>();
+                json!({"kind": "usersCard", "at": at, "users": users})
+            }
+            ChatLine::RoomsCard { at, rooms } => {
+                let rooms = rooms
+                    .iter()
+                    .map(|(name, users)| json!({"name": name, "users": users}))
+                    .collect::<Vec<_>>();
+                json!({"kind": "roomsCard", "at": at, "rooms": rooms})
+            }
+            ChatLine::Ai {
+                from,
+                prompt,
+                response,
+                stats,
+                thread,
+                at,
+            } => json!({
+                "kind": "ai",
+                "at": at,
+                "from": from,
+                "prompt": prompt,
+                "response": response,
+                "stats": stats,
+                "thread": thread,
+            }),
+            ChatLine::AiLive {
+                from,
+                prompt,
+                response,
+                at,
+                ..
+            } => json!({
+                "kind": "aiLive",
+                "at": at,
+                "from": from,
+                "prompt": prompt,
+                "response": response,
+            }),
+            ChatLine::Unknown { msg_type, payload, at } => {
+                json!({"kind": "unknown", "at": at, "type": msg_type, "payload": payload})
+            }
+        })
+        .collect::<Vec<_>>();
+    let doc = json!({
+        "server": server_url,
+        "exportedAt": now_unix_ms(),
+        "lines": entries,
+    });
+    serde_json::to_string_pretty(&doc).unwrap_or_default()
+}
+
+fn render_text(lines: &[ChatLine], server_url: &str, redact_ips: bool) -> String {
+    let mut out = format!("Chat transcript - {}\n\n", server_url);
+    for line in lines {
+        match line {
+            ChatLine::Chat {
+                from,
+                text,
+                room,
+                at,
+            } => {
+                out.push_str(&format!(
+                    "{}{}{}: {}\n",
+                    format_at_prefix(*at),
+                    room_prefix(room),
+                    from,
+                    text
+                ));
+            }
+            ChatLine::Dm { from, to, text, at, .. } => {
+                out.push_str(&format!("{}[DM] {} → {}: {}\n", format_at_prefix(*at), from, to, text));
+            }
+            ChatLine::System { text, at } | ChatLine::Status { text, at } => {
+                out.push_str(&format!("{}* {}\n", format_at_prefix(*at), text));
+            }
+            ChatLine::Error(text) => out.push_str(&format!("error: {}\n", text)),
+            ChatLine::StatusCard { at, rows } => {
+                out.push_str(&format!("{}Server status\n", format_at_prefix(*at)));
+                for (label, value) in rows {
+                    out.push_str(&format!("  {}: {}\n", label, value));
+                }
+            }
+            ChatLine::UsersCard { at, users } => {
+                out.push_str(&format!("{}Users online ({})\n", format_at_prefix(*at), users.len()));
+                for [name, ip, id] in users_rows(users, redact_ips) {
+                    out.push_str(&format!("  {} ({}, {})\n", name, ip, id));
+                }
+            }
+            ChatLine::RoomsCard { at, rooms } => {
+                out.push_str(&format!("{}Kanalen ({})\n", format_at_prefix(*at), rooms.len()));
+                for (name, users) in rooms {
+                    out.push_str(&format!("  #{} ({})\n", name, users));
+                }
+            }
+            ChatLine::Ai {
+                from,
+                prompt,
+                response,
+                stats,
+                at,
+                ..
+            } => {
+                out.push_str(&format!("{}AI voor {}: {}\n", format_at_prefix(*at), from, prompt));
+                for response_line in response.lines() {
+                    out.push_str(&format!("  {}\n", response_line));
+                }
+                out.push_str(&format!("  ({})\n", stats));
+            }
+            ChatLine::AiLive {
+                from,
+                prompt,
+                response,
+                at,
+                ..
+            } => {
+                out.push_str(&format!("{}AI voor {}: {}\n", format_at_prefix(*at), from, prompt));
+                for response_line in response.lines() {
+                    out.push_str(&format!("  {}\n", response_line));
+                }
+                out.push_str("  (nog bezig)\n");
+            }
+            ChatLine::Unknown { msg_type, payload, at } => {
+                out.push_str(&format!("{}[{}] {}\n", format_at_prefix(*at), msg_type, payload));
+            }
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureFormat {
+    Jsonl,
+    Har,
+}
+
+impl CaptureFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            CaptureFormat::Jsonl => "jsonl",
+            CaptureFormat::Har => "har",
+        }
+    }
+}
+
+/// A raw frame in the shape both capture formats need, from either the raw
+/// pane or a recording file.
+#[derive(Debug, Clone)]
+pub struct CaptureFrame {
+    pub direction: Direction,
+    pub wall_ms: u64,
+    /// Monotonic offset from the first frame in the capture.
+    pub mono_ms: u64,
+    pub payload: String,
+}
+
+pub fn capture_from_raw_lines<'a>(lines: impl IntoIterator<Item = &'a RawLine>) -> Vec<CaptureFrame> {
+    let mut base = None;
+    lines
+        .into_iter()
+        .filter_map(|raw| {
+            let (direction, payload) = split_raw_line(&raw.line)?;
+            let base = *base.get_or_insert(raw.mono);
+            Some(CaptureFrame {
+                direction,
+                wall_ms: raw.wall_ms,
+                mono_ms: raw.mono.saturating_duration_since(base).as_millis() as u64,
+                payload: payload.to_string(),
+            })
+        })
+        .collect()
+}
+
+pub fn capture_from_recording(frames: &[RecordedFrame]) -> Vec<CaptureFrame> {
+    let base = frames.first().map(|f| f.elapsed_ms).unwrap_or(0);
+    frames
+        .iter()
+        .map(|frame| CaptureFrame {
+            direction: frame.direction,
+            wall_ms: frame.timestamp,
+            mono_ms: frame.elapsed_ms.saturating_sub(base),
+            payload: frame.payload.clone(),
+        })
+        .collect()
+}
+
+fn iso_time(unix_ms: u64) -> String {
+    chrono::DateTime::from_timestamp_millis(unix_ms as i64)
+        .map(|dt| dt.to_rfc3339_opts(chrono::SecondsFormat::Millis, true))
+        .unwrap_or_default()
+}
+
+pub fn render_capture_jsonl(frames: &[CaptureFrame]) -> String {
+    let mut out = String::new();
+    for frame in frames {
+        let direction = match frame.direction {
+            Direction::In => "in",
+            Direction::Out => "out",
+        };
+        let line = json!({
+            "direction": direction,
+            "wallMs": frame.wall_ms,
+            "wallTime": iso_time(frame.wall_ms),
+            "monoMs": frame.mono_ms,
+            "size": frame.payload.len(),
+            "payload": frame.payload,
+        });
+        out.push_str(&line.to_string());
+        out.push('\n');
+    }
+    out
+}
+
+/// HAR 1.2 with one WebSocket entry in the `_webSocketMessages` layout that
+/// Chrome devtools writes and reads back.
+pub fn render_capture_har(
+    frames: &[CaptureFrame],
+    server_url: &str,
+    security: Option<&SecurityInfo>,
+) -> String {
+    let started_ms = frames.first().map(|f| f.wall_ms).unwrap_or_else(now_unix_ms);
+    let url = security.map(|s| s.url.as_str()).unwrap_or(server_url);
+    let headers = security
+        .map(|s| {
+            s.headers
+                .iter()
+                .map(|(name, value)| json!({"name": name, "value": value}))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let messages = frames
+        .iter()
+        .map(|frame| {
+            let kind = match frame.direction {
+                Direction::In => "receive",
+                Direction::Out => "send",
+            };
+            json!({
+                "type": kind,
+                "time": frame.wall_ms as f64 / 1000.0,
+                "opcode": 1,
+                "data": frame.payload,
+            })
+        })
+        .collect::<Vec<_>>();
+    let doc = json!({
+        "log": {
+            "version": "1.2",
+            "creator": {"name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION")},
+            "pages": [],
+            "entries": [{
+                "startedDateTime": iso_time(started_ms),
+                "time": frames.last().map(|f| f.mono_ms).unwrap_or(0),
+                "request": {
+                    "method": "GET",
+                    "url": url,
+                    "httpVersion": "HTTP/1.1",
+                    "headers": [],
+                    "queryString": [],
+                    "cookies": [],
+                    "headersSize": -1,
+                    "bodySize": 0,
+                },
+                "response": {
+                    "status": security.and_then(|s| s.http_status).unwrap_or(101),
+                    "statusText": "Switching Protocols",
+                    "httpVersion": "HTTP/1.1",
+                    "headers": headers,
+                    "cookies": [],
+                    "content": {"size": 0, "mimeType": "x-unknown"},
+                    "redirectURL": "",
+                    "headersSize": -1,
+                    "bodySize": 0,
+                },
+                "cache": {},
+                "timings": {"send": 0, "wait": 0, "receive": 0},
+                "_resourceType": "websocket",
+                "_webSocketMessages": messages,
+            }],
+        }
+    });
+    serde_json::to_string_pretty(&doc).unwrap_or_default()
+}
+
+pub fn export_capture(
+    frames: &[CaptureFrame],
+    format: CaptureFormat,
+    server_url: &str,
+    security: Option<&SecurityInfo>,
+) -> Result<PathBuf, String> {
+    let dir = exports_dir();
+    fs::create_dir_all(&dir)
+        .map_err(|err| format!("Failed to create exports directory: {}", err))?;
+    let path = dir.join(export_file_name(server_url, format.extension()));
+    let contents = match format {
+        CaptureFormat::Jsonl => render_capture_jsonl(frames),
+        CaptureFormat::Har => render_capture_har(frames, server_url, security),
+    };
+    fs::write(&path, contents).map_err(|err| format!("Failed to write export: {}", err))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{
+        capture_from_raw_lines, render_capture_har, render_capture_jsonl, render_transcript,
+        ExportFormat, ExportOptions,
+    };
+    use crate::network::SecurityInfo;
+    use crate::session::{ChatLine, RawLine};
+
+    fn sample() -> Vec<ChatLine> {
+        vec![
+            ChatLine::Chat {
+                from: "Bas".to_string(),
+                text: "<b>hoi</b>".to_string(),
+                room: None,
+                at: None,
+            },
+            ChatLine::UsersCard {
+                at: None,
+                users: vec![("Bas".to_string(), "10.0.0.7".to_string(), "u1".to_string())],
+            },
+        ]
+    }
+
+    #[test]
+    fn markdown_tables_redact_ips() {
+        let options = ExportOptions {
+            format: ExportFormat::Markdown,
+            redact_ips: true,
+        };
+        let out = render_transcript(&sample(), "ws://localhost", options);
+        assert!(out.contains("| Bas | x.x.x.x | u1 |"));
+        assert!(!out.contains("10.0.0.7"));
+    }
+
+    #[test]
+    fn html_escapes_message_text() {
+        let options = ExportOptions {
+            format: ExportFormat::Html,
+            redact_ips: false,
+        };
+        let out = render_transcript(&sample(), "ws://localhost", options);
+        assert!(out.contains("&lt;b&gt;hoi&lt;/b&gt;"));
+        assert!(out.contains("10.0.0.7"));
+    }
+
+    #[test]
+    fn capture_formats_carry_timing_size_and_headers() {
+        let start = Instant::now();
+        let raw = [(">> {\"type\":\"status\"}", 0), ("<< {\"type\":\"pong\"}", 250)]
+            .map(|(line, offset)| RawLine {
+                line: line.to_string(),
+                payload: line[3..].to_string(),
+                wall_ms: 1_700_000_000_000 + offset,
+                mono: start + Duration::from_millis(offset),
+                hand_crafted: false,
+                frame_type: None,
+                auto_ping: false,
+                schema_issues: Vec::new(),
+            });
+        let frames = capture_from_raw_lines(&raw);
+
+        let jsonl = render_capture_jsonl(&frames);
+        let second: serde_json::Value = serde_json::from_str(jsonl.lines().nth(1).unwrap()).unwrap();
+        assert_eq!(second["direction"], "in");
+        assert_eq!(second["monoMs"], 250);
+        assert_eq!(second["size"], 15);
+
+        let security = SecurityInfo {
+            url: "wss://chat.example/ws".to_string(),
+            transport: "wss".to_string(),
+            tls: true,
+            http_status: Some(101),
+            headers: vec![("upgrade".to_string(), "websocket".to_string())],
+        };
+        let har: serde_json::Value =
+            serde_json::from_str(&render_capture_har(&frames, "ignored", Some(&security))).unwrap();
+        let entry = &har["log"]["entries"][0];
+        assert_eq!(entry["request"]["url"], "wss://chat.example/ws");
+        assert_eq!(entry["response"]["headers"][0]["value"], "websocket");
+        assert_eq!(entry["_webSocketMessages"][0]["type"], "send");
+        assert_eq!(entry["_webSocketMessages"][1]["time"], 1_700_000_000.25);
+    }
+}
@@ -0,0 +1,343 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::now_unix_ms;
+use crate::settings::config_dir;
+
+const HISTORY_DIR: &str = "history";
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+static NEXT_STORE_ID: AtomicU64 = AtomicU64::new(0);
+/// Open stores by file, oldest first. Only the oldest store of a file writes
+/// to it, and retention never rewrites a file another store has offsets into.
+static OPEN_STORES: Mutex<Vec<(PathBuf, u64)>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy)]
+pub struct HistoryPolicy {
+    pub enabled: bool,
+    /// Entries older than this are dropped when the store is opened. 0 = keep forever.
+    pub max_days: u32,
+    /// Oldest entries are dropped until the file fits. 0 = no size limit.
+    pub max_mb: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HistoryEntry<T> {
+    saved_at: u64,
+    line: T,
+}
+
+#[derive(Deserialize)]
+struct EntryTimestamp {
+    saved_at: u64,
+}
+
+/// Append-only JSONL chat log for a single server URL. Older pages are read
+/// on demand so the UI only parses what the user scrolls back to.
+pub struct HistoryStore {
+    path: PathBuf,
+    id: u64,
+    // Byte offsets of lines that have not been handed to the UI yet, oldest first.
+    unloaded: Vec<u64>,
+    // Start of the oldest line the UI already has; pages are read up to here.
+    loaded_from: u64,
+}
+
+fn history_dir() -> PathBuf {
+    let dir = config_dir().unwrap_or_else(|| PathBuf::from(".cybox-chat-gui"));
+    dir.join(HISTORY_DIR)
+}
+
+/// File name for `key`: letters, digits and `-` stay, every other byte
+/// becomes `_XX` (hex). Reversible, so two keys never share a file.
+fn history_file_name(key: &str) -> String {
+    let mut name = String::new();
+    for byte in key.trim().bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' {
+            name.push(byte as char);
+        } else {
+            name.push_str(&format!("_{:02X}", byte));
+        }
+    }
+    name
+}
+
+fn history_path(key: &str) -> PathBuf {
+    history_dir().join(format!("{}.jsonl", history_file_name(key)))
+}
+
+/// Where the log for `server_url` was kept before identities; that naming
+/// folded every other character into `_`.
+fn legacy_history_path(server_url: &str) -> PathBuf {
+    let file_name = server_url
+        .trim()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    history_dir().join(format!("{}.jsonl", file_name))
+}
+
+impl HistoryStore {
+    /// Opens the log for `identity` (see `session::identity_key`). A log
+    /// from before identities, kept per `legacy_url`, is taken over once.
+    pub fn open(identity: &str, legacy_url: &str, policy: &HistoryPolicy) -> Result<Self, String> {
+        let path = history_path(identity);
+        let legacy = legacy_history_path(legacy_url);
+        if !path.exists() && legacy.exists() && !is_open(&legacy) {
+            fs::rename(&legacy, &path)
+                .map_err(|err| format!("Failed to move old history: {}", err))?;
+        }
+        Self::open_at(path, policy)
+    }
+
+    fn open_at(path: PathBuf, policy: &HistoryPolicy) -> Result<Self, String> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|err| format!("Failed to create history directory: {}", err))?;
+        }
+        let mut stores = OPEN_STORES.lock().unwrap_or_else(|err| err.into_inner());
+        if path.exists() && !stores.iter().any(|(open, _)| *open == path) {
+            apply_retention(&path, policy)?;
+        }
+        let (unloaded, loaded_from) = scan_line_offsets(&path)?;
+        let id = NEXT_STORE_ID.fetch_add(1, Ordering::Relaxed);
+        stores.push((path.clone(), id));
+        Ok(Self {
+            path,
+            id,
+            unloaded,
+            loaded_from,
+        })
+    }
+
+    /// Whether this store writes: with the same identity open in several
+    /// tabs, each line would otherwise be appended once per tab.
+    fn is_writer(&self) -> bool {
+        let stores = OPEN_STORES.lock().unwrap_or_else(|err| err.into_inner());
+        stores.iter().find(|(open, _)| *open == self.path).map(|(_, id)| *id) == Some(self.id)
+    }
+
+    pub fn has_older(&self) -> bool {
+        !self.unloaded.is_empty()
+    }
+
+    /// Returns up to `limit` entries that precede everything loaded so far,
+    /// in chronological order. Lines that no longer parse are skipped.
+    pub fn load_older<T: DeserializeOwned>(&mut self, limit: usize) -> Result<Vec<T>, String> {
+        let start_idx = self.unloaded.len().saturating_sub(limit);
+        let Some(&start) = self.unloaded.get(start_idx) else {
+            return Ok(Vec::new());
+        };
+        let mut file =
+            File::open(&self.path).map_err(|err| format!("Failed to open history: {}", err))?;
+        file.seek(SeekFrom::Start(start))
+            .map_err(|err| format!("Failed to read history: {}", err))?;
+        let mut chunk = vec![0u8; (self.loaded_from - start) as usize];
+        file.read_exact(&mut chunk)
+            .map_err(|err| format!("Failed to read history: {}", err))?;
+        self.unloaded.truncate(start_idx);
+        self.loaded_from = start;
+
+        Ok(String::from_utf8_lossy(&chunk)
+            .lines()
+            .filter_map(|line| serde_json::from_str::<HistoryEntry<T>>(line).ok())
+            .map(|entry| entry.line)
+            .collect())
+    }
+
+    pub fn append<T: Serialize>(&mut self, line: &T) -> Result<(), String> {
+        if !self.is_writer() {
+            return Ok(());
+        }
+        let entry = HistoryEntry {
+            saved_at: now_unix_ms(),
+            line,
+        };
+        let json = serde_json::to_string(&entry)
+            .map_err(|err| format!("Failed to serialize history entry: {}", err))?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|err| format!("Failed to open history: {}", err))?;
+        writeln!(file, "{}", json).map_err(|err| format!("Failed to write history: {}", err))
+    }
+}
+
+impl Drop for HistoryStore {
+    fn drop(&mut self) {
+        let mut stores = OPEN_STORES.lock().unwrap_or_else(|err| err.into_inner());
+        stores.retain(|(_, id)| *id != self.id);
+    }
+}
+
+fn is_open(path: &Path) -> bool {
+    let stores = OPEN_STORES.lock().unwrap_or_else(|err| err.into_inner());
+    stores.iter().any(|(open, _)| open == path)
+}
+
+/// Returns the start offset of every non-empty line plus the total file length.
+fn scan_line_offsets(path: &Path) -> Result<(Vec<u64>, u64), String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return Ok((Vec::new(), 0)),
+    };
+    let mut reader = BufReader::new(file);
+    let mut offsets = Vec::new();
+    let mut pos = 0u64;
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        let read = reader
+            .read_until(b'\n', &mut buf)
+            .map_err(|err| format!("Failed to read history: {}", err))?;
+        if read == 0 {
+            break;
+        }
+        if buf.iter().any(|b| !b.is_ascii_whitespace()) {
+            offsets.push(pos);
+        }
+        pos += read as u64;
+    }
+    Ok((offsets, pos))
+}
+
+/// Drops entries past `policy`. Lines that do not parse are not judged but
+/// moved to `<file>.bad`, so retention never loses them.
+fn apply_retention(path: &Path, policy: &HistoryPolicy) -> Result<(), String> {
+    let raw =
+        fs::read_to_string(path).map_err(|err| format!("Failed to read history: {}", err))?;
+    let min_saved_at = if policy.max_days > 0 {
+        now_unix_ms().saturating_sub(u64::from(policy.max_days) * DAY_MS)
+    } else {
+        0
+    };
+
+    let mut entries = Vec::new();
+    let mut unreadable = Vec::new();
+    for line in raw.lines().filter(|line| !line.trim().is_empty()) {
+        match serde_json::from_str::<EntryTimestamp>(line) {
+            Ok(entry) => entries.push((entry.saved_at, line)),
+            Err(_) => unreadable.push(line),
+        }
+    }
+    if !unreadable.is_empty() {
+        let mut side = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path.with_extension("jsonl.bad"))
+            .map_err(|err| format!("Failed to keep unreadable history: {}", err))?;
+        for line in &unreadable {
+            writeln!(side, "{}", line)
+                .map_err(|err| format!("Failed to keep unreadable history: {}", err))?;
+        }
+    }
+    let mut kept = entries
+        .into_iter()
+        .filter(|(saved_at, _)| *saved_at >= min_saved_at)
+        .map(|(_, line)| line)
+        .collect::<Vec<_>>();
+
+    if policy.max_mb > 0 {
+        let max_bytes = u64::from(policy.max_mb) * 1024 * 1024;
+        let mut total = kept.iter().map(|l| l.len() as u64 + 1).sum::<u64>();
+        let mut drop = 0;
+        while total > max_bytes && drop < kept.len() {
+            total -= kept[drop].len() as u64 + 1;
+            drop += 1;
+        }
+        kept.drain(..drop);
+    }
+
+    if unreadable.is_empty() && kept.len() == raw.lines().count() {
+        return Ok(());
+    }
+    let mut compacted = kept.join("\n");
+    if !compacted.is_empty() {
+        compacted.push('\n');
+    }
+    let tmp_path = path.with_extension("jsonl.tmp");
+    fs::write(&tmp_path, compacted).map_err(|err| format!("Failed to compact history: {}", err))?;
+    fs::rename(&tmp_path, path).map_err(|err| format!("Failed to compact history: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{apply_retention, history_file_name, HistoryPolicy, HistoryStore, DAY_MS};
+    use crate::protocol::now_unix_ms;
+
+    #[test]
+    fn retention_drops_expired_and_oversized_entries() {
+        let path = std::env::temp_dir().join(format!("history-{}.jsonl", uuid::Uuid::new_v4()));
+        let now = now_unix_ms();
+        let old = format!(r#"{{"saved_at":{},"line":"old"}}"#, now - 10 * DAY_MS);
+        let recent = format!(r#"{{"saved_at":{},"line":"recent"}}"#, now);
+        fs::write(&path, format!("{}\n{{kapot\n{}\n", old, recent)).unwrap();
+
+        let policy = HistoryPolicy {
+            enabled: true,
+            max_days: 7,
+            max_mb: 0,
+        };
+        apply_retention(&path, &policy).unwrap();
+        let compacted = fs::read_to_string(&path).unwrap();
+        let bad_path = path.with_extension("jsonl.bad");
+        let unreadable = fs::read_to_string(&bad_path).unwrap();
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(&bad_path);
+
+        assert_eq!(compacted, format!("{}\n", recent));
+        assert_eq!(unreadable, "{kapot\n");
+    }
+
+    #[test]
+    fn shared_file_is_not_compacted_or_written_twice() {
+        let path = std::env::temp_dir().join(format!("history-{}.jsonl", uuid::Uuid::new_v4()));
+        let now = now_unix_ms();
+        let lines = (0..3)
+            .map(|n| format!(r#"{{"saved_at":{},"line":"regel {}"}}"#, now - (3 - n) * DAY_MS, n))
+            .collect::<Vec<_>>();
+        fs::write(&path, format!("{}\n", lines.join("\n"))).unwrap();
+        let keep_all = HistoryPolicy {
+            enabled: true,
+            max_days: 0,
+            max_mb: 0,
+        };
+        let keep_one_day = HistoryPolicy {
+            max_days: 1,
+            ..keep_all
+        };
+
+        let mut first = HistoryStore::open_at(path.clone(), &keep_all).unwrap();
+        let mut second = HistoryStore::open_at(path.clone(), &keep_one_day).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 3);
+        first.append(&"nieuw".to_string()).unwrap();
+        second.append(&"nieuw".to_string()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 4);
+        assert_eq!(
+            first.load_older::<String>(10).unwrap(),
+            vec!["regel 0", "regel 1", "regel 2"]
+        );
+
+        drop(first);
+        second.append(&"daarna".to_string()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 5);
+        drop(second);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn file_names_keep_keys_apart() {
+        assert_ne!(history_file_name("ws://a-b:1"), history_file_name("ws://a.b:1"));
+        assert_ne!(history_file_name("ws://a_b:1"), history_file_name("ws://a.b:1"));
+        assert_eq!(history_file_name("id-1 ws://a.b:1"), "id-1_20ws_3A_2F_2Fa_2Eb_3A1");
+    }
+}
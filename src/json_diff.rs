@@ -0,0 +1,145 @@
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// One leaf-level difference between two JSON values. `path` uses
+/// `a.b[2].c` notation; the root itself is `$`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffEntry {
+    pub path: String,
+    pub kind: DiffKind,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+impl DiffEntry {
+    /// `new - old` when both sides are numbers, e.g. for `memoryMb` in two
+    /// status frames.
+    pub fn numeric_delta(&self) -> Option<f64> {
+        let old = self.old.as_ref()?.as_f64()?;
+        let new = self.new.as_ref()?.as_f64()?;
+        Some(new - old)
+    }
+}
+
+/// Structural diff from `old` to `new`. Objects are compared per key and
+/// arrays per index; everything else is compared as a whole.
+pub fn diff_values(old: &Value, new: &Value) -> Vec<DiffEntry> {
+    let mut entries = Vec::new();
+    diff_into(&mut entries, "$", old, new);
+    entries
+}
+
+fn child_path(parent: &str, key: &str) -> String {
+    if parent == "$" {
+        key.to_string()
+    } else {
+        format!("{}.{}", parent, key)
+    }
+}
+
+fn index_path(parent: &str, idx: usize) -> String {
+    format!("{}[{}]", parent, idx)
+}
+
+fn diff_into(entries: &mut Vec<DiffEntry>, path: &str, old: &Value, new: &Value) {
+    match (old, new) {
+        (Value::Object(old_map), Value::Object(new_map)) => {
+            for (key, old_value) in old_map {
+                let child = child_path(path, key);
+                match new_map.get(key) {
+                    Some(new_value) => diff_into(entries, &child, old_value, new_value),
+                    None => entries.push(DiffEntry {
+                        path: child,
+                        kind: DiffKind::Removed,
+                        old: Some(old_value.clone()),
+                        new: None,
+                    }),
+                }
+            }
+            for (key, new_value) in new_map {
+                if !old_map.contains_key(key) {
+                    entries.push(DiffEntry {
+                        path: child_path(path, key),
+                        kind: DiffKind::Added,
+                        old: None,
+                        new: Some(new_value.clone()),
+                    });
+                }
+            }
+        }
+        (Value::Array(old_arr), Value::Array(new_arr)) => {
+            for idx in 0..old_arr.len().max(new_arr.len()) {
+                let child = index_path(path, idx);
+                match (old_arr.get(idx), new_arr.get(idx)) {
+                    (Some(o), Some(n)) => diff_into(entries, &child, o, n),
+                    (Some(o), None) => entries.push(DiffEntry {
+                        path: child,
+                        kind: DiffKind::Removed,
+                        old: Some(o.clone()),
+                        new: None,
+                    }),
+                    (None, Some(n)) => entries.push(DiffEntry {
+                        path: child,
+                        kind: DiffKind::Added,
+                        old: None,
+                        new: Some(n.clone()),
+                    }),
+                    (None, None) => {}
+                }
+            }
+        }
+        _ if old != new => entries.push(DiffEntry {
+            path: path.to_string(),
+            kind: DiffKind::Changed,
+            old: Some(old.clone()),
+            new: Some(new.clone()),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{diff_values, DiffKind};
+
+    #[test]
+    fn status_frames_diff_per_field() {
+        let old = json!({"type":"status","messagesSent":10,"memoryMb":41.5,"userCount":3,"version":"1.2.0"});
+        let new = json!({"type":"status","messagesSent":14,"memoryMb":40.0,"userCount":3,"rooms":2});
+        let diff = diff_values(&old, &new);
+        let summary = diff
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry.kind, entry.numeric_delta()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("memoryMb", DiffKind::Changed, Some(-1.5)),
+                ("messagesSent", DiffKind::Changed, Some(4.0)),
+                ("version", DiffKind::Removed, None),
+                ("rooms", DiffKind::Added, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_arrays_use_indexed_paths() {
+        let old = json!({"users":[{"name":"Bas"},{"name":"Eva"}]});
+        let new = json!({"users":[{"name":"Bas"},{"name":"Eef"},{"name":"Kim"}]});
+        let diff = diff_values(&old, &new);
+        assert_eq!(diff.len(), 2);
+        assert_eq!(diff[0].path, "users[1].name");
+        assert_eq!(diff[0].kind, DiffKind::Changed);
+        assert_eq!(diff[1].path, "users[2]");
+        assert_eq!(diff[1].kind, DiffKind::Added);
+        assert!(diff_values(&old, &old).is_empty());
+    }
+}
@@ -1,454 +1,429 @@
-use std::collections::{HashMap, VecDeque};
-use std::sync::mpsc::{channel, Receiver};
+use std::collections::{BTreeMap, HashSet};
 use std::time::{Duration, Instant};
 
 use eframe::egui;
-use tokio::sync::mpsc::UnboundedSender;
 
+mod ai_usage;
+mod capabilities;
+mod cli;
+mod export;
+mod history;
+mod json_diff;
+mod markdown;
+mod mock_server;
 mod network;
+mod outbox;
 mod protocol;
+mod recording;
+mod schema;
+mod search;
+mod session;
 mod settings;
 
-use network::{start_connection, SecurityInfo, UiEvent, WsCommand};
-use protocol::{format_at_prefix, format_uptime, parse_user_input, Incoming, Outgoing, ParsedInput};
-use settings::{load_settings, save_settings, AppSettings};
+use ai_usage::{day_key, limit_warning, percentile, AiUsageLog, UsageTotals};
+use export::{CaptureFormat, ExportFormat, ExportOptions};
+use history::HistoryPolicy;
+use json_diff::{diff_values, DiffKind};
+use markdown::{highlight_code, parse_markdown, Block, Span, TokenKind};
+use network::ReconnectPolicy;
+use outbox::{describe_outgoing, Outbox};
+use protocol::{
+    format_at_prefix, now_unix_ms, parse_user_input, Outgoing, ParsedInput, DEFAULT_ROOM,
+};
+use schema::SchemaIssue;
+use search::{frame_type_counts, FrameFilter, LineKind, Matcher, SearchState};
+use session::{ChatLine, Conversation, OutboxAction, RawLine, Session};
+use settings::{load_settings, save_settings, AppSettings, ServerProfile, UnknownTypeAction};
 
-const AUTO_PING_INTERVAL_SECS: u64 = 5;
-const MAX_LATENCY_SAMPLES: usize = 100;
-const AUTO_PING_PREFIX: &str = "auto-";
-const MAX_RAW_MESSAGES: usize = 500;
+enum TabAction {
+    Select(usize),
+    Close(usize),
+    Open(usize),
+}
+
+enum ProfileAction {
+    Add,
+    Remove(usize),
+}
 
-#[derive(Clone)]
-struct RawLine {
-    line: String,
-    payload: String,
+/// Clicks inside a chat line, applied once the chat pane is drawn.
+enum ChatLineAction {
+    SetUnknownType(String, UnknownTypeAction),
+    OpenDm(String),
+    JoinRoom(String),
+    LeaveRoom(String),
+    CancelAi(String),
+    ReplyAi(String),
 }
 
-#[derive(Default, Clone)]
-struct Metrics {
-    ws_in_frames: u64,
-    ws_out_frames: u64,
-    reconnects: u64,
-    connect_count: u64,
-    last_connected_at: Option<Instant>,
-    error_timestamps: VecDeque<Instant>,
+/// Turns `text` into a layout job with every `matcher` hit given a highlight
+/// background, keeping the styling of the original `RichText`.
+fn highlight_text(ui: &egui::Ui, text: egui::RichText, matcher: Option<&Matcher>) -> egui::WidgetText {
+    let Some(matcher) = matcher else {
+        return text.into();
+    };
+    let ranges = matcher.find_ranges(text.text()).collect::<Vec<_>>();
+    if ranges.is_empty() {
+        return text.into();
+    }
+    let base = egui::WidgetText::from(text).into_layout_job(
+        ui.style(),
+        egui::FontSelection::Default,
+        egui::Align::Center,
+    );
+    let Some(section) = base.sections.first() else {
+        return base.into();
+    };
+    let mut format = section.format.clone();
+    let mut hit_format = format.clone();
+    hit_format.background = egui::Color32::from_rgb(140, 112, 28);
+    hit_format.color = egui::Color32::WHITE;
+    format.background = egui::Color32::TRANSPARENT;
+
+    let mut job = egui::text::LayoutJob {
+        wrap: base.wrap.clone(),
+        halign: base.halign,
+        justify: base.justify,
+        ..Default::default()
+    };
+    let mut pos = 0;
+    for range in ranges {
+        if range.start > pos {
+            job.append(&base.text[pos..range.start], 0.0, format.clone());
+        }
+        job.append(&base.text[range.clone()], 0.0, hit_format.clone());
+        pos = range.end;
+    }
+    if pos < base.text.len() {
+        job.append(&base.text[pos..], 0.0, format);
+    }
+    job.into()
 }
 
-fn is_guest_name(name: &str) -> bool {
-    name.trim().to_ascii_lowercase().starts_with("guest-")
+/// Parsed Markdown per (text, safe mode), so long AI answers are not
+/// re-parsed every frame.
+#[derive(Default)]
+struct MarkdownParser;
+
+impl egui::cache::ComputerMut<(&str, bool), Vec<Block>> for MarkdownParser {
+    fn compute(&mut self, (text, safe): (&str, bool)) -> Vec<Block> {
+        parse_markdown(text, safe)
+    }
 }
 
-#[derive(Clone)]
-enum ChatLine {
-    Chat {
-        from: String,
-        text: String,
-        at: Option<u64>,
-    },
-    System {
-        text: String,
-        at: Option<u64>,
-    },
-    Error(String),
-    Status {
-        text: String,
-        at: Option<u64>,
-    },
-    StatusCard {
-        at: Option<u64>,
-        rows: Vec<(String, String)>,
-    },
-    UsersCard {
-        at: Option<u64>,
-        users: Vec<(String, String, String)>,
-    },
-    Ai {
-        from: String,
-        prompt: String,
-        response: String,
-        stats: String,
-        at: Option<u64>,
-    },
+type MarkdownCache = egui::cache::FrameCache<Vec<Block>, MarkdownParser>;
+
+fn token_color(kind: TokenKind) -> egui::Color32 {
+    match kind {
+        TokenKind::Plain => egui::Color32::from_rgb(214, 222, 235),
+        TokenKind::Keyword => egui::Color32::from_rgb(198, 146, 233),
+        TokenKind::Literal => egui::Color32::from_rgb(163, 213, 139),
+        TokenKind::Comment => egui::Color32::from_gray(120),
+        TokenKind::Number => egui::Color32::from_rgb(240, 178, 110),
+    }
 }
 
 struct ChatApp {
-    server_url: String,
+    profiles: Vec<ServerProfile>,
+    profile_editor_open: bool,
+    startup_auto_connect: bool,
+    // One tab per connection; `active_session` is the one in front
+    sessions: Vec<Session>,
+    active_session: usize,
     input: String,
-    messages: Vec<ChatLine>,
-    raw_messages: VecDeque<RawLine>,
-    selected_raw_index: Option<usize>,
-    connected: bool,
-    preferred_username: String,
-    username: String,
-
-    // Channel to send messages to WebSocket
-    ws_tx: Option<UnboundedSender<WsCommand>>,
-    // Channel to receive events from WebSocket thread
-    ui_rx: Option<Receiver<UiEvent>>,
-    // Pending ping requests for roundtrip calculation
-    pending_pings: HashMap<String, Instant>,
-    latency_samples: VecDeque<f32>,
-    last_auto_ping_sent: Option<Instant>,
-    security_info: Option<SecurityInfo>,
-    metrics: Metrics,
+    auto_reconnect: bool,
+    reconnect_max_attempts: u32,
+    history_policy: HistoryPolicy,
+    unknown_types: BTreeMap<String, UnknownTypeAction>,
+    // Messages typed while offline, flushed on the next Connected
+    outbox: Outbox,
+    search: SearchState,
+    frame_filter: FrameFilter,
+    export_dialog_open: bool,
+    export_options: ExportOptions,
+    // Text of the raw frame composer in the Raw WebSocket pane
+    raw_composer: String,
+    // Room name typed in the sidebar's join field
+    room_input: String,
+    // Links and headings from other users render as plain text
+    markdown_safe_mode: bool,
+    // Own AI answers of all tabs, behind the day and profile totals
+    ai_usage: AiUsageLog,
+    ai_daily_limit: Option<f64>,
+    // Prompt that was already warned about the daily limit; sending it again goes through
+    ai_limit_confirmed: Option<String>,
+    replay_dialog_open: bool,
+    replay_path: String,
+    // Playback multiplier; 0 = as fast as possible
+    replay_speed: f32,
     theme_initialized: bool,
 }
 
 impl Default for ChatApp {
     fn default() -> Self {
         let settings = load_settings();
-        let preferred_username = if is_guest_name(&settings.username) {
-            String::new()
-        } else {
-            settings.username.clone()
+        let active_profile = settings
+            .profiles
+            .iter()
+            .position(|p| p.name == settings.active_profile)
+            .unwrap_or(0);
+        let history_policy = HistoryPolicy {
+            enabled: settings.history_enabled,
+            max_days: settings.history_max_days,
+            max_mb: settings.history_max_mb,
         };
+        let mut session = Session::new(active_profile, &settings.profiles[active_profile]);
+        session.open_history(&history_policy);
+        let (outbox, outbox_error) = Outbox::load();
+        if let Some(err) = outbox_error {
+            session.push_line(ChatLine::Error(err));
+        }
         Self {
-            server_url: settings.server_url,
+            startup_auto_connect: settings.profiles[active_profile].auto_connect,
+            profiles: settings.profiles,
+            profile_editor_open: false,
+            sessions: vec![session],
+            active_session: 0,
             input: String::new(),
-            messages: Vec::new(),
-            raw_messages: VecDeque::new(),
-            selected_raw_index: None,
-            connected: false,
-            preferred_username,
-            username: settings.username,
-            ws_tx: None,
-            ui_rx: None,
-            pending_pings: HashMap::new(),
-            latency_samples: VecDeque::new(),
-            last_auto_ping_sent: None,
-            security_info: None,
-            metrics: Metrics::default(),
+            auto_reconnect: settings.auto_reconnect,
+            reconnect_max_attempts: settings.reconnect_max_attempts,
+            history_policy,
+            unknown_types: settings.unknown_types,
+            outbox,
+            search: SearchState::default(),
+            frame_filter: FrameFilter::default(),
+            export_dialog_open: false,
+            export_options: ExportOptions::default(),
+            raw_composer: String::new(),
+            room_input: String::new(),
+            markdown_safe_mode: settings.markdown_safe_mode,
+            ai_usage: AiUsageLog::load(),
+            ai_daily_limit: settings.ai_daily_limit,
+            ai_limit_confirmed: None,
+            replay_dialog_open: false,
+            replay_path: String::new(),
+            replay_speed: 1.0,
             theme_initialized: false,
         }
     }
 }
 
 impl ChatApp {
+    fn session(&self) -> &Session {
+        &self.sessions[self.active_session]
+    }
+
+    fn session_mut(&mut self) -> &mut Session {
+        &mut self.sessions[self.active_session]
+    }
+
     fn persist_settings(&mut self) {
+        self.sync_active_profile();
         let settings = AppSettings {
-            server_url: self.server_url.clone(),
-            username: self.preferred_username.clone(),
+            profiles: self.profiles.clone(),
+            active_profile: self.profiles[self.session().profile].name.clone(),
+            auto_reconnect: self.auto_reconnect,
+            reconnect_max_attempts: self.reconnect_max_attempts,
+            history_enabled: self.history_policy.enabled,
+            history_max_days: self.history_policy.max_days,
+            history_max_mb: self.history_policy.max_mb,
+            unknown_types: self.unknown_types.clone(),
+            markdown_safe_mode: self.markdown_safe_mode,
+            ai_daily_limit: self.ai_daily_limit,
         };
 
         if let Err(err) = save_settings(&settings) {
-            self.messages.push(ChatLine::Error(err));
+            self.session_mut().push_line(ChatLine::Error(err));
         }
     }
 
-    fn connect(&mut self, ctx: egui::Context) {
-        let url = self.server_url.clone();
-        let (ui_tx, ui_rx) = channel::<UiEvent>();
-
-        self.ws_tx = Some(start_connection(url, ui_tx, ctx));
-        self.ui_rx = Some(ui_rx);
-        self.persist_settings();
+    fn sync_active_profile(&mut self) {
+        let session = &self.sessions[self.active_session];
+        let profile = &mut self.profiles[session.profile];
+        profile.server_url = session.server_url.clone();
+        profile.username = session.preferred_username.clone();
     }
 
-    fn send_ws(&mut self, outgoing: Outgoing) {
-        if let Some(tx) = &self.ws_tx {
-            let _ = tx.send(WsCommand::Send(outgoing));
+    fn load_active_profile(&mut self) {
+        let session = &mut self.sessions[self.active_session];
+        let profile = &self.profiles[session.profile];
+        session.server_url = profile.server_url.clone();
+        session.preferred_username = if session::is_guest_name(&profile.username) {
+            String::new()
         } else {
-            self.messages
-                .push(ChatLine::Error("Not connected to server.".to_string()));
-        }
+            profile.username.clone()
+        };
     }
 
-    fn send_message(&mut self) {
-        let text = self.input.clone();
-        match parse_user_input(&text) {
-            ParsedInput::Empty => {}
-            ParsedInput::Error(err) => self.messages.push(ChatLine::Error(err)),
-            ParsedInput::Chat(text) => self.send_ws(Outgoing::Chat { text }),
-            ParsedInput::SetName(name) => {
-                self.preferred_username = name.clone();
-                self.persist_settings();
-                self.send_ws(Outgoing::SetName { name });
-            }
-            ParsedInput::Status => self.send_ws(Outgoing::Status),
-            ParsedInput::ListUsers => self.send_ws(Outgoing::ListUsers),
-            ParsedInput::Ping(token) => {
-                let token = token.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
-                self.pending_pings.insert(token.clone(), Instant::now());
-                self.send_ws(Outgoing::Ping { token: Some(token) });
-            }
-            ParsedInput::Ai(prompt) => {
-                self.messages.push(ChatLine::System {
-                    text: "AI is thinking...".to_string(),
-                    at: None,
-                });
-                self.send_ws(Outgoing::Ai { prompt });
-            }
+    /// Points the front tab at another profile. The tab starts over with a
+    /// fresh session, just like opening the profile in a new tab.
+    fn switch_profile(&mut self, idx: usize, ctx: &egui::Context) {
+        if idx == self.session().profile || idx >= self.profiles.len() {
+            return;
         }
+        self.sync_active_profile();
+        self.session_mut().disconnect();
+        let mut session = Session::new(idx, &self.profiles[idx]);
+        session.open_history(&self.history_policy);
+        self.sessions[self.active_session] = session;
+        self.persist_settings();
+        if self.profiles[idx].auto_connect {
+            self.connect(ctx.clone());
+        }
+    }
 
-        self.input.clear();
+    fn open_tab(&mut self, profile_idx: usize, ctx: &egui::Context) {
+        self.sync_active_profile();
+        let mut session = Session::new(profile_idx, &self.profiles[profile_idx]);
+        session.open_history(&self.history_policy);
+        self.sessions.push(session);
+        self.active_session = self.sessions.len() - 1;
+        if self.profiles[profile_idx].auto_connect {
+            self.connect(ctx.clone());
+        }
     }
 
-    fn process_incoming(&mut self) {
-        let mut events = Vec::new();
-        if let Some(rx) = &self.ui_rx {
-            while let Ok(event) = rx.try_recv() {
-                events.push(event);
-            }
+    fn close_tab(&mut self, idx: usize) {
+        if self.sessions.len() <= 1 || idx >= self.sessions.len() {
+            return;
+        }
+        self.sessions[idx].disconnect();
+        self.sessions.remove(idx);
+        if self.active_session > idx || self.active_session >= self.sessions.len() {
+            self.active_session = self.active_session.saturating_sub(1);
         }
+    }
 
-        for event in events {
-            match event {
-                    UiEvent::Connected => {
-                        if self.metrics.connect_count > 0 {
-                            self.metrics.reconnects += 1;
-                        }
-                        self.metrics.connect_count += 1;
-                        self.metrics.last_connected_at = Some(Instant::now());
-                        self.connected = true;
-                        self.last_auto_ping_sent = Some(Instant::now());
-                        if !self.preferred_username.trim().is_empty()
-                            && !is_guest_name(&self.preferred_username)
-                        {
-                            self.send_ws(Outgoing::SetName {
-                                name: self.preferred_username.clone(),
-                            });
-                        }
-                        self.messages.push(ChatLine::System {
-                            text: "Connected!".to_string(),
-                            at: None,
-                        });
-                    }
-                    UiEvent::Disconnected(reason) => {
-                        self.connected = false;
-                        self.ws_tx = None;
-                        self.pending_pings.clear();
-                        self.last_auto_ping_sent = None;
-                        if let Some(reason) = reason {
-                            self.messages.push(ChatLine::Error(reason));
-                        }
-                        self.messages.push(ChatLine::System {
-                            text: "Disconnected".to_string(),
-                            at: None,
-                        });
-                    }
-                    UiEvent::Warning(text) => {
-                        self.record_error_event();
-                        self.messages.push(ChatLine::Error(text));
-                    }
-                    UiEvent::Error(text) => {
-                        self.record_error_event();
-                        self.messages.push(ChatLine::Error(text));
-                    }
-                    UiEvent::Incoming(Incoming::Chat { from, text, at }) => {
-                        self.messages.push(ChatLine::Chat { from, text, at });
-                    }
-                    UiEvent::Incoming(Incoming::System { text, at }) => {
-                        self.messages.push(ChatLine::System { text, at });
-                    }
-                    UiEvent::Incoming(Incoming::AckName { name, at }) => {
-                        self.username = name.clone();
-                        self.messages.push(ChatLine::System {
-                            text: format!("Your name is now: {}", name),
-                            at,
-                        });
-                    }
-                    UiEvent::Incoming(Incoming::Status {
-                        version,
-                        rust_version,
-                        os,
-                        cpu_cores,
-                        uptime_seconds,
-                        user_count,
-                        peak_users,
-                        connections_total,
-                        messages_sent,
-                        messages_per_second,
-                        memory_mb,
-                        ai_enabled,
-                        ai_model,
-                        at,
-                    }) => {
-                        let mut rows = vec![
-                            ("Version".to_string(), version),
-                            ("Uptime".to_string(), format_uptime(uptime_seconds)),
-                        ];
-
-                        if let Some(os_name) = os {
-                            rows.push((
-                                "Platform".to_string(),
-                                cpu_cores
-                                    .map(|c| format!("{} ({} cores)", os_name, c))
-                                    .unwrap_or(os_name),
-                            ));
-                        }
-                        if let Some(rust_ver) = rust_version {
-                            rows.push(("Rust".to_string(), rust_ver));
-                        }
-                        let users_value = if let Some(peak) = peak_users {
-                            format!("{} (peak: {})", user_count, peak)
-                        } else {
-                            user_count.to_string()
-                        };
-                        rows.push(("Users".to_string(), users_value));
-                        if let Some(conns) = connections_total {
-                            rows.push(("Connections".to_string(), conns.to_string()));
-                        }
-                        rows.push(("Messages".to_string(), messages_sent.to_string()));
-                        rows.push((
-                            "Throughput".to_string(),
-                            format!("{:.2} msg/s", messages_per_second),
-                        ));
-                        rows.push(("Memory".to_string(), format!("{:.2} MB", memory_mb)));
-                        if let Some(enabled) = ai_enabled {
-                            let ai_status = if enabled {
-                                ai_model.unwrap_or_else(|| "enabled".to_string())
-                            } else {
-                                "disabled".to_string()
-                            };
-                            rows.push(("AI".to_string(), ai_status));
-                        }
-                        self.messages.push(ChatLine::StatusCard { at, rows });
-                    }
-                    UiEvent::Incoming(Incoming::ListUsers { users, at }) => {
-                        let mapped = users
-                            .into_iter()
-                            .map(|u| (u.name, u.ip, u.id))
-                            .collect::<Vec<_>>();
-                        self.messages.push(ChatLine::UsersCard { at, users: mapped });
-                    }
-                    UiEvent::Incoming(Incoming::Error { message, at }) => {
-                        let prefix = format_at_prefix(at);
-                        self.messages.push(ChatLine::Error(format!("{}{}", prefix, message)));
-                    }
-                    UiEvent::Incoming(Incoming::Pong { token, at }) => {
-                        let roundtrip = token
-                            .as_ref()
-                            .and_then(|t| self.pending_pings.remove(t).map(|start| start.elapsed()));
-                        let is_auto_ping = token
-                            .as_ref()
-                            .map(|t| t.starts_with(AUTO_PING_PREFIX))
-                            .unwrap_or(false);
-                        let token_str = token
-                            .as_ref()
-                            .map(|t| format!(" (token: {}...)", &t[..8.min(t.len())]))
-                            .unwrap_or_default();
-                        if let Some(rtt) = roundtrip {
-                            let rtt_ms = (rtt.as_secs_f64() * 1000.0) as f32;
-                            if is_auto_ping {
-                                self.record_latency_sample(rtt_ms);
-                            } else {
-                                self.messages.push(ChatLine::Status {
-                                    text: format!(
-                                        "Pong! roundtrip: {:.2}ms{}",
-                                        rtt.as_secs_f64() * 1000.0,
-                                        token_str
-                                    ),
-                                    at,
-                                });
-                            }
-                        } else {
-                            if !is_auto_ping {
-                                self.messages.push(ChatLine::Status {
-                                    text: format!("Pong!{}", token_str),
-                                    at,
-                                });
-                            }
-                        }
-                    }
-                    UiEvent::Incoming(Incoming::Ai {
-                        from,
-                        prompt,
-                        response,
-                        response_ms,
-                        tokens,
-                        cost,
-                        at,
-                    }) => {
-                        let mut stats_parts = vec![format!("{}ms", response_ms)];
-                        if let Some(t) = tokens {
-                            stats_parts.push(format!("{} tokens", t));
-                        }
-                        if let Some(c) = cost {
-                            stats_parts.push(format!("${:.4}", c));
-                        }
-                        self.messages.push(ChatLine::Ai {
-                            from,
-                            prompt,
-                            response,
-                            stats: stats_parts.join(" | "),
-                            at,
-                        });
-                    }
-                    UiEvent::Raw(line) => {
-                        self.record_raw_line(line.clone());
-                        if line.starts_with(">> ") {
-                            self.metrics.ws_out_frames += 1;
-                        } else if line.starts_with("<< ") {
-                            self.metrics.ws_in_frames += 1;
-                        }
-                    }
-                    UiEvent::Security(info) => {
-                        self.security_info = Some(info);
+    fn apply_profile_action(&mut self, action: ProfileAction) {
+        match action {
+            ProfileAction::Add => {
+                let mut n = self.profiles.len() + 1;
+                while self.profiles.iter().any(|p| p.name == format!("Profiel {}", n)) {
+                    n += 1;
+                }
+                let server_url = self.session().server_url.clone();
+                self.profiles
+                    .push(ServerProfile::new(&format!("Profiel {}", n), &server_url));
+            }
+            ProfileAction::Remove(idx) => {
+                let in_use = self.sessions.iter().any(|s| s.profile == idx);
+                if self.profiles.len() <= 1 || idx >= self.profiles.len() || in_use {
+                    return;
+                }
+                self.profiles.remove(idx);
+                for session in &mut self.sessions {
+                    if session.profile > idx {
+                        session.profile -= 1;
                     }
                 }
+            }
         }
+        self.persist_settings();
     }
 
-    fn record_error_event(&mut self) {
-        let now = Instant::now();
-        self.metrics.error_timestamps.push_back(now);
-        self.prune_old_errors(now);
+    fn connect(&mut self, ctx: egui::Context) {
+        let tls = self.profiles[self.session().profile].tls.clone();
+        let reconnect = ReconnectPolicy {
+            enabled: self.auto_reconnect,
+            max_attempts: self.reconnect_max_attempts,
+            ..ReconnectPolicy::default()
+        };
+        let history_policy = self.history_policy;
+        self.session_mut()
+            .connect(tls, reconnect, &history_policy, ctx);
+        self.persist_settings();
     }
 
-    fn prune_old_errors(&mut self, now: Instant) {
-        while let Some(oldest) = self.metrics.error_timestamps.front() {
-            if now.duration_since(*oldest) > Duration::from_secs(60) {
-                let _ = self.metrics.error_timestamps.pop_front();
-            } else {
-                break;
+    fn send_message(&mut self) {
+        let text = self.input.clone();
+        let parsed = parse_user_input(&text);
+        if let ParsedInput::Ai(prompt) = &parsed {
+            // A server without AI refuses below; no limit warning for that
+            if self.session().capabilities.supports_ai() && !self.confirm_ai_spending(prompt) {
+                return;
             }
         }
-    }
-
-    fn record_raw_line(&mut self, line: String) {
-        let payload = line
-            .strip_prefix(">> ")
-            .or_else(|| line.strip_prefix("<< "))
-            .unwrap_or(&line)
-            .to_string();
-        self.raw_messages.push_back(RawLine { line, payload });
-        while self.raw_messages.len() > MAX_RAW_MESSAGES {
-            let _ = self.raw_messages.pop_front();
-            if let Some(sel) = self.selected_raw_index {
-                self.selected_raw_index = sel.checked_sub(1);
+        let session = &mut self.sessions[self.active_session];
+        let outbox = &mut self.outbox;
+        match parsed {
+            ParsedInput::Empty => {}
+            ParsedInput::Error(err) => session.push_line(ChatLine::Error(err)),
+            ParsedInput::Chat(text) => match &session.active_conversation {
+                Conversation::Dm(peer) => {
+                    let to = peer.clone();
+                    session.send_ws(Outgoing::Dm { to, text }, outbox);
+                }
+                Conversation::Room(_) => {
+                    let room = session.chat_room();
+                    session.send_ws(Outgoing::Chat { text, room }, outbox);
+                }
+            },
+            ParsedInput::Dm { to, text } => {
+                session.open_dm(&to);
+                session.send_ws(Outgoing::Dm { to, text }, outbox);
+            }
+            ParsedInput::Join(room) => session.join_room(room, outbox),
+            ParsedInput::Leave(room) => {
+                let room = room.or_else(|| match &session.active_conversation {
+                    Conversation::Room(open) => Some(open.clone()),
+                    Conversation::Dm(_) => None,
+                });
+                match room {
+                    Some(room) => session.leave_room(room, outbox),
+                    None => session.push_line(ChatLine::Error("Usage: /leave <room>".to_string())),
+                }
+            }
+            ParsedInput::ListRooms => session.send_ws(Outgoing::ListRooms, outbox),
+            ParsedInput::SetName(name) => {
+                session.preferred_username = name.clone();
+                session.send_ws(Outgoing::SetName { name }, outbox);
+                self.persist_settings();
+            }
+            ParsedInput::Status => session.send_ws(Outgoing::Status, outbox),
+            ParsedInput::ListUsers => session.send_ws(Outgoing::ListUsers, outbox),
+            ParsedInput::Ping(token) => {
+                let token = token.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+                session.pending_pings.insert(token.clone(), Instant::now());
+                session.send_ws(Outgoing::Ping { token: Some(token) }, outbox);
+            }
+            ParsedInput::Ai(_) if !session.capabilities.supports_ai() => {
+                session.push_line(ChatLine::Error("AI staat uit op deze server.".to_string()));
+            }
+            ParsedInput::Ai(prompt) => {
+                let request = session.ai_request(prompt);
+                session.send_ws(request, outbox);
             }
         }
-    }
 
-    fn record_latency_sample(&mut self, ms: f32) {
-        self.latency_samples.push_back(ms);
-        while self.latency_samples.len() > MAX_LATENCY_SAMPLES {
-            let _ = self.latency_samples.pop_front();
-        }
+        self.input.clear();
     }
 
-    fn maybe_send_auto_ping(&mut self) {
-        if !self.connected {
-            return;
-        }
-        let now = Instant::now();
-        let should_ping = self
-            .last_auto_ping_sent
-            .map(|last| now.duration_since(last).as_secs() >= AUTO_PING_INTERVAL_SECS)
-            .unwrap_or(true);
-        if !should_ping {
-            return;
+    /// Warns instead of sending once today's AI cost reached the daily
+    /// limit. The input is kept, so sending the same prompt again goes through.
+    fn confirm_ai_spending(&mut self, prompt: &str) -> bool {
+        let spent = self.ai_usage.day_totals(&day_key(now_unix_ms())).cost;
+        let Some(warning) = self.ai_daily_limit.and_then(|limit| limit_warning(spent, limit)) else {
+            return true;
+        };
+        if self.ai_limit_confirmed.as_deref() == Some(prompt) {
+            self.ai_limit_confirmed = None;
+            return true;
         }
+        self.ai_limit_confirmed = Some(prompt.to_string());
+        self.session_mut().push_notice(ChatLine::System {
+            text: format!("{} Verstuur nogmaals om toch te vragen.", warning),
+            at: None,
+        });
+        false
+    }
 
-        if let Some(tx) = &self.ws_tx {
-            let token = format!("{}{}", AUTO_PING_PREFIX, uuid::Uuid::new_v4());
-            self.pending_pings.insert(token.clone(), now);
-            let _ = tx.send(WsCommand::Send(Outgoing::Ping { token: Some(token) }));
-            self.last_auto_ping_sent = Some(now);
-        }
+    fn draw_latency_graph(session: &Session, ui: &mut egui::Ui, size: egui::Vec2) {
+        let samples = session.latency_samples.iter().copied().collect::<Vec<_>>();
+        Self::draw_sample_graph(ui, size, "Latency (ms)", "Wachten op metingen...", &samples);
     }
 
-    fn draw_latency_graph(&self, ui: &mut egui::Ui, size: egui::Vec2) {
+    /// Line chart of `samples` in milliseconds, oldest left.
+    fn draw_sample_graph(ui: &mut egui::Ui, size: egui::Vec2, title: &str, empty_text: &str, samples: &[f32]) {
         let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
         let painter = ui.painter_at(rect);
         painter.rect_filled(rect, 8.0, egui::Color32::from_rgb(20, 33, 47));
@@ -462,16 +437,16 @@ impl ChatApp {
         painter.text(
             egui::pos2(inner.left(), inner.top()),
             egui::Align2::LEFT_TOP,
-            "Latency (ms)",
+            title,
             egui::FontId::proportional(12.0),
             egui::Color32::from_rgb(183, 214, 245),
         );
 
-        if self.latency_samples.is_empty() {
+        if samples.is_empty() {
             painter.text(
                 inner.center(),
                 egui::Align2::CENTER_CENTER,
-                "Wachten op metingen...",
+                empty_text,
                 egui::FontId::proportional(11.0),
                 egui::Color32::from_gray(150),
             );
@@ -482,12 +457,7 @@ impl ChatApp {
         let chart_bottom = inner.bottom() - 2.0;
         let chart_left = inner.left() + 2.0;
         let chart_right = inner.right() - 2.0;
-        let max_value = self
-            .latency_samples
-            .iter()
-            .copied()
-            .fold(0.0_f32, f32::max)
-            .max(20.0);
+        let max_value = samples.iter().copied().fold(0.0_f32, f32::max).max(20.0);
 
         for i in 0..=4 {
             let t = i as f32 / 4.0;
@@ -506,9 +476,9 @@ impl ChatApp {
             );
         }
 
-        let mut points = Vec::with_capacity(self.latency_samples.len());
-        let denom = (self.latency_samples.len().saturating_sub(1)).max(1) as f32;
-        for (idx, value) in self.latency_samples.iter().enumerate() {
+        let mut points = Vec::with_capacity(samples.len());
+        let denom = (samples.len().saturating_sub(1)).max(1) as f32;
+        for (idx, value) in samples.iter().enumerate() {
             let t = idx as f32 / denom;
             let x = egui::lerp(chart_left..=chart_right, t);
             let y = egui::remap_clamp(*value, 0.0..=max_value, chart_bottom..=chart_top);
@@ -526,7 +496,7 @@ impl ChatApp {
             painter.circle_filled(*last, 2.8, egui::Color32::from_rgb(157, 226, 255));
         }
 
-        if let Some(last_ms) = self.latency_samples.back() {
+        if let Some(last_ms) = samples.last() {
             painter.text(
                 egui::pos2(inner.right(), inner.top()),
                 egui::Align2::RIGHT_TOP,
@@ -551,46 +521,31 @@ impl ChatApp {
         );
     }
 
-    fn latency_avg_ms(&self) -> Option<f32> {
-        if self.latency_samples.is_empty() {
-            return None;
-        }
-        let sum: f32 = self.latency_samples.iter().copied().sum();
-        Some(sum / self.latency_samples.len() as f32)
-    }
 
-    fn latency_p95_ms(&self) -> Option<f32> {
-        if self.latency_samples.is_empty() {
-            return None;
-        }
-        let mut values = self.latency_samples.iter().copied().collect::<Vec<_>>();
-        values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
-        let idx = ((values.len() as f32) * 0.95).ceil() as usize;
-        values.get(idx.saturating_sub(1)).copied()
-    }
 
-    fn render_metrics_panel(&mut self, ui: &mut egui::Ui) {
-        self.prune_old_errors(Instant::now());
+    fn render_metrics_panel(session: &mut Session, ui: &mut egui::Ui) {
+        session.prune_old_errors(Instant::now());
         egui::CollapsingHeader::new("Metrics")
             .default_open(false)
             .show(ui, |ui| {
-                let errors_per_min = self.metrics.error_timestamps.len();
-                let avg = self
+                let errors_per_min = session.metrics.error_timestamps.len();
+                let avg = session
                     .latency_avg_ms()
                     .map(|v| format!("{:.1} ms", v))
                     .unwrap_or_else(|| "-".to_string());
-                let p95 = self
+                let p95 = session
                     .latency_p95_ms()
                     .map(|v| format!("{:.1} ms", v))
                     .unwrap_or_else(|| "-".to_string());
 
                 let rows = vec![
-                    ("Frames in", self.metrics.ws_in_frames.to_string()),
-                    ("Frames out", self.metrics.ws_out_frames.to_string()),
-                    ("Reconnects", self.metrics.reconnects.to_string()),
+                    ("Frames in", session.metrics.ws_in_frames.to_string()),
+                    ("Frames out", session.metrics.ws_out_frames.to_string()),
+                    ("Reconnects", session.metrics.reconnects.to_string()),
                     ("Avg latency", avg),
                     ("P95 latency", p95),
                     ("Errors/min", errors_per_min.to_string()),
+                    ("Schema issues", session.metrics.schema_violations.to_string()),
                 ];
                 for (k, v) in rows {
                     ui.horizontal(|ui| {
@@ -612,11 +567,109 @@ impl ChatApp {
             });
     }
 
-    fn render_security_panel(&self, ui: &mut egui::Ui) {
+    /// Token and cost totals, response times and the daily limit. Returns
+    /// true when the limit was changed and settings need saving.
+    fn render_ai_usage_panel(
+        session: &Session,
+        usage: &AiUsageLog,
+        profiles: &[ServerProfile],
+        daily_limit: &mut Option<f64>,
+        ui: &mut egui::Ui,
+    ) -> bool {
+        let mut limit_changed = false;
+        egui::CollapsingHeader::new("AI usage")
+            .default_open(false)
+            .show(ui, |ui| {
+                let row = |ui: &mut egui::Ui, k: &str, v: String| {
+                    ui.horizontal(|ui| {
+                        ui.add_sized(
+                            [92.0, 16.0],
+                            egui::Label::new(
+                                egui::RichText::new(k)
+                                    .small()
+                                    .color(egui::Color32::from_gray(160)),
+                            ),
+                        );
+                        ui.label(
+                            egui::RichText::new(v)
+                                .small()
+                                .color(egui::Color32::from_rgb(190, 216, 244)),
+                        );
+                    });
+                };
+                let heading = |ui: &mut egui::Ui, text: &str| {
+                    ui.label(
+                        egui::RichText::new(text)
+                            .small()
+                            .strong()
+                            .color(egui::Color32::from_rgb(164, 198, 233)),
+                    );
+                };
+
+                let today = usage.day_totals(&day_key(now_unix_ms()));
+                let response_ms = session.ai_usage.iter().map(|r| r.response_ms).collect::<Vec<_>>();
+                let pct = |p| {
+                    percentile(&response_ms, p)
+                        .map(|v| format!("{} ms", v))
+                        .unwrap_or_else(|| "-".to_string())
+                };
+                row(ui, "Sessie", UsageTotals::sum(&session.ai_usage).describe());
+                row(ui, "Vandaag", today.describe());
+                row(ui, "P50 / P90", format!("{} / {}", pct(50), pct(90)));
+                row(ui, "P99", pct(99));
+                let samples = response_ms.iter().map(|&ms| ms as f32).collect::<Vec<_>>();
+                Self::draw_sample_graph(
+                    ui,
+                    egui::vec2(ui.available_width(), 84.0),
+                    "AI-responstijd (ms)",
+                    "Nog geen AI-antwoorden",
+                    &samples,
+                );
+
+                let per_profile = usage.per_profile(profiles);
+                if !per_profile.is_empty() {
+                    heading(ui, "Per profiel");
+                    for (profile, totals) in per_profile {
+                        row(ui, &profile, totals.describe());
+                    }
+                    heading(ui, "Per dag");
+                    for (day, totals) in usage.per_day().iter().rev().take(7) {
+                        row(ui, day, totals.describe());
+                    }
+                }
+
+                ui.add_space(4.0);
+                ui.horizontal(|ui| {
+                    let mut enabled = daily_limit.is_some();
+                    let mut amount = daily_limit.unwrap_or(1.0);
+                    let toggled = ui
+                        .checkbox(&mut enabled, egui::RichText::new("Daglimiet $").small())
+                        .on_hover_text("Waarschuwen voor een nieuwe /ai zodra de kosten van vandaag dit bedrag bereiken")
+                        .changed();
+                    let edit = ui.add_enabled(
+                        enabled,
+                        egui::DragValue::new(&mut amount)
+                            .speed(0.01)
+                            .range(0.0..=1000.0)
+                            .fixed_decimals(2),
+                    );
+                    if toggled || edit.changed() {
+                        *daily_limit = enabled.then_some(amount);
+                    }
+                    limit_changed = toggled || edit.drag_stopped() || edit.lost_focus();
+                });
+                if let Some(limit) = daily_limit {
+                    row(ui, "Limiet", format!("${:.4} van ${:.2}", today.cost, limit));
+                }
+            });
+        limit_changed
+    }
+
+    fn render_security_panel(session: &Session, ui: &mut egui::Ui) {
         egui::CollapsingHeader::new("Security / TLS")
             .default_open(false)
             .show(ui, |ui| {
-                if let Some(info) = &self.security_info {
+                if let Some(info) = &session.security_info {
                     let rows = vec![
                         ("URL", info.url.clone()),
                         ("Transport", info.transport.clone()),
@@ -634,6 +687,23 @@ impl ChatApp {
                                 .map(|s| s.to_string())
                                 .unwrap_or_else(|| "-".to_string()),
                         ),
+                        (
+                            "Server",
+                            session
+                                .capabilities
+                                .version
+                                .clone()
+                                .unwrap_or_else(|| "-".to_string()),
+                        ),
+                        (
+                            "AI",
+                            match (session.capabilities.ai_enabled, &session.capabilities.ai_model) {
+                                (Some(false), _) => "uit".to_string(),
+                                (Some(true), Some(model)) => format!("aan ({})", model),
+                                (Some(true), None) => "aan".to_string(),
+                                (None, _) => "onbekend".to_string(),
+                            },
+                        ),
                     ];
                     for (k, v) in rows {
                         ui.horizontal_wrapped(|ui| {
@@ -686,6 +756,114 @@ impl ChatApp {
             });
     }
 
+    /// Draws the per-type summary and filter controls above the frame list and
+    /// returns the compiled payload regex.
+    fn render_frame_filter(filter: &mut FrameFilter, session: &Session, ui: &mut egui::Ui) -> Option<Matcher> {
+        let counts = frame_type_counts(session.raw_messages.iter().map(|raw| raw.frame_type.as_deref()));
+        ui.horizontal_wrapped(|ui| {
+            ui.spacing_mut().item_spacing.x = 4.0;
+            let all = egui::RichText::new(format!("alle ({})", session.raw_messages.len())).small();
+            if ui.selectable_label(filter.frame_type.is_none(), all).clicked() {
+                filter.frame_type = None;
+            }
+            for (frame_type, count) in &counts {
+                let selected = filter.frame_type.as_deref() == Some(frame_type.as_str());
+                let label = egui::RichText::new(format!("{} ({})", frame_type, count))
+                    .small()
+                    .monospace();
+                if ui.selectable_label(selected, label).clicked() {
+                    filter.frame_type = if selected { None } else { Some(frame_type.clone()) };
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut filter.show_out, egui::RichText::new(">>").small().monospace())
+                .on_hover_text("Uitgaande frames");
+            ui.checkbox(&mut filter.show_in, egui::RichText::new("<<").small().monospace())
+                .on_hover_text("Inkomende frames");
+            ui.checkbox(&mut filter.hide_auto_ping, egui::RichText::new("verberg auto-ping").small())
+                .on_hover_text("Ping/pong van de latency-grafiek verbergen");
+        });
+        ui.add(
+            egui::TextEdit::singleline(&mut filter.payload_query)
+                .hint_text("regex op payload")
+                .font(egui::TextStyle::Monospace)
+                .desired_width(f32::INFINITY),
+        );
+        match filter.payload_matcher() {
+            Ok(matcher) => matcher,
+            Err(err) => {
+                ui.label(
+                    egui::RichText::new(err)
+                        .small()
+                        .color(egui::Color32::from_rgb(240, 136, 136)),
+                );
+                None
+            }
+        }
+    }
+
+    fn render_raw_composer(session: &mut Session, text: &mut String, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Send raw")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(egui::RichText::new("Template").small());
+                    egui::ComboBox::from_id_salt("raw_template")
+                        .selected_text("kies...")
+                        .width(110.0)
+                        .show_ui(ui, |ui| {
+                            for template in Outgoing::templates() {
+                                if ui.selectable_label(false, template.type_name()).clicked() {
+                                    *text = serde_json::to_string_pretty(&template).unwrap_or_default();
+                                }
+                            }
+                        });
+                });
+                ui.add(
+                    egui::TextEdit::multiline(text)
+                        .code_editor()
+                        .desired_rows(4)
+                        .desired_width(f32::INFINITY)
+                        .hint_text(r#"{"type":"chat","text":"..."}"#),
+                );
+                let valid = match serde_json::from_str::<serde_json::Value>(text) {
+                    Ok(value) => {
+                        let note = match value.get("type").and_then(|t| t.as_str()) {
+                            Some(kind) => format!("✓ geldige JSON, type '{}'", kind),
+                            None => "✓ geldige JSON, zonder 'type'".to_string(),
+                        };
+                        ui.label(
+                            egui::RichText::new(note)
+                                .small()
+                                .color(egui::Color32::from_rgb(104, 219, 152)),
+                        );
+                        true
+                    }
+                    Err(err) => {
+                        if !text.trim().is_empty() {
+                            ui.label(
+                                egui::RichText::new(format!("✗ {}", err))
+                                    .small()
+                                    .color(egui::Color32::from_rgb(240, 136, 136)),
+                            );
+                        }
+                        false
+                    }
+                };
+                let label = if valid { "Verstuur" } else { "Verstuur ongeldige JSON" };
+                let send = ui
+                    .add_enabled(
+                        session.connected && !text.trim().is_empty(),
+                        egui::Button::new(egui::RichText::new(label).small()),
+                    )
+                    .on_disabled_hover_text("Alleen mogelijk met een actieve verbinding.");
+                if send.clicked() {
+                    session.send_raw(text.clone());
+                }
+            });
+    }
+
     fn render_json_value(ui: &mut egui::Ui, key: Option<&str>, value: &serde_json::Value) {
         match value {
             serde_json::Value::Object(map) => {
@@ -725,6 +903,201 @@ impl ChatApp {
         }
     }
 
+    /// Badge with the contract violations of an incoming frame; extra fields
+    /// are shown as a softer warning since the parser ignores them.
+    fn render_schema_badge(ui: &mut egui::Ui, raw: &RawLine) {
+        if raw.schema_issues.is_empty() {
+            return;
+        }
+        let errors = raw.schema_issues.iter().filter(|issue| issue.is_error()).count();
+        let extra = raw
+            .schema_issues
+            .iter()
+            .filter(|issue| matches!(issue, SchemaIssue::ExtraField(_)))
+            .count();
+        let (text, fill) = if errors > 0 {
+            (
+                format!("✗ schema: {} fout(en)", errors),
+                egui::Color32::from_rgb(120, 44, 44),
+            )
+        } else if extra > 0 {
+            (
+                format!("⚠ schema: {} extra veld(en)", extra),
+                egui::Color32::from_rgb(110, 88, 36),
+            )
+        } else {
+            (
+                "ℹ onbekend berichttype".to_string(),
+                egui::Color32::from_rgb(52, 66, 86),
+            )
+        };
+        egui::Frame::none()
+            .fill(fill)
+            .rounding(egui::Rounding::same(8.0))
+            .inner_margin(egui::Margin::symmetric(6.0, 2.0))
+            .show(ui, |ui| {
+                ui.label(egui::RichText::new(text).small().strong().color(egui::Color32::WHITE));
+            });
+        for issue in &raw.schema_issues {
+            ui.label(
+                egui::RichText::new(format!("• {}", issue.describe()))
+                    .small()
+                    .color(egui::Color32::from_gray(190)),
+            );
+        }
+    }
+
+    /// Sidebar with the joined rooms, a join field and the open DM
+    /// conversations. Joining and leaving go through the server, so those
+    /// come back as actions.
+    fn render_conversation_sidebar(
+        session: &mut Session,
+        room_input: &mut String,
+        ui: &mut egui::Ui,
+    ) -> Option<ChatLineAction> {
+        let mut action = None;
+        let mut select = None;
+        let mut close = None;
+        egui::SidePanel::left("conversation_sidebar")
+            .resizable(false)
+            .exact_width(130.0)
+            .frame(egui::Frame::default().inner_margin(egui::Margin::symmetric(0.0, 2.0)))
+            .show_inside(ui, |ui| {
+                ui.label(
+                    egui::RichText::new("Kanalen")
+                        .small()
+                        .color(egui::Color32::from_gray(150)),
+                );
+                for room in &session.rooms {
+                    let selected = session.active_conversation == Conversation::Room(room.clone());
+                    let unread = session.room_unread.get(room).copied().unwrap_or(0);
+                    let label = if unread > 0 {
+                        egui::RichText::new(format!("#{} ({})", room, unread)).strong()
+                    } else {
+                        egui::RichText::new(format!("#{}", room))
+                    };
+                    ui.horizontal(|ui| {
+                        if ui.selectable_label(selected, label).clicked() {
+                            select = Some(Conversation::Room(room.clone()));
+                        }
+                        if room != DEFAULT_ROOM
+                            && ui.small_button("✕").on_hover_text("Kanaal verlaten").clicked()
+                        {
+                            action = Some(ChatLineAction::LeaveRoom(room.clone()));
+                        }
+                    });
+                }
+                let join = ui.add(
+                    egui::TextEdit::singleline(room_input)
+                        .hint_text("+ kanaal")
+                        .desired_width(f32::INFINITY),
+                );
+                if join.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    match parse_user_input(&format!("/join {}", room_input)) {
+                        ParsedInput::Join(room) => action = Some(ChatLineAction::JoinRoom(room)),
+                        ParsedInput::Error(err) => session.push_line(ChatLine::Error(err)),
+                        _ => {}
+                    }
+                    room_input.clear();
+                }
+                if session.dm_peers.is_empty() {
+                    return;
+                }
+                ui.add_space(4.0);
+                ui.label(
+                    egui::RichText::new("Privé")
+                        .small()
+                        .color(egui::Color32::from_gray(150)),
+                );
+                for peer in &session.dm_peers {
+                    let selected = session.active_conversation == Conversation::Dm(peer.clone());
+                    let unread = session.dm_unread.get(peer).copied().unwrap_or(0);
+                    let label = if unread > 0 {
+                        egui::RichText::new(format!("@{} ({})", peer, unread)).strong()
+                    } else {
+                        egui::RichText::new(format!("@{}", peer))
+                    };
+                    ui.horizontal(|ui| {
+                        if ui.selectable_label(selected, label).clicked() {
+                            select = Some(Conversation::Dm(peer.clone()));
+                        }
+                        if ui.small_button("✕").on_hover_text("Gesprek sluiten").clicked() {
+                            close = Some(peer.clone());
+                        }
+                    });
+                }
+            });
+        if let Some(conversation) = select {
+            session.select_conversation(conversation);
+        }
+        if let Some(peer) = close {
+            session.close_dm(&peer);
+        }
+        action
+    }
+
+    /// Structural diff between two raw frames; `old` is the earlier one.
+    fn render_frame_diff(ui: &mut egui::Ui, old: &RawLine, new: &RawLine) {
+        let parse = |raw: &RawLine| serde_json::from_str::<serde_json::Value>(&raw.payload);
+        let (Ok(old_value), Ok(new_value)) = (parse(old), parse(new)) else {
+            ui.label(
+                egui::RichText::new("Beide frames moeten geldige JSON zijn om te vergelijken.")
+                    .small()
+                    .color(egui::Color32::from_gray(160)),
+            );
+            return;
+        };
+        ui.label(
+            egui::RichText::new(format!(
+                "{} → {} (+{} ms)",
+                old.frame_type.as_deref().unwrap_or("?"),
+                new.frame_type.as_deref().unwrap_or("?"),
+                new.mono.saturating_duration_since(old.mono).as_millis()
+            ))
+            .small()
+            .color(egui::Color32::from_gray(160)),
+        );
+        let diff = diff_values(&old_value, &new_value);
+        if diff.is_empty() {
+            ui.label(
+                egui::RichText::new("Geen verschillen.")
+                    .small()
+                    .color(egui::Color32::from_gray(160)),
+            );
+            return;
+        }
+        for entry in &diff {
+            let show = |value: &Option<serde_json::Value>| {
+                value.as_ref().map(|v| v.to_string()).unwrap_or_default()
+            };
+            let (marker, color, detail) = match entry.kind {
+                DiffKind::Added => ("+", egui::Color32::from_rgb(104, 219, 152), show(&entry.new)),
+                DiffKind::Removed => ("-", egui::Color32::from_rgb(240, 136, 136), show(&entry.old)),
+                DiffKind::Changed => {
+                    let delta = entry
+                        .numeric_delta()
+                        .map(|d| format!(" ({:+})", d))
+                        .unwrap_or_default();
+                    (
+                        "~",
+                        egui::Color32::from_rgb(238, 200, 112),
+                        format!("{} → {}{}", show(&entry.old), show(&entry.new), delta),
+                    )
+                }
+            };
+            ui.horizontal_wrapped(|ui| {
+                ui.label(egui::RichText::new(marker).small().monospace().strong().color(color));
+                ui.label(egui::RichText::new(&entry.path).small().monospace().color(color));
+                ui.label(
+                    egui::RichText::new(detail)
+                        .small()
+                        .monospace()
+                        .color(egui::Color32::from_rgb(180, 212, 244)),
+                );
+            });
+        }
+    }
+
     fn apply_modern_theme(&mut self, ctx: &egui::Context) {
         if self.theme_initialized {
             return;
@@ -754,10 +1127,410 @@ impl ChatApp {
         self.theme_initialized = true;
     }
 
-    fn render_chat_line(&self, ui: &mut egui::Ui, line: &ChatLine) {
+    /// Saves the raw pane (or, with `full`, the running recording) as a capture file.
+    fn export_frames(session: &mut Session, format: CaptureFormat, full: bool) {
+        let frames = match session.recording_path().filter(|_| full) {
+            Some(path) => match recording::load_recording(path) {
+                Ok(frames) => export::capture_from_recording(&frames),
+                Err(err) => {
+                    session.push_line(ChatLine::Error(err));
+                    return;
+                }
+            },
+            None => export::capture_from_raw_lines(&session.raw_messages),
+        };
+        let result = export::export_capture(
+            &frames,
+            format,
+            &session.server_url,
+            session.security_info.as_ref(),
+        );
+        match result {
+            Ok(path) => session.push_notice(ChatLine::System {
+                text: format!("{} frames opgeslagen: {}", frames.len(), path.display()),
+                at: None,
+            }),
+            Err(err) => session.push_line(ChatLine::Error(err)),
+        }
+    }
+
+    fn render_export_dialog(&mut self, ctx: &egui::Context) {
+        if !self.export_dialog_open {
+            return;
+        }
+        let mut open = true;
+        let mut save = false;
+        let mut copy = false;
+        egui::Window::new("Transcript exporteren")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Formaat");
+                    for format in ExportFormat::ALL {
+                        ui.selectable_value(&mut self.export_options.format, format, format.label());
+                    }
+                });
+                ui.checkbox(&mut self.export_options.redact_ips, "IP-adressen in gebruikerslijsten verbergen");
+                ui.label(
+                    egui::RichText::new(format!(
+                        "{} regels uit dit tabblad (alleen wat geladen is).",
+                        self.session().messages.len()
+                    ))
+                    .small()
+                    .color(egui::Color32::from_gray(160)),
+                );
+                ui.add_space(4.0);
+                ui.horizontal(|ui| {
+                    save = ui.button("Opslaan").clicked();
+                    copy = ui.button("Kopiëren").clicked();
+                });
+            });
+        self.export_dialog_open = open && !save && !copy;
+        let options = self.export_options;
+        let session = &mut self.sessions[self.active_session];
+        if copy {
+            ctx.copy_text(export::render_transcript(
+                &session.messages,
+                &session.server_url,
+                options,
+            ));
+        }
+        if save {
+            match export::export_transcript(&session.messages, &session.server_url, options) {
+                Ok(path) => session.push_notice(ChatLine::System {
+                    text: format!("Transcript opgeslagen: {}", path.display()),
+                    at: None,
+                }),
+                Err(err) => session.push_line(ChatLine::Error(err)),
+            }
+        }
+    }
+
+    fn render_replay_dialog(&mut self, ctx: &egui::Context) {
+        if !self.replay_dialog_open {
+            return;
+        }
+        let mut open = true;
+        let mut start = false;
+        egui::Window::new("Replay")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let recordings = recording::list_recordings();
+                if recordings.is_empty() {
+                    ui.label(
+                        egui::RichText::new("Nog geen opnames gevonden.")
+                            .small()
+                            .color(egui::Color32::from_gray(160)),
+                    );
+                } else {
+                    egui::ScrollArea::vertical()
+                        .max_height(160.0)
+                        .show(ui, |ui| {
+                            for path in recordings {
+                                let path_str = path.display().to_string();
+                                let name = path
+                                    .file_name()
+                                    .map(|n| n.to_string_lossy().to_string())
+                                    .unwrap_or_else(|| path_str.clone());
+                                let selected = self.replay_path == path_str;
+                                if ui.selectable_label(selected, name).clicked() {
+                                    self.replay_path = path_str;
+                                }
+                            }
+                        });
+                }
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("Bestand");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.replay_path)
+                            .desired_width(320.0)
+                            .hint_text("pad naar .jsonl opname"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Snelheid");
+                    for (label, speed) in [("1x", 1.0), ("2x", 2.0), ("5x", 5.0), ("10x", 10.0), ("Max", 0.0)] {
+                        ui.selectable_value(&mut self.replay_speed, speed, label);
+                    }
+                });
+                ui.add_space(4.0);
+                let can_start = !self.replay_path.trim().is_empty();
+                if ui
+                    .add_enabled(can_start, egui::Button::new("Start replay"))
+                    .on_hover_text("Vervangt de verbinding van het huidige tabblad")
+                    .clicked()
+                {
+                    start = true;
+                }
+            });
+        self.replay_dialog_open = open && !start;
+        if start {
+            let path = std::path::PathBuf::from(self.replay_path.trim());
+            let speed = self.replay_speed;
+            self.session_mut().replay(&path, speed, ctx.clone());
+        }
+    }
+
+    fn render_profile_editor(&mut self, ctx: &egui::Context) {
+        if !self.profile_editor_open {
+            return;
+        }
+        self.sync_active_profile();
+        let mut open = true;
+        let mut changed = false;
+        let mut action = None;
+        egui::Window::new("Profielen")
+            .open(&mut open)
+            .resizable(false)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                let active_profile = self.sessions[self.active_session].profile;
+                let profile_count = self.profiles.len();
+                for (idx, profile) in self.profiles.iter_mut().enumerate() {
+                    let in_use = self.sessions.iter().any(|s| s.profile == idx);
+                    egui::CollapsingHeader::new(egui::RichText::new(&profile.name).strong())
+                        .id_salt(("profile", idx))
+                        .default_open(idx == active_profile)
+                        .show(ui, |ui| {
+                            egui::Grid::new(("profile_grid", idx))
+                                .num_columns(2)
+                                .spacing([8.0, 4.0])
+                                .show(ui, |ui| {
+                                    ui.label("Naam");
+                                    changed |= ui.text_edit_singleline(&mut profile.name).changed();
+                                    ui.end_row();
+                                    ui.label("Server");
+                                    changed |= ui
+                                        .add(
+                                            egui::TextEdit::singleline(&mut profile.server_url)
+                                                .hint_text("ws://127.0.0.1:3001"),
+                                        )
+                                        .changed();
+                                    ui.end_row();
+                                    ui.label("Naam op server");
+                                    changed |= ui.text_edit_singleline(&mut profile.username).changed();
+                                    ui.end_row();
+                                    ui.label("Opties");
+                                    ui.vertical(|ui| {
+                                        changed |= ui
+                                            .checkbox(&mut profile.auto_connect, "Auto-connect bij opstarten")
+                                            .changed();
+                                        changed |= ui
+                                            .checkbox(
+                                                &mut profile.tls.accept_invalid_certs,
+                                                "Ongeldige TLS-certificaten accepteren",
+                                            )
+                                            .changed();
+                                        changed |= ui
+                                            .checkbox(
+                                                &mut profile.tls.accept_invalid_hostnames,
+                                                "TLS hostname niet controleren",
+                                            )
+                                            .changed();
+                                    });
+                                    ui.end_row();
+                                });
+                            if ui
+                                .add_enabled(
+                                    profile_count > 1 && !in_use,
+                                    egui::Button::new("Verwijderen"),
+                                )
+                                .on_disabled_hover_text("Profiel is open in een tabblad.")
+                                .clicked()
+                            {
+                                action = Some(ProfileAction::Remove(idx));
+                            }
+                        });
+                }
+                ui.separator();
+                if ui.button("+ Nieuw profiel").clicked() {
+                    action = Some(ProfileAction::Add);
+                }
+                if !self.unknown_types.is_empty() {
+                    ui.separator();
+                    ui.label(egui::RichText::new("Onbekende berichttypes").strong());
+                    let mut removed = None;
+                    for (msg_type, choice) in self.unknown_types.iter_mut() {
+                        ui.horizontal(|ui| {
+                            ui.label(egui::RichText::new(msg_type).monospace());
+                            egui::ComboBox::from_id_salt(("unknown_type", msg_type.as_str()))
+                                .selected_text(choice.label())
+                                .show_ui(ui, |ui| {
+                                    for option in UnknownTypeAction::ALL {
+                                        changed |= ui
+                                            .selectable_value(choice, option, option.label())
+                                            .changed();
+                                    }
+                                });
+                            if ui.small_button("✕").on_hover_text("Terug naar standaard (tonen)").clicked() {
+                                removed = Some(msg_type.clone());
+                            }
+                        });
+                    }
+                    if let Some(msg_type) = removed {
+                        self.unknown_types.remove(&msg_type);
+                        changed = true;
+                    }
+                }
+            });
+        self.profile_editor_open = open;
+        self.load_active_profile();
+        for session in &mut self.sessions {
+            session.profile_name = self.profiles[session.profile].name.clone();
+        }
+        if let Some(action) = action {
+            self.apply_profile_action(action);
+        } else if changed {
+            self.persist_settings();
+        }
+    }
+
+    fn render_search_bar(&mut self, ui: &mut egui::Ui, match_count: usize, error: Option<&str>) {
+        ui.horizontal(|ui| {
+            let query = ui.add_sized(
+                [(ui.available_width() - 250.0).max(120.0), 22.0],
+                egui::TextEdit::singleline(&mut self.search.query)
+                    .vertical_align(egui::Align::Center)
+                    .hint_text("Zoeken (Ctrl+F)..."),
+            );
+            if self.search.focus_requested {
+                query.request_focus();
+                self.search.focus_requested = false;
+            }
+            if query.changed() {
+                self.search.current = 0;
+                self.search.scroll_to_current = true;
+            }
+            if query.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                let backwards = ui.input(|i| i.modifiers.shift);
+                self.search.step(!backwards, match_count);
+                query.request_focus();
+            }
+            if ui
+                .checkbox(&mut self.search.use_regex, egui::RichText::new("Regex").small())
+                .changed()
+                || ui
+                    .checkbox(&mut self.search.case_sensitive, egui::RichText::new("Aa").small())
+                    .on_hover_text("Hoofdlettergevoelig")
+                    .changed()
+            {
+                self.search.current = 0;
+            }
+            if ui.small_button("◀").clicked() {
+                self.search.step(false, match_count);
+            }
+            if ui.small_button("▶").clicked() {
+                self.search.step(true, match_count);
+            }
+            let position = if match_count == 0 {
+                "0/0".to_string()
+            } else {
+                format!("{}/{}", self.search.current + 1, match_count)
+            };
+            ui.label(egui::RichText::new(position).small().color(egui::Color32::from_gray(170)));
+            if ui.small_button("✕").clicked() {
+                self.search.open = false;
+            }
+        });
+        ui.horizontal_wrapped(|ui| {
+            ui.label(egui::RichText::new("Van:").small());
+            ui.add_sized(
+                [110.0, 18.0],
+                egui::TextEdit::singleline(&mut self.search.sender).hint_text("afzender"),
+            );
+            for (idx, kind) in LineKind::ALL.iter().enumerate() {
+                let enabled = self.search.kinds[idx];
+                if ui
+                    .selectable_label(enabled, egui::RichText::new(kind.label()).small())
+                    .clicked()
+                {
+                    self.search.kinds[idx] = !enabled;
+                    self.search.current = 0;
+                }
+            }
+            if let Some(err) = error {
+                ui.label(
+                    egui::RichText::new(err)
+                        .small()
+                        .color(egui::Color32::from_rgb(246, 171, 171)),
+                );
+            }
+        });
+        if ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+            self.search.open = false;
+        }
+        ui.separator();
+    }
+
+    fn render_outbox_items(
+        ui: &mut egui::Ui,
+        outbox: &Outbox,
+        session: &Session,
+    ) -> Option<OutboxAction> {
+        let mut action = None;
+        let identity = session.identity();
+        for item in outbox.pending_for(&session.server_url, &identity) {
+            egui::Frame::default()
+                .fill(egui::Color32::from_rgb(30, 34, 42))
+                .stroke(egui::Stroke::new(1.0, egui::Color32::from_rgb(92, 98, 110)))
+                .rounding(egui::Rounding::same(8.0))
+                .inner_margin(egui::Margin::symmetric(10.0, 8.0))
+                .show(ui, |ui| {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.label(
+                            egui::RichText::new("⏳ pending")
+                                .small()
+                                .strong()
+                                .color(egui::Color32::from_gray(170)),
+                        );
+                        ui.label(
+                            egui::RichText::new(describe_outgoing(&item.outgoing))
+                                .color(egui::Color32::from_gray(196)),
+                        );
+                    });
+                    ui.horizontal(|ui| {
+                        if let Some(err) = &item.last_error {
+                            ui.label(
+                                egui::RichText::new(format!(
+                                    "{} (pogingen: {})",
+                                    err, item.attempts
+                                ))
+                                .small()
+                                .color(egui::Color32::from_rgb(226, 160, 160)),
+                            );
+                        }
+                        let retry = ui
+                            .add_enabled(session.connected, egui::Button::new(egui::RichText::new("Retry").small()))
+                            .on_disabled_hover_text("Wordt verstuurd zodra de verbinding terug is.");
+                        if retry.clicked() {
+                            action = Some(OutboxAction::Retry(item.id.clone()));
+                        }
+                        if ui.button(egui::RichText::new("Cancel").small()).clicked() {
+                            action = Some(OutboxAction::Cancel(item.id.clone()));
+                        }
+                    });
+                });
+            ui.add_space(6.0);
+        }
+        action
+    }
+
+    fn render_chat_line(
+        ui: &mut egui::Ui,
+        line: &ChatLine,
+        username: &str,
+        highlight: Option<&Matcher>,
+        markdown_safe: bool,
+    ) -> Option<ChatLineAction> {
+        let mut action = None;
         match line {
-            ChatLine::Chat { from, text, at } => {
-                let is_self = !self.username.is_empty() && from == &self.username;
+            ChatLine::Chat { from, text, at, .. } => {
+                let is_self = !username.is_empty() && from == username;
                 let fill = if is_self {
                     egui::Color32::from_rgb(23, 55, 83)
                 } else {
@@ -773,15 +1546,36 @@ impl ChatApp {
                     .stroke(egui::Stroke::new(1.0, border))
                     .rounding(egui::Rounding::same(8.0))
                     .inner_margin(egui::Margin::symmetric(10.0, 8.0))
+                    .show(ui, |ui| {
+                        let prefix = format_at_prefix(*at);
+                        let lead = egui::RichText::new(format!("{}{}", prefix, from))
+                            .strong()
+                            .color(egui::Color32::from_rgb(149, 198, 241));
+                        let color = ui.visuals().text_color();
+                        Self::render_markdown(ui, text, markdown_safe && !is_self, color, Some(lead), highlight);
+                    });
+            }
+            ChatLine::Dm { from, text, at, .. } => {
+                let is_self = !username.is_empty() && from == username;
+                let fill = if is_self {
+                    egui::Color32::from_rgb(45, 36, 72)
+                } else {
+                    egui::Color32::from_rgb(36, 31, 52)
+                };
+                egui::Frame::default()
+                    .fill(fill)
+                    .stroke(egui::Stroke::new(1.0, egui::Color32::from_rgb(112, 90, 160)))
+                    .rounding(egui::Rounding::same(8.0))
+                    .inner_margin(egui::Margin::symmetric(10.0, 8.0))
                     .show(ui, |ui| {
                         ui.horizontal_wrapped(|ui| {
                             let prefix = format_at_prefix(*at);
                             ui.label(
                                 egui::RichText::new(format!("{}{}", prefix, from))
                                     .strong()
-                                    .color(egui::Color32::from_rgb(149, 198, 241)),
+                                    .color(egui::Color32::from_rgb(201, 168, 240)),
                             );
-                            ui.label(text);
+                            ui.label(highlight_text(ui, egui::RichText::new(text), highlight));
                         });
                     });
             }
@@ -793,11 +1587,13 @@ impl ChatApp {
                     .inner_margin(egui::Margin::symmetric(10.0, 8.0))
                     .show(ui, |ui| {
                         let prefix = format_at_prefix(*at);
-                        ui.label(
+                        ui.label(highlight_text(
+                            ui,
                             egui::RichText::new(format!("{}{}", prefix, text))
                                 .italics()
                                 .color(egui::Color32::from_rgb(236, 214, 145)),
-                        );
+                            highlight,
+                        ));
                     });
             }
             ChatLine::Error(text) => {
@@ -807,7 +1603,12 @@ impl ChatApp {
                     .rounding(egui::Rounding::same(8.0))
                     .inner_margin(egui::Margin::symmetric(10.0, 8.0))
                     .show(ui, |ui| {
-                        ui.label(egui::RichText::new(format!("✗ {}", text)).color(egui::Color32::from_rgb(246, 171, 171)));
+                        ui.label(highlight_text(
+                            ui,
+                            egui::RichText::new(format!("✗ {}", text))
+                                .color(egui::Color32::from_rgb(246, 171, 171)),
+                            highlight,
+                        ));
                     });
             }
             ChatLine::Status { text, at } => {
@@ -818,7 +1619,12 @@ impl ChatApp {
                     .inner_margin(egui::Margin::symmetric(10.0, 8.0))
                     .show(ui, |ui| {
                         let prefix = format_at_prefix(*at);
-                        ui.label(egui::RichText::new(format!("{}{}", prefix, text)).color(egui::Color32::from_rgb(166, 204, 245)));
+                        ui.label(highlight_text(
+                            ui,
+                            egui::RichText::new(format!("{}{}", prefix, text))
+                                .color(egui::Color32::from_rgb(166, 204, 245)),
+                            highlight,
+                        ));
                     });
             }
             ChatLine::StatusCard { at, rows } => {
@@ -845,10 +1651,12 @@ impl ChatApp {
                                             .color(egui::Color32::from_gray(178)),
                                     ),
                                 );
-                                ui.label(
+                                ui.label(highlight_text(
+                                    ui,
                                     egui::RichText::new(value)
                                         .color(egui::Color32::from_rgb(214, 230, 248)),
-                                );
+                                    highlight,
+                                ));
                             });
                         }
                     });
@@ -884,16 +1692,36 @@ impl ChatApp {
                                     .inner_margin(egui::Margin::symmetric(8.0, 6.0))
                                     .show(ui, |ui| {
                                         ui.horizontal(|ui| {
-                                            ui.label(
-                                                egui::RichText::new(name)
-                                                    .strong()
-                                                    .color(egui::Color32::from_rgb(208, 228, 250)),
+                                            let is_self = name == username;
+                                            let name_label = ui.add(
+                                                egui::Label::new(highlight_text(
+                                                    ui,
+                                                    egui::RichText::new(name)
+                                                        .strong()
+                                                        .color(egui::Color32::from_rgb(208, 228, 250)),
+                                                    highlight,
+                                                ))
+                                                .sense(if is_self {
+                                                    egui::Sense::hover()
+                                                } else {
+                                                    egui::Sense::click()
+                                                }),
                                             );
+                                            if !is_self {
+                                                let name_label = name_label
+                                                    .on_hover_cursor(egui::CursorIcon::PointingHand)
+                                                    .on_hover_text("Privébericht sturen");
+                                                if name_label.clicked() {
+                                                    action = Some(ChatLineAction::OpenDm(name.clone()));
+                                                }
+                                            }
                                             ui.separator();
-                                            ui.label(
+                                            ui.label(highlight_text(
+                                                ui,
                                                 egui::RichText::new(ip)
                                                     .color(egui::Color32::from_gray(184)),
-                                            );
+                                                highlight,
+                                            ));
                                         });
                                         ui.label(
                                             egui::RichText::new(format!("id: {}", id))
@@ -906,11 +1734,53 @@ impl ChatApp {
                         }
                     });
             }
+            ChatLine::RoomsCard { at, rooms } => {
+                egui::Frame::default()
+                    .fill(egui::Color32::from_rgb(28, 43, 56))
+                    .stroke(egui::Stroke::new(1.0, egui::Color32::from_rgb(89, 126, 160)))
+                    .rounding(egui::Rounding::same(8.0))
+                    .inner_margin(egui::Margin::symmetric(10.0, 8.0))
+                    .show(ui, |ui| {
+                        let prefix = format_at_prefix(*at);
+                        ui.label(
+                            egui::RichText::new(format!("{}Kanalen ({})", prefix, rooms.len()))
+                                .strong()
+                                .color(egui::Color32::from_rgb(182, 216, 249)),
+                        );
+                        ui.add_space(4.0);
+                        for (name, users) in rooms {
+                            ui.horizontal(|ui| {
+                                let room_label = ui
+                                    .add(
+                                        egui::Label::new(highlight_text(
+                                            ui,
+                                            egui::RichText::new(format!("#{}", name))
+                                                .strong()
+                                                .color(egui::Color32::from_rgb(208, 228, 250)),
+                                            highlight,
+                                        ))
+                                        .sense(egui::Sense::click()),
+                                    )
+                                    .on_hover_cursor(egui::CursorIcon::PointingHand)
+                                    .on_hover_text("Kanaal openen");
+                                if room_label.clicked() {
+                                    action = Some(ChatLineAction::JoinRoom(name.clone()));
+                                }
+                                ui.label(
+                                    egui::RichText::new(format!("{} online", users))
+                                        .small()
+                                        .color(egui::Color32::from_gray(170)),
+                                );
+                            });
+                        }
+                    });
+            }
             ChatLine::Ai {
                 from,
                 prompt,
                 response,
                 stats,
+                thread,
                 at,
             } => {
                 egui::Frame::default()
@@ -920,16 +1790,294 @@ impl ChatApp {
                     .inner_margin(egui::Margin::symmetric(10.0, 8.0))
                     .show(ui, |ui| {
                         let prefix = format_at_prefix(*at);
-                        ui.label(
+                        ui.label(highlight_text(
+                            ui,
                             egui::RichText::new(format!("{}AI • {} vraagt: {}", prefix, from, prompt))
                                 .strong()
                                 .color(egui::Color32::from_rgb(130, 233, 198)),
-                        );
+                            highlight,
+                        ));
                         ui.add_space(2.0);
-                        ui.label(egui::RichText::new(response).color(egui::Color32::from_rgb(193, 235, 220)));
+                        Self::render_markdown(
+                            ui,
+                            response,
+                            markdown_safe && from != username,
+                            egui::Color32::from_rgb(193, 235, 220),
+                            None,
+                            highlight,
+                        );
                         ui.add_space(4.0);
-                        ui.label(egui::RichText::new(stats).small().color(egui::Color32::from_gray(164)));
+                        ui.horizontal(|ui| {
+                            ui.label(egui::RichText::new(stats).small().color(egui::Color32::from_gray(164)));
+                            if let Some(thread) = thread {
+                                if ui
+                                    .small_button("↪ Antwoorden")
+                                    .on_hover_text("Vervolgvraag stellen in dit gesprek")
+                                    .clicked()
+                                {
+                                    action = Some(ChatLineAction::ReplyAi(thread.clone()));
+                                }
+                            }
+                        });
+                    });
+            }
+            ChatLine::AiLive {
+                stream,
+                from,
+                prompt,
+                response,
+                started_ms,
+                at,
+                ..
+            } => {
+                egui::Frame::default()
+                    .fill(egui::Color32::from_rgb(23, 56, 50))
+                    .stroke(egui::Stroke::new(1.0, egui::Color32::from_rgb(73, 146, 128)))
+                    .rounding(egui::Rounding::same(8.0))
+                    .inner_margin(egui::Margin::symmetric(10.0, 8.0))
+                    .show(ui, |ui| {
+                        let prefix = format_at_prefix(*at);
+                        ui.label(highlight_text(
+                            ui,
+                            egui::RichText::new(format!("{}AI • {} vraagt: {}", prefix, from, prompt))
+                                .strong()
+                                .color(egui::Color32::from_rgb(130, 233, 198)),
+                            highlight,
+                        ));
+                        ui.add_space(2.0);
+                        if !response.is_empty() {
+                            Self::render_markdown(
+                                ui,
+                                response,
+                                markdown_safe && from != username,
+                                egui::Color32::from_rgb(193, 235, 220),
+                                None,
+                                highlight,
+                            );
+                            ui.add_space(4.0);
+                        }
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            let elapsed = now_unix_ms().saturating_sub(*started_ms) as f32 / 1000.0;
+                            let state = if response.is_empty() { "denkt na" } else { "schrijft" };
+                            ui.label(
+                                egui::RichText::new(format!("{} • {:.1}s", state, elapsed))
+                                    .small()
+                                    .color(egui::Color32::from_gray(164)),
+                            );
+                            if from == username {
+                                let cancel = ui
+                                    .add_enabled(stream.is_some(), egui::Button::new("Annuleren").small())
+                                    .on_disabled_hover_text("Kan pas na het eerste antwoorddeel");
+                                if let (true, Some(stream)) = (cancel.clicked(), stream) {
+                                    action = Some(ChatLineAction::CancelAi(stream.clone()));
+                                }
+                            }
+                        });
+                        ui.ctx().request_repaint_after(std::time::Duration::from_millis(100));
+                    });
+            }
+            ChatLine::Unknown { msg_type, payload, at } => {
+                egui::Frame::default()
+                    .fill(egui::Color32::from_rgb(32, 36, 43))
+                    .stroke(egui::Stroke::new(1.0, egui::Color32::from_rgb(78, 86, 99)))
+                    .rounding(egui::Rounding::same(8.0))
+                    .inner_margin(egui::Margin::symmetric(10.0, 8.0))
+                    .show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            let prefix = format_at_prefix(*at);
+                            ui.label(highlight_text(
+                                ui,
+                                egui::RichText::new(format!("{}{}", prefix, msg_type))
+                                    .strong()
+                                    .monospace()
+                                    .color(egui::Color32::from_gray(200)),
+                                highlight,
+                            ));
+                            ui.label(
+                                egui::RichText::new("onbekend berichttype")
+                                    .small()
+                                    .color(egui::Color32::from_gray(140)),
+                            );
+                            ui.menu_button(egui::RichText::new("⋯").small(), |ui| {
+                                for choice in [UnknownTypeAction::Hide, UnknownTypeAction::Warn] {
+                                    let label = format!("{} voor '{}'", choice.label(), msg_type);
+                                    if ui.button(label).clicked() {
+                                        action = Some(ChatLineAction::SetUnknownType(msg_type.clone(), choice));
+                                        ui.close_menu();
+                                    }
+                                }
+                            });
+                        });
+                        egui::CollapsingHeader::new(egui::RichText::new("payload").small())
+                            .id_salt(("unknown_payload", at, msg_type, payload.to_string()))
+                            .default_open(false)
+                            .show(ui, |ui| {
+                                Self::render_json_value(ui, None, payload);
+                            });
+                    });
+            }
+        }
+        action
+    }
+
+    /// Markdown text of a chat line or AI answer. `lead` (the sender) goes in
+    /// front of the first paragraph, like the plain label it replaces.
+    fn render_markdown(
+        ui: &mut egui::Ui,
+        text: &str,
+        safe: bool,
+        color: egui::Color32,
+        lead: Option<egui::RichText>,
+        highlight: Option<&Matcher>,
+    ) {
+        let blocks = ui
+            .ctx()
+            .memory_mut(|mem| mem.caches.cache::<MarkdownCache>().get((text, safe)));
+        let mut lead = lead;
+        if !matches!(blocks.first(), Some(Block::Paragraph(_))) {
+            if let Some(lead) = lead.take() {
+                ui.label(lead);
+            }
+        }
+        for block in &blocks {
+            match block {
+                Block::Paragraph(spans) => {
+                    ui.horizontal_wrapped(|ui| {
+                        if let Some(lead) = lead.take() {
+                            ui.label(lead);
+                        }
+                        Self::render_spans(ui, spans, color, None, highlight);
+                    });
+                }
+                Block::Heading { level, spans } => {
+                    let size = match level {
+                        1 => 20.0,
+                        2 => 17.0,
+                        _ => 15.0,
+                    };
+                    ui.horizontal_wrapped(|ui| {
+                        Self::render_spans(ui, spans, color, Some(size), highlight);
+                    });
+                }
+                Block::ListItem { depth, marker, spans } => {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.add_space(*depth as f32 * 14.0);
+                        ui.label(egui::RichText::new(format!("{} ", marker)).color(color));
+                        Self::render_spans(ui, spans, color, None, highlight);
+                    });
+                }
+                Block::Quote(spans) => {
+                    ui.horizontal_wrapped(|ui| {
+                        ui.label(egui::RichText::new("▎").color(egui::Color32::from_gray(110)));
+                        Self::render_spans(ui, spans, egui::Color32::from_gray(170), None, highlight);
+                    });
+                }
+                Block::Code { lang, code } => Self::render_code_block(ui, lang.as_deref(), code),
+                Block::Rule => {
+                    ui.separator();
+                }
+            }
+        }
+    }
+
+    fn render_spans(
+        ui: &mut egui::Ui,
+        spans: &[Span],
+        color: egui::Color32,
+        size: Option<f32>,
+        highlight: Option<&Matcher>,
+    ) {
+        ui.spacing_mut().item_spacing.x = 0.0;
+        for span in spans {
+            if span.text == "\n" {
+                ui.end_row();
+                continue;
+            }
+            let mut text = egui::RichText::new(&span.text).color(color);
+            if let Some(size) = size {
+                text = text.size(size).strong();
+            }
+            if span.strong {
+                text = text.strong();
+            }
+            if span.emphasis {
+                text = text.italics();
+            }
+            if span.strikethrough {
+                text = text.strikethrough();
+            }
+            if span.code {
+                text = text.code();
+            }
+            match &span.link {
+                Some(url) => {
+                    ui.hyperlink_to(text, url);
+                }
+                None => {
+                    ui.label(highlight_text(ui, text, highlight));
+                }
+            }
+        }
+    }
+
+    fn render_code_block(ui: &mut egui::Ui, lang: Option<&str>, code: &str) {
+        egui::Frame::default()
+            .fill(egui::Color32::from_rgb(16, 22, 30))
+            .stroke(egui::Stroke::new(1.0, egui::Color32::from_rgb(52, 64, 80)))
+            .rounding(egui::Rounding::same(6.0))
+            .inner_margin(egui::Margin::symmetric(8.0, 6.0))
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(
+                        egui::RichText::new(lang.unwrap_or("code"))
+                            .small()
+                            .color(egui::Color32::from_gray(150)),
+                    );
+                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                        if ui.small_button("Kopiëren").on_hover_text("Code naar klembord").clicked() {
+                            ui.ctx().copy_text(code.to_string());
+                        }
                     });
+                });
+                let mut job = egui::text::LayoutJob::default();
+                for (kind, text) in highlight_code(lang, code) {
+                    job.append(
+                        text,
+                        0.0,
+                        egui::TextFormat {
+                            font_id: egui::FontId::monospace(12.5),
+                            color: token_color(kind),
+                            ..Default::default()
+                        },
+                    );
+                }
+                job.wrap.max_width = ui.available_width();
+                ui.label(job);
+            });
+    }
+
+    fn apply_chat_line_action(&mut self, action: ChatLineAction) {
+        match action {
+            ChatLineAction::SetUnknownType(msg_type, choice) => {
+                self.unknown_types.insert(msg_type, choice);
+                self.persist_settings();
+            }
+            ChatLineAction::OpenDm(peer) => self.session_mut().open_dm(&peer),
+            ChatLineAction::JoinRoom(room) => {
+                self.sessions[self.active_session].join_room(room, &mut self.outbox)
+            }
+            ChatLineAction::LeaveRoom(room) => {
+                self.sessions[self.active_session].leave_room(room, &mut self.outbox)
+            }
+            ChatLineAction::CancelAi(id) => {
+                self.sessions[self.active_session].send_ws(Outgoing::AiCancel { id }, &mut self.outbox)
+            }
+            ChatLineAction::ReplyAi(thread) => {
+                self.session_mut().ai_reply_thread = Some(thread);
+                if !self.input.starts_with("/ai ") {
+                    self.input = "/ai ".to_string();
+                }
             }
         }
     }
@@ -938,8 +2086,57 @@ impl ChatApp {
 impl eframe::App for ChatApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.apply_modern_theme(ctx);
-        self.process_incoming();
-        self.maybe_send_auto_ping();
+        for session in &mut self.sessions {
+            session.process_incoming(&mut self.outbox, &self.unknown_types, &mut self.ai_usage);
+            session.maybe_send_auto_ping();
+            session.expire_ai_requests();
+        }
+        self.session_mut().unread = 0;
+        if self.sessions.iter().any(|s| s.reconnecting.is_some()) {
+            ctx.request_repaint_after(Duration::from_millis(250));
+        }
+        if std::mem::take(&mut self.startup_auto_connect) {
+            self.connect(ctx.clone());
+        }
+        let find_shortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::F);
+        if ctx.input_mut(|i| i.consume_shortcut(&find_shortcut)) {
+            self.search.open = true;
+            self.search.focus_requested = true;
+        }
+        let (matcher, search_error) = if self.search.open {
+            match Matcher::new(
+                &self.search.query,
+                self.search.use_regex,
+                self.search.case_sensitive,
+            ) {
+                Ok(matcher) => (matcher, None),
+                Err(err) => (None, Some(err)),
+            }
+        } else {
+            (None, None)
+        };
+        let search_matches = if self.search.is_filtering() {
+            self.sessions[self.active_session]
+                .messages
+                .iter()
+                .enumerate()
+                .filter(|(_, line)| {
+                    line.belongs_to(&self.sessions[self.active_session].active_conversation)
+                        && self.search.matches_line(
+                        matcher.as_ref(),
+                        line.kind(),
+                        line.sender(),
+                        &line.search_text(),
+                    )
+                })
+                .map(|(idx, _)| idx)
+                .collect::<Vec<_>>()
+        } else {
+            Vec::new()
+        };
+        if self.search.current >= search_matches.len() {
+            self.search.current = search_matches.len().saturating_sub(1);
+        }
 
         egui::TopBottomPanel::top("top_panel")
             .resizable(false)
@@ -970,11 +2167,45 @@ impl eframe::App for ChatApp {
                                                 .size(16.0)
                                                 .color(egui::Color32::from_rgb(192, 218, 247)),
                                         );
+                                        let current_profile = self.session().profile;
+                                        let mut selected_profile = current_profile;
+                                        egui::ComboBox::from_id_salt("profile_switcher")
+                                            .width(130.0)
+                                            .selected_text(&self.profiles[current_profile].name)
+                                            .show_ui(ui, |ui| {
+                                                for (idx, profile) in self.profiles.iter().enumerate() {
+                                                    ui.selectable_value(
+                                                        &mut selected_profile,
+                                                        idx,
+                                                        &profile.name,
+                                                    );
+                                                }
+                                            });
+                                        if selected_profile != current_profile {
+                                            self.switch_profile(selected_profile, ctx);
+                                        }
+                                        if ui
+                                            .small_button("⚙")
+                                            .on_hover_text("Profielen beheren")
+                                            .clicked()
+                                        {
+                                            self.profile_editor_open = !self.profile_editor_open;
+                                        }
+                                        if ui
+                                            .small_button("⤓")
+                                            .on_hover_text("Transcript exporteren")
+                                            .clicked()
+                                        {
+                                            self.export_dialog_open = !self.export_dialog_open;
+                                        }
 
                                         ui.with_layout(
                                             egui::Layout::right_to_left(egui::Align::Center),
                                             |ui| {
-                                                let (btn_text, btn_fill) = if self.connected {
+                                                let active = self.session().is_active();
+                                                let (btn_text, btn_fill) = if self.session().replaying {
+                                                    ("Stop replay", egui::Color32::from_rgb(180, 70, 70))
+                                                } else if active {
                                                     ("Disconnect", egui::Color32::from_rgb(180, 70, 70))
                                                 } else {
                                                     ("Connect", egui::Color32::from_rgb(45, 128, 86))
@@ -988,33 +2219,46 @@ impl eframe::App for ChatApp {
                                                 .rounding(egui::Rounding::same(7.0))
                                                 .stroke(egui::Stroke::NONE);
                                                 if ui.add(btn).clicked() {
-                                                    if self.connected {
-                                                        if let Some(tx) = self.ws_tx.take() {
-                                                            let _ = tx.send(WsCommand::Disconnect);
-                                                        }
-                                                        self.connected = false;
-                                                        self.pending_pings.clear();
-                                                        self.last_auto_ping_sent = None;
-                                                        self.messages.push(ChatLine::System {
-                                                            text: "Disconnect requested".to_string(),
-                                                            at: None,
-                                                        });
+                                                    if active {
+                                                        self.session_mut().disconnect();
                                                     } else {
                                                         self.connect(ctx.clone());
                                                     }
                                                 }
 
                                                 let (status_text, status_fill, status_stroke, status_dot) =
-                                                    if self.connected {
+                                                    if self.session().connected {
                                                         (
-                                                            "Online",
+                                                            "Online".to_string(),
                                                             egui::Color32::from_rgb(33, 66, 48),
                                                             egui::Color32::from_rgb(77, 138, 107),
                                                             egui::Color32::from_rgb(104, 219, 152),
                                                         )
+                                                    } else if self.session().replaying {
+                                                        (
+                                                            "Replay".to_string(),
+                                                            egui::Color32::from_rgb(32, 52, 78),
+                                                            egui::Color32::from_rgb(72, 114, 160),
+                                                            egui::Color32::from_rgb(111, 196, 255),
+                                                        )
+                                                    } else if let Some(state) = self.session().reconnecting {
+                                                        let remaining = state
+                                                            .retry_at
+                                                            .saturating_duration_since(Instant::now());
+                                                        (
+                                                            format!(
+                                                                "Reconnecting in {}s ({}/{})",
+                                                                remaining.as_secs_f32().ceil() as u64,
+                                                                state.attempt,
+                                                                state.max_attempts
+                                                            ),
+                                                            egui::Color32::from_rgb(72, 58, 28),
+                                                            egui::Color32::from_rgb(146, 118, 62),
+                                                            egui::Color32::from_rgb(240, 200, 112),
+                                                        )
                                                     } else {
                                                         (
-                                                            "Offline",
+                                                            "Offline".to_string(),
                                                             egui::Color32::from_rgb(73, 38, 42),
                                                             egui::Color32::from_rgb(138, 84, 90),
                                                             egui::Color32::from_rgb(240, 136, 136),
@@ -1036,7 +2280,7 @@ impl eframe::App for ChatApp {
                                                         );
                                                     });
 
-                                                if !self.username.is_empty() {
+                                                if !self.session().username.is_empty() {
                                                     egui::Frame::default()
                                                         .fill(egui::Color32::from_rgb(31, 44, 61))
                                                         .stroke(egui::Stroke::new(
@@ -1049,7 +2293,7 @@ impl eframe::App for ChatApp {
                                                             ui.label(
                                                                 egui::RichText::new(format!(
                                                                     "👤 {}",
-                                                                    self.username
+                                                                    self.session().username
                                                                 ))
                                                                 .small()
                                                                 .color(egui::Color32::from_rgb(
@@ -1069,24 +2313,118 @@ impl eframe::App for ChatApp {
                                             egui::Label::new(egui::RichText::new("Server").strong()),
                                         );
                                         let server_response = ui.add_sized(
-                                            [ui.available_width() - 2.0, 22.0],
-                                            egui::TextEdit::singleline(&mut self.server_url)
-                                                .vertical_align(egui::Align::Center)
+                                            [ui.available_width() - 126.0, 22.0],
+                                            egui::TextEdit::singleline(
+                                                &mut self.sessions[self.active_session].server_url,
+                                            )
+                                            .vertical_align(egui::Align::Center)
                                                 .hint_text("ws://127.0.0.1:3001"),
                                         );
                                         if server_response.lost_focus() && server_response.changed() {
                                             self.persist_settings();
                                         }
+                                        if ui
+                                            .checkbox(&mut self.auto_reconnect, "Auto-reconnect")
+                                            .changed()
+                                        {
+                                            self.persist_settings();
+                                        }
                                     });
                                 },
                             );
 
                             ui.add_space(gap);
-                            self.draw_latency_graph(ui, graph_size);
+                            Self::draw_latency_graph(self.session(), ui, graph_size);
                         });
                     });
         });
 
+        let mut tab_action = None;
+        egui::TopBottomPanel::top("tabs_panel")
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    let closable = self.sessions.len() > 1;
+                    for (idx, session) in self.sessions.iter().enumerate() {
+                        let dot = if session.connected {
+                            egui::Color32::from_rgb(104, 219, 152)
+                        } else if session.replaying {
+                            egui::Color32::from_rgb(111, 196, 255)
+                        } else if session.reconnecting.is_some() {
+                            egui::Color32::from_rgb(240, 200, 112)
+                        } else {
+                            egui::Color32::from_rgb(240, 136, 136)
+                        };
+                        let selected = idx == self.active_session;
+                        egui::Frame::default()
+                            .fill(if selected {
+                                egui::Color32::from_rgb(37, 56, 80)
+                            } else {
+                                egui::Color32::from_rgb(26, 35, 48)
+                            })
+                            .stroke(egui::Stroke::new(1.0, egui::Color32::from_rgb(60, 83, 112)))
+                            .rounding(egui::Rounding::same(7.0))
+                            .inner_margin(egui::Margin::symmetric(8.0, 2.0))
+                            .show(ui, |ui| {
+                                ui.horizontal(|ui| {
+                                    ui.label(egui::RichText::new("●").small().color(dot));
+                                    let mut title = self.profiles[session.profile].name.clone();
+                                    if !session.username.is_empty() {
+                                        title = format!("{} · {}", title, session.username);
+                                    }
+                                    let label = egui::RichText::new(title).color(if selected {
+                                        egui::Color32::WHITE
+                                    } else {
+                                        egui::Color32::from_gray(180)
+                                    });
+                                    if ui.selectable_label(selected, label).clicked() {
+                                        tab_action = Some(TabAction::Select(idx));
+                                    }
+                                    if session.unread > 0 {
+                                        ui.label(
+                                            egui::RichText::new(session.unread.to_string())
+                                                .small()
+                                                .strong()
+                                                .color(egui::Color32::from_rgb(240, 200, 112)),
+                                        );
+                                    }
+                                    if closable
+                                        && ui
+                                            .small_button("✕")
+                                            .on_hover_text("Tabblad sluiten")
+                                            .clicked()
+                                    {
+                                        tab_action = Some(TabAction::Close(idx));
+                                    }
+                                });
+                            });
+                    }
+                    ui.menu_button("+", |ui| {
+                        for (idx, profile) in self.profiles.iter().enumerate() {
+                            if ui.button(&profile.name).clicked() {
+                                tab_action = Some(TabAction::Open(idx));
+                                ui.close_menu();
+                            }
+                        }
+                    })
+                    .response
+                    .on_hover_text("Nieuw tabblad");
+                });
+            });
+        match tab_action {
+            Some(TabAction::Select(idx)) => {
+                self.sync_active_profile();
+                self.active_session = idx;
+            }
+            Some(TabAction::Close(idx)) => self.close_tab(idx),
+            Some(TabAction::Open(idx)) => self.open_tab(idx, ctx),
+            None => {}
+        }
+
+        self.render_profile_editor(ctx);
+        self.render_export_dialog(ctx);
+        self.render_replay_dialog(ctx);
+
         egui::TopBottomPanel::bottom("input_panel")
             .resizable(false)
             .show(ctx, |ui| {
@@ -1097,12 +2435,44 @@ impl eframe::App for ChatApp {
                     .outer_margin(egui::Margin::symmetric(6.0, 4.0))
                     .inner_margin(egui::Margin::symmetric(10.0, 8.0))
                     .show(ui, |ui| {
+                        // Editing the input into something else drops the follow-up
+                        if !self.input.starts_with("/ai") {
+                            self.session_mut().ai_reply_thread = None;
+                        }
+                        let session = self.session();
+                        let reply_to = session
+                            .ai_reply_thread
+                            .as_deref()
+                            .map(|thread| session.thread_root_prompt(thread).unwrap_or("AI-gesprek").to_string());
+                        if let Some(root) = reply_to {
+                            ui.horizontal(|ui| {
+                                ui.label(
+                                    egui::RichText::new(format!("↪ Vervolgvraag op: {}", root))
+                                        .small()
+                                        .color(egui::Color32::from_rgb(130, 233, 198)),
+                                );
+                                if ui
+                                    .small_button("✕")
+                                    .on_hover_text("Als nieuwe vraag versturen")
+                                    .clicked()
+                                {
+                                    self.session_mut().ai_reply_thread = None;
+                                }
+                            });
+                            ui.add_space(4.0);
+                        }
                         ui.horizontal(|ui| {
                             let response = ui.add_sized(
                                 [ui.available_width() - 84.0, 26.0],
                                 egui::TextEdit::singleline(&mut self.input)
                                     .vertical_align(egui::Align::Center)
-                                    .hint_text("Type a message or /command..."),
+                                    .hint_text(match &self.sessions[self.active_session].active_conversation {
+                                        Conversation::Dm(peer) => format!("Privébericht aan {}...", peer),
+                                        Conversation::Room(room) if room == DEFAULT_ROOM => {
+                                            "Type a message or /command...".to_string()
+                                        }
+                                        Conversation::Room(room) => format!("Bericht aan #{}...", room),
+                                    }),
                             );
 
                             if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
@@ -1136,13 +2506,31 @@ impl eframe::App for ChatApp {
                             let ai_chip = egui::Button::new(egui::RichText::new("/ai ").small())
                                 .rounding(egui::Rounding::same(999.0))
                                 .fill(egui::Color32::from_rgb(33, 61, 54));
-                            if ui.add(ai_chip).clicked() {
+                            if ui
+                                .add_enabled(self.session().capabilities.supports_ai(), ai_chip)
+                                .on_disabled_hover_text("AI staat uit op deze server (status.aiEnabled = false).")
+                                .clicked()
+                            {
                                 self.input = "/ai ".to_string();
                             }
+                            ui.separator();
+                            if ui
+                                .checkbox(
+                                    &mut self.markdown_safe_mode,
+                                    egui::RichText::new("Veilige Markdown").small(),
+                                )
+                                .on_hover_text(
+                                    "Links en koppen van anderen (en AI-antwoorden op hun vragen) als platte tekst tonen",
+                                )
+                                .changed()
+                            {
+                                self.persist_settings();
+                            }
                         });
                     });
         });
 
+        let mut line_action = None;
         egui::CentralPanel::default().show(ctx, |ui| {
             let rect = ui.max_rect();
             ui.painter()
@@ -1182,16 +2570,118 @@ impl eframe::App for ChatApp {
                                         .rounding(egui::Rounding::same(10.0))
                                         .inner_margin(egui::Margin::symmetric(8.0, 8.0))
                                         .show(ui, |ui| {
-                                            egui::ScrollArea::vertical()
+                                            if self.search.open {
+                                                self.render_search_bar(
+                                                    ui,
+                                                    search_matches.len(),
+                                                    search_error.as_deref(),
+                                                );
+                                            }
+                                            let filtering = self.search.is_filtering();
+                                            let scroll_to_current = self.search.scroll_to_current;
+                                            self.search.scroll_to_current = false;
+                                            let current_match = self.search.current;
+                                            let markdown_safe = self.markdown_safe_mode;
+                                            let session = &mut self.sessions[self.active_session];
+                                            line_action = line_action.take().or(
+                                                Self::render_conversation_sidebar(
+                                                    session,
+                                                    &mut self.room_input,
+                                                    ui,
+                                                ),
+                                            );
+                                            let mut chat_scroll = egui::ScrollArea::vertical()
                                                 .id_salt("chat_scroll")
                                                 .auto_shrink([false, false])
-                                                .stick_to_bottom(true)
+                                                .stick_to_bottom(true);
+                                            if let Some(offset) = session.history_scroll_target.take() {
+                                                chat_scroll = chat_scroll.vertical_scroll_offset(offset);
+                                            }
+                                            let chat_output = chat_scroll
                                                 .show(ui, |ui| {
-                                                    for line in &self.messages {
-                                                        self.render_chat_line(ui, line);
-                                                        ui.add_space(6.0);
+                                                    if filtering {
+                                                        for (pos, idx) in search_matches.iter().enumerate() {
+                                                            let scope = ui.scope(|ui| {
+                                                                Self::render_chat_line(
+                                                                    ui,
+                                                                    &session.messages[*idx],
+                                                                    &session.username,
+                                                                    matcher.as_ref(),
+                                                                    markdown_safe,
+                                                                )
+                                                            });
+                                                            line_action = line_action.take().or(scope.inner);
+                                                            let response = scope.response;
+                                                            if pos == current_match {
+                                                                ui.painter().rect_stroke(
+                                                                    response.rect.expand(2.0),
+                                                                    9.0,
+                                                                    egui::Stroke::new(
+                                                                        1.5,
+                                                                        egui::Color32::from_rgb(226, 186, 72),
+                                                                    ),
+                                                                );
+                                                                if scroll_to_current {
+                                                                    response.scroll_to_me(Some(egui::Align::Center));
+                                                                }
+                                                            }
+                                                            ui.add_space(6.0);
+                                                        }
+                                                    } else {
+                                                        let followups = session.thread_followups();
+                                                        let nested =
+                                                            followups.values().flatten().copied().collect::<HashSet<_>>();
+                                                        for (idx, line) in session.messages.iter().enumerate() {
+                                                            if nested.contains(&idx)
+                                                                || !line.belongs_to(&session.active_conversation)
+                                                            {
+                                                                continue;
+                                                            }
+                                                            line_action = line_action.take().or(Self::render_chat_line(
+                                                                ui,
+                                                                line,
+                                                                &session.username,
+                                                                None,
+                                                                markdown_safe,
+                                                            ));
+                                                            if let Some(replies) = followups.get(&idx) {
+                                                                let header = match replies.len() {
+                                                                    1 => "1 vervolgvraag".to_string(),
+                                                                    n => format!("{} vervolgvragen", n),
+                                                                };
+                                                                egui::CollapsingHeader::new(
+                                                                    egui::RichText::new(header).small(),
+                                                                )
+                                                                .id_salt(("ai_thread", line.thread()))
+                                                                .default_open(true)
+                                                                .show(ui, |ui| {
+                                                                    for reply in replies {
+                                                                        line_action = line_action.take().or(
+                                                                            Self::render_chat_line(
+                                                                                ui,
+                                                                                &session.messages[*reply],
+                                                                                &session.username,
+                                                                                None,
+                                                                                markdown_safe,
+                                                                            ),
+                                                                        );
+                                                                        ui.add_space(4.0);
+                                                                    }
+                                                                });
+                                                            }
+                                                            ui.add_space(6.0);
+                                                        }
                                                     }
-                                                    if self.messages.is_empty() {
+                                                    let action =
+                                                        Self::render_outbox_items(ui, &self.outbox, session);
+                                                    let identity = session.identity();
+                                                    if session.messages.is_empty()
+                                                        && self
+                                                            .outbox
+                                                            .pending_for(&session.server_url, &identity)
+                                                            .next()
+                                                            .is_none()
+                                                    {
                                                         ui.add_space(12.0);
                                                         ui.centered_and_justified(|ui| {
                                                             ui.label(
@@ -1203,7 +2693,16 @@ impl eframe::App for ChatApp {
                                                             );
                                                         });
                                                     }
+                                                    action
                                                 });
+                                            if let Some(action) = chat_output.inner {
+                                                session.apply_outbox_action(action, &mut self.outbox);
+                                            }
+                                            session.maybe_load_history_page(
+                                                ui.ctx(),
+                                                chat_output.content_size.y,
+                                                chat_output.state.offset.y,
+                                            );
                                         });
                                 },
                             );
@@ -1222,13 +2721,88 @@ impl eframe::App for ChatApp {
                                         .rounding(egui::Rounding::same(10.0))
                                         .inner_margin(egui::Margin::symmetric(8.0, 8.0))
                                         .show(ui, |ui| {
-                                            ui.label(
-                                                egui::RichText::new("Raw WebSocket")
-                                                    .strong()
-                                                    .color(egui::Color32::from_rgb(176, 209, 243)),
-                                            );
-                                            self.render_metrics_panel(ui);
-                                            self.render_security_panel(ui);
+                                            ui.horizontal(|ui| {
+                                                ui.label(
+                                                    egui::RichText::new("Raw WebSocket")
+                                                        .strong()
+                                                        .color(egui::Color32::from_rgb(176, 209, 243)),
+                                                );
+                                                ui.with_layout(
+                                                    egui::Layout::right_to_left(egui::Align::Center),
+                                                    |ui| {
+                                                        let session = &mut self.sessions[self.active_session];
+                                                        ui.menu_button("⤓", |ui| {
+                                                            let mut export = None;
+                                                            if ui.button("Frames als JSONL").clicked() {
+                                                                export = Some((CaptureFormat::Jsonl, false));
+                                                            }
+                                                            if ui.button("Frames als HAR").clicked() {
+                                                                export = Some((CaptureFormat::Har, false));
+                                                            }
+                                                            if session.is_recording() {
+                                                                ui.separator();
+                                                                if ui.button("Volledige opname als JSONL").clicked() {
+                                                                    export = Some((CaptureFormat::Jsonl, true));
+                                                                }
+                                                                if ui.button("Volledige opname als HAR").clicked() {
+                                                                    export = Some((CaptureFormat::Har, true));
+                                                                }
+                                                            }
+                                                            if let Some((format, full)) = export {
+                                                                ui.close_menu();
+                                                                Self::export_frames(session, format, full);
+                                                            }
+                                                        })
+                                                        .response
+                                                        .on_hover_text("Raw frames exporteren");
+                                                        if ui
+                                                            .small_button("▶ Replay")
+                                                            .on_hover_text("Opname afspelen in dit tabblad")
+                                                            .clicked()
+                                                        {
+                                                            self.replay_dialog_open = !self.replay_dialog_open;
+                                                        }
+                                                        let session = &mut self.sessions[self.active_session];
+                                                        let record_label = if session.is_recording() {
+                                                            egui::RichText::new(format!(
+                                                                "⏹ Opname ({})",
+                                                                session.recorded_frames()
+                                                            ))
+                                                            .color(egui::Color32::from_rgb(240, 136, 136))
+                                                        } else {
+                                                            egui::RichText::new("⏺ Opnemen")
+                                                        };
+                                                        if ui
+                                                            .add_enabled(
+                                                                !session.replaying,
+                                                                egui::Button::new(record_label.small()),
+                                                            )
+                                                            .on_hover_text("Alle frames van deze sessie naar een bestand schrijven")
+                                                            .clicked()
+                                                        {
+                                                            if session.is_recording() {
+                                                                session.stop_recording();
+                                                            } else {
+                                                                session.start_recording();
+                                                            }
+                                                        }
+                                                    },
+                                                );
+                                            });
+                                            let session = &mut self.sessions[self.active_session];
+                                            Self::render_metrics_panel(session, ui);
+                                            if Self::render_ai_usage_panel(
+                                                session,
+                                                &self.ai_usage,
+                                                &self.profiles,
+                                                &mut self.ai_daily_limit,
+                                                ui,
+                                            ) {
+                                                self.persist_settings();
+                                            }
+                                            let session = &mut self.sessions[self.active_session];
+                                            Self::render_security_panel(session, ui);
+                                            Self::render_raw_composer(session, &mut self.raw_composer, ui);
                                             ui.separator();
                                             ui.label(
                                                 egui::RichText::new("Frames")
@@ -1236,50 +2810,118 @@ impl eframe::App for ChatApp {
                                                     .strong()
                                                     .color(egui::Color32::from_rgb(164, 198, 233)),
                                             );
+                                            let payload_matcher =
+                                                Self::render_frame_filter(&mut self.frame_filter, session, ui);
                                             let available_h = ui.available_height();
                                             let frames_h =
                                                 (available_h * 0.65).clamp(220.0, 520.0);
                                             let inspector_h =
                                                 (available_h * 0.28).clamp(110.0, 260.0);
+                                            let mut clicked = None;
                                             egui::ScrollArea::vertical()
                                                 .id_salt("raw_scroll")
                                                 .max_height(frames_h)
                                                 .auto_shrink([false, false])
                                                 .stick_to_bottom(true)
                                                 .show(ui, |ui| {
-                                                    for (idx, raw) in self.raw_messages.iter().enumerate() {
-                                                        let selected = self.selected_raw_index == Some(idx);
-                                                        if ui
-                                                            .selectable_label(
-                                                                selected,
-                                                                egui::RichText::new(&raw.line)
-                                                                    .monospace()
-                                                                    .size(10.5)
-                                                                    .color(egui::Color32::from_rgb(
-                                                                        153, 181, 214,
-                                                                    )),
+                                                    for (idx, raw) in session.raw_messages.iter().enumerate() {
+                                                        if !self.frame_filter.matches_frame(
+                                                            payload_matcher.as_ref(),
+                                                            raw.direction(),
+                                                            raw.frame_type.as_deref(),
+                                                            raw.auto_ping,
+                                                            &raw.payload,
+                                                        ) {
+                                                            continue;
+                                                        }
+                                                        if let Some(matcher) = &matcher {
+                                                            if !matcher.is_match(&raw.line) {
+                                                                continue;
+                                                            }
+                                                        }
+                                                        let compared = session.compare_raw_index == Some(idx);
+                                                        let selected =
+                                                            session.selected_raw_index == Some(idx) || compared;
+                                                        let (label, color) = if compared {
+                                                            (
+                                                                format!("⇄ {}", raw.line),
+                                                                egui::Color32::from_rgb(196, 170, 235),
                                                             )
-                                                            .clicked()
-                                                        {
-                                                            self.selected_raw_index = Some(idx);
+                                                        } else if raw.hand_crafted {
+                                                            (
+                                                                format!("✎ {}", raw.line),
+                                                                egui::Color32::from_rgb(226, 186, 120),
+                                                            )
+                                                        } else {
+                                                            (raw.line.clone(), egui::Color32::from_rgb(153, 181, 214))
+                                                        };
+                                                        let text = highlight_text(
+                                                            ui,
+                                                            egui::RichText::new(label)
+                                                                .monospace()
+                                                                .size(10.5)
+                                                                .color(color),
+                                                            matcher.as_ref(),
+                                                        );
+                                                        let mut response = ui.selectable_label(selected, text);
+                                                        if raw.hand_crafted {
+                                                            response = response.on_hover_text("Handmatig verstuurd via Send raw");
+                                                        }
+                                                        if response.clicked() {
+                                                            let compare =
+                                                                ui.input(|i| i.modifiers.command || i.modifiers.shift);
+                                                            clicked = Some((idx, compare));
                                                         }
                                                     }
                                                 });
+                                            match clicked {
+                                                Some((idx, true)) => {
+                                                    session.compare_raw_index =
+                                                        (session.compare_raw_index != Some(idx)).then_some(idx);
+                                                }
+                                                Some((idx, false)) => session.selected_raw_index = Some(idx),
+                                                None => {}
+                                            }
                                             ui.add_space(4.0);
-                                            ui.label(
-                                                egui::RichText::new("JSON Inspector")
-                                                    .small()
-                                                    .strong()
-                                                    .color(egui::Color32::from_rgb(164, 198, 233)),
-                                            );
+                                            ui.horizontal(|ui| {
+                                                ui.label(
+                                                    egui::RichText::new("JSON Inspector")
+                                                        .small()
+                                                        .strong()
+                                                        .color(egui::Color32::from_rgb(164, 198, 233)),
+                                                );
+                                                if session.compare_raw_index.is_some() {
+                                                    if ui.small_button("✕ vergelijking").clicked() {
+                                                        session.compare_raw_index = None;
+                                                    }
+                                                } else {
+                                                    ui.label(
+                                                        egui::RichText::new("Ctrl/Cmd+klik: vergelijk met tweede frame")
+                                                            .small()
+                                                            .color(egui::Color32::from_gray(130)),
+                                                    );
+                                                }
+                                            });
                                             egui::ScrollArea::vertical()
                                                 .id_salt("json_inspector_scroll")
                                                 .max_height(inspector_h)
                                                 .auto_shrink([false, false])
                                                 .stick_to_bottom(false)
                                                 .show(ui, |ui| {
-                                                    if let Some(idx) = self.selected_raw_index {
-                                                        if let Some(raw) = self.raw_messages.get(idx) {
+                                                    let pair = session
+                                                        .selected_raw_index
+                                                        .zip(session.compare_raw_index)
+                                                        .filter(|(a, b)| a != b);
+                                                    if let Some((a, b)) = pair {
+                                                        let (old, new) = (a.min(b), a.max(b));
+                                                        if let (Some(old), Some(new)) =
+                                                            (session.raw_messages.get(old), session.raw_messages.get(new))
+                                                        {
+                                                            Self::render_frame_diff(ui, old, new);
+                                                        }
+                                                    } else if let Some(idx) = session.selected_raw_index {
+                                                        if let Some(raw) = session.raw_messages.get(idx) {
+                                                            Self::render_schema_badge(ui, raw);
                                                             match serde_json::from_str::<serde_json::Value>(
                                                                 &raw.payload,
                                                             ) {
@@ -1328,10 +2970,21 @@ impl eframe::App for ChatApp {
                     });
                 });
         });
+        if let Some(action) = line_action {
+            self.apply_chat_line_action(action);
+        }
     }
 }
 
 fn main() -> eframe::Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.iter().any(|arg| arg == "--mock-server") {
+        std::process::exit(mock_server::run(&args));
+    }
+    if args.iter().any(|arg| arg == "--headless") {
+        std::process::exit(cli::run(&args));
+    }
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([1180.0, 980.0])
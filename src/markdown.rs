@@ -0,0 +1,389 @@
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+
+/// A run of text with one style. `link` is only set outside safe mode.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub strong: bool,
+    pub emphasis: bool,
+    pub strikethrough: bool,
+    pub code: bool,
+    pub link: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Heading { level: u8, spans: Vec<Span> },
+    Paragraph(Vec<Span>),
+    /// `marker` is `•`, `3.` or empty for a second paragraph in the same item.
+    ListItem { depth: usize, marker: String, spans: Vec<Span> },
+    Quote(Vec<Span>),
+    Code { lang: Option<String>, code: String },
+    Rule,
+}
+
+#[derive(Default)]
+struct Builder {
+    safe: bool,
+    blocks: Vec<Block>,
+    spans: Vec<Span>,
+    strong: usize,
+    emphasis: usize,
+    strikethrough: usize,
+    link: Option<String>,
+    // Safe mode: index of the link's first span and its URL, shown after it
+    safe_link: Option<(usize, String)>,
+    heading: Option<u8>,
+    quote_depth: usize,
+    // Next number per open list; `None` for bullet lists
+    lists: Vec<Option<u64>>,
+    item_marker: Option<String>,
+    in_item: bool,
+    code: Option<(Option<String>, String)>,
+}
+
+impl Builder {
+    fn push_text(&mut self, text: &str, code: bool) {
+        if let Some((_, buf)) = &mut self.code {
+            buf.push_str(text);
+            return;
+        }
+        self.spans.push(Span {
+            text: text.to_string(),
+            strong: self.strong > 0,
+            emphasis: self.emphasis > 0,
+            strikethrough: self.strikethrough > 0,
+            code,
+            link: self.link.clone(),
+        });
+    }
+
+    /// Turns the collected spans into a block that fits where the parser is.
+    fn flush(&mut self) {
+        if self.spans.is_empty() {
+            return;
+        }
+        let spans = std::mem::take(&mut self.spans);
+        let block = if let Some(level) = self.heading.take() {
+            Block::Heading { level, spans }
+        } else if self.in_item {
+            Block::ListItem {
+                depth: self.lists.len().saturating_sub(1),
+                marker: self.item_marker.take().unwrap_or_default(),
+                spans,
+            }
+        } else if self.quote_depth > 0 {
+            Block::Quote(spans)
+        } else {
+            Block::Paragraph(spans)
+        };
+        self.blocks.push(block);
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Heading { level, .. } => {
+                self.flush();
+                // Safe mode keeps untrusted text at body size
+                if !self.safe {
+                    self.heading = Some(level as u8);
+                }
+            }
+            Tag::Paragraph | Tag::HtmlBlock => {}
+            Tag::BlockQuote(_) => {
+                self.flush();
+                self.quote_depth += 1;
+            }
+            Tag::CodeBlock(kind) => {
+                self.flush();
+                let lang = match kind {
+                    CodeBlockKind::Fenced(info) => info
+                        .split_whitespace()
+                        .next()
+                        .map(|lang| lang.to_lowercase()),
+                    CodeBlockKind::Indented => None,
+                };
+                self.code = Some((lang, String::new()));
+            }
+            Tag::List(start) => {
+                self.flush();
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.flush();
+                self.in_item = true;
+                let marker = match self.lists.last_mut() {
+                    Some(Some(next)) => {
+                        *next += 1;
+                        format!("{}.", *next - 1)
+                    }
+                    _ => "•".to_string(),
+                };
+                self.item_marker = Some(marker);
+            }
+            Tag::Emphasis => self.emphasis += 1,
+            Tag::Strong => self.strong += 1,
+            Tag::Strikethrough => self.strikethrough += 1,
+            Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. } => {
+                if self.safe {
+                    self.safe_link = Some((self.spans.len(), dest_url.to_string()));
+                } else {
+                    self.link = Some(dest_url.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Heading(_) | TagEnd::Paragraph | TagEnd::HtmlBlock => self.flush(),
+            TagEnd::BlockQuote(_) => {
+                self.flush();
+                self.quote_depth = self.quote_depth.saturating_sub(1);
+            }
+            TagEnd::CodeBlock => {
+                if let Some((lang, mut code)) = self.code.take() {
+                    if code.ends_with('\n') {
+                        code.pop();
+                    }
+                    self.blocks.push(Block::Code { lang, code });
+                }
+            }
+            TagEnd::List(_) => {
+                self.flush();
+                self.lists.pop();
+                // A nested list ends inside its parent item
+                self.in_item = !self.lists.is_empty();
+            }
+            TagEnd::Item => {
+                self.flush();
+                self.in_item = false;
+            }
+            TagEnd::Emphasis => self.emphasis = self.emphasis.saturating_sub(1),
+            TagEnd::Strong => self.strong = self.strong.saturating_sub(1),
+            TagEnd::Strikethrough => self.strikethrough = self.strikethrough.saturating_sub(1),
+            TagEnd::Link | TagEnd::Image => {
+                self.link = None;
+                if let Some((start, url)) = self.safe_link.take() {
+                    let label = self.spans[start.min(self.spans.len())..]
+                        .iter()
+                        .map(|span| span.text.as_str())
+                        .collect::<String>();
+                    if label != url {
+                        self.push_text(&format!(" ({})", url), false);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Parses chat or AI text into blocks for the chat pane. In safe mode links
+/// and images become plain text and headings body text, so an untrusted
+/// sender cannot hide a URL behind a label or shout in large type. Raw HTML
+/// is always shown as text.
+pub fn parse_markdown(text: &str, safe: bool) -> Vec<Block> {
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut builder = Builder {
+        safe,
+        ..Builder::default()
+    };
+    for event in Parser::new_ext(text, options) {
+        match event {
+            Event::Start(tag) => builder.start(tag),
+            Event::End(tag) => builder.end(tag),
+            Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) => {
+                builder.push_text(&text, false);
+            }
+            Event::Code(code) => builder.push_text(&code, true),
+            Event::SoftBreak => builder.push_text(" ", false),
+            Event::HardBreak => builder.push_text("\n", false),
+            Event::TaskListMarker(done) => builder.push_text(if done { "☑ " } else { "☐ " }, false),
+            Event::Rule => {
+                builder.flush();
+                builder.blocks.push(Block::Rule);
+            }
+            _ => {}
+        }
+    }
+    builder.flush();
+    builder.blocks
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Plain,
+    Keyword,
+    Literal,
+    Comment,
+    Number,
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "const", "else", "enum", "false", "fn", "for", "if", "impl", "in",
+    "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self",
+    "static", "struct", "trait", "true", "type", "use", "where", "while",
+];
+const JS_KEYWORDS: &[&str] = &[
+    "async", "await", "class", "const", "else", "export", "false", "for", "from", "function",
+    "if", "import", "let", "new", "null", "return", "this", "true", "typeof", "undefined", "var",
+    "while",
+];
+const PYTHON_KEYWORDS: &[&str] = &[
+    "and", "as", "async", "await", "class", "def", "elif", "else", "except", "False", "finally",
+    "for", "from", "if", "import", "in", "lambda", "None", "not", "or", "pass", "return", "True",
+    "try", "while", "with", "yield",
+];
+const SHELL_KEYWORDS: &[&str] = &[
+    "case", "do", "done", "echo", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+    "in", "local", "return", "then", "while",
+];
+const JSON_KEYWORDS: &[&str] = &["false", "null", "true"];
+
+/// Keywords and line-comment marker for a fenced code block's language.
+/// The marker is empty when the language has no line comments or is unknown.
+fn language(lang: Option<&str>) -> (&'static [&'static str], &'static str) {
+    match lang.unwrap_or_default() {
+        "rust" | "rs" => (RUST_KEYWORDS, "//"),
+        "js" | "javascript" | "ts" | "typescript" | "jsx" | "tsx" => (JS_KEYWORDS, "//"),
+        "py" | "python" => (PYTHON_KEYWORDS, "#"),
+        "sh" | "bash" | "shell" | "zsh" => (SHELL_KEYWORDS, "#"),
+        "json" => (JSON_KEYWORDS, ""),
+        _ => (&[], ""),
+    }
+}
+
+/// Splits `code` into highlighted tokens. A small lexer for keywords,
+/// strings, numbers and line comments; good enough for chat snippets.
+pub fn highlight_code<'a>(lang: Option<&str>, code: &'a str) -> Vec<(TokenKind, &'a str)> {
+    let (keywords, comment) = language(lang);
+    let mut tokens = Vec::new();
+    let mut plain_start = 0;
+    let mut idx = 0;
+    let bytes = code.as_bytes();
+    while idx < code.len() {
+        let rest = &code[idx..];
+        let c = rest.chars().next().unwrap_or_default();
+        let token = if !comment.is_empty() && rest.starts_with(comment) {
+            let len = rest.find('\n').unwrap_or(rest.len());
+            Some((TokenKind::Comment, len))
+        } else if c == '"' || c == '\'' {
+            string_len(rest, c).map(|len| (TokenKind::Literal, len))
+        } else if c.is_ascii_digit() && (idx == 0 || !is_word_byte(bytes[idx - 1])) {
+            let len = rest
+                .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '.' || ch == '_'))
+                .unwrap_or(rest.len());
+            Some((TokenKind::Number, len))
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest
+                .find(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+                .unwrap_or(rest.len());
+            let kind = if keywords.contains(&&rest[..len]) {
+                TokenKind::Keyword
+            } else {
+                TokenKind::Plain
+            };
+            Some((kind, len))
+        } else {
+            None
+        };
+        match token {
+            Some((TokenKind::Plain, len)) => idx += len,
+            Some((kind, len)) => {
+                if plain_start < idx {
+                    tokens.push((TokenKind::Plain, &code[plain_start..idx]));
+                }
+                tokens.push((kind, &code[idx..idx + len]));
+                idx += len;
+                plain_start = idx;
+            }
+            None => idx += c.len_utf8(),
+        }
+    }
+    if plain_start < code.len() {
+        tokens.push((TokenKind::Plain, &code[plain_start..]));
+    }
+    tokens
+}
+
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+/// Length of a quoted string on one line, including both quotes. `None`
+/// when it does not close, e.g. a Rust lifetime.
+fn string_len(rest: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (offset, c) in rest.char_indices().skip(1) {
+        match c {
+            '\n' => return None,
+            '\\' if !escaped => escaped = true,
+            c if c == quote && !escaped => return Some(offset + c.len_utf8()),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{highlight_code, parse_markdown, Block, TokenKind};
+
+    #[test]
+    fn parses_lists_code_and_links() {
+        let text = "# Stappen\n\n1. **Eerst** dit\n2. dan `cargo test`\n\n```rust\nfn main() {}\n```\n\nZie [docs](https://example.com).";
+        let blocks = parse_markdown(text, false);
+        assert!(matches!(&blocks[0], Block::Heading { level: 1, .. }));
+        let Block::ListItem { marker, spans, .. } = &blocks[1] else {
+            panic!("expected list item: {:?}", blocks[1]);
+        };
+        assert_eq!(marker, "1.");
+        assert!(spans[0].strong);
+        assert!(matches!(&blocks[2], Block::ListItem { marker, spans, .. } if marker == "2." && spans[1].code));
+        assert!(matches!(&blocks[3], Block::Code { lang: Some(lang), code } if lang == "rust" && code == "fn main() {}"));
+        let Block::Paragraph(spans) = &blocks[4] else {
+            panic!("expected paragraph");
+        };
+        assert_eq!(spans[1].link.as_deref(), Some("https://example.com"));
+
+        let safe = parse_markdown(text, true);
+        assert!(matches!(&safe[0], Block::Paragraph(_)));
+        let Block::Paragraph(spans) = &safe[4] else {
+            panic!("expected paragraph");
+        };
+        assert!(spans.iter().all(|span| span.link.is_none()));
+        let shown = spans.iter().map(|span| span.text.as_str()).collect::<String>();
+        assert_eq!(shown, "Zie docs (https://example.com).");
+    }
+
+    #[test]
+    fn highlights_keywords_strings_and_comments() {
+        let tokens = highlight_code(Some("rust"), "let s = \"hi\"; // groet\nx2 + 42");
+        let kinds = tokens
+            .iter()
+            .filter(|(kind, _)| *kind != TokenKind::Plain)
+            .map(|(kind, text)| (*kind, *text))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                (TokenKind::Keyword, "let"),
+                (TokenKind::Literal, "\"hi\""),
+                (TokenKind::Comment, "// groet"),
+                (TokenKind::Number, "42"),
+            ]
+        );
+        let joined = tokens.iter().map(|(_, text)| *text).collect::<String>();
+        assert_eq!(joined, "let s = \"hi\"; // groet\nx2 + 42");
+    }
+
+    #[test]
+    fn untagged_and_json_blocks_have_no_comments() {
+        for lang in [None, Some("text"), Some("json")] {
+            let tokens = highlight_code(lang, "{\"url\": \"x\"} https://example.com/pad");
+            assert!(tokens.iter().all(|(kind, _)| *kind != TokenKind::Comment), "{:?}", lang);
+        }
+    }
+}
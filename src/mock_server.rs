@@ -0,0 +1,770 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::capabilities::SERVER_VERSION_HEADER;
+use crate::protocol::{
+    now_unix_ms, AiTurn, Incoming, Outgoing, RoomInfo, UserInfo, DEFAULT_ROOM,
+};
+
+const DEFAULT_PORT: u16 = 3001;
+const DEFAULT_AI_DELAY_MS: u64 = 1500;
+
+const USAGE: &str = "Gebruik: cybox-chat-gui --mock-server [opties]
+
+Start een lokale nep-chatserver op 127.0.0.1 voor ontwikkelen en testen.
+
+Opties:
+  --port <poort>       Poort om op te luisteren (standaard 3001, 0 = willekeurig)
+  --ai-delay-ms <ms>   Vertraging voor nep-AI-antwoorden (standaard 1500)
+  --no-ai              AI uitzetten (status.aiEnabled = false)
+  --ai-stream          AI-antwoorden streamen als aiChunk/aiDone
+  -h, --help           Deze hulp tonen";
+
+#[derive(Debug, Clone)]
+pub struct MockConfig {
+    pub port: u16,
+    pub ai_delay: Duration,
+    pub ai_enabled: bool,
+    pub ai_stream: bool,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            port: DEFAULT_PORT,
+            ai_delay: Duration::from_millis(DEFAULT_AI_DELAY_MS),
+            ai_enabled: true,
+            ai_stream: false,
+        }
+    }
+}
+
+struct MockClient {
+    name: String,
+    ip: String,
+    // Joined rooms besides the default room, which every client is in
+    rooms: BTreeSet<String>,
+    tx: UnboundedSender<Message>,
+}
+
+struct MockState {
+    clients: HashMap<String, MockClient>,
+    started_at: Instant,
+    next_guest: u64,
+    connections_total: u64,
+    peak_users: usize,
+    messages_sent: u64,
+    // Streams asked to stop with `aiCancel`; the stream task checks per chunk
+    cancelled_streams: HashSet<String>,
+}
+
+type SharedState = Arc<Mutex<MockState>>;
+
+impl MockState {
+    fn send_to(&self, id: &str, incoming: &Incoming) {
+        if let Some(client) = self.clients.get(id) {
+            let _ = client.tx.send(encode(incoming));
+        }
+    }
+
+    fn broadcast(&self, incoming: &Incoming) {
+        let message = encode(incoming);
+        for client in self.clients.values() {
+            let _ = client.tx.send(message.clone());
+        }
+    }
+
+    fn send_to_room(&self, room: &str, incoming: &Incoming) {
+        let message = encode(incoming);
+        for client in self.clients.values().filter(|c| c.rooms.contains(room)) {
+            let _ = client.tx.send(message.clone());
+        }
+    }
+
+    fn error(&self, id: &str, message: String) {
+        self.send_to(
+            id,
+            &Incoming::Error {
+                message,
+                at: Some(now_unix_ms()),
+            },
+        );
+    }
+
+    fn rooms(&self) -> Vec<RoomInfo> {
+        let mut counts = BTreeMap::new();
+        for room in self.clients.values().flat_map(|c| &c.rooms) {
+            *counts.entry(room.clone()).or_insert(0) += 1;
+        }
+        let mut rooms = vec![RoomInfo {
+            name: DEFAULT_ROOM.to_string(),
+            users: self.clients.len(),
+        }];
+        rooms.extend(counts.into_iter().map(|(name, users)| RoomInfo { name, users }));
+        rooms
+    }
+
+    fn status(&self, ai_enabled: bool) -> Incoming {
+        let uptime = self.started_at.elapsed();
+        Incoming::Status {
+            version: mock_version(),
+            rust_version: None,
+            os: Some(std::env::consts::OS.to_string()),
+            cpu_cores: std::thread::available_parallelism().ok().map(|n| n.get()),
+            uptime_seconds: uptime.as_secs(),
+            user_count: self.clients.len(),
+            peak_users: Some(self.peak_users),
+            connections_total: Some(self.connections_total),
+            messages_sent: self.messages_sent,
+            messages_per_second: self.messages_sent as f64 / uptime.as_secs_f64().max(1.0),
+            memory_mb: 4.2,
+            ai_enabled: Some(ai_enabled),
+            ai_model: Some("mock-echo".to_string()),
+            at: Some(now_unix_ms()),
+        }
+    }
+}
+
+fn mock_version() -> String {
+    format!("mock-{}", env!("CARGO_PKG_VERSION"))
+}
+
+fn encode(incoming: &Incoming) -> Message {
+    Message::Text(serde_json::to_string(incoming).unwrap().into())
+}
+
+fn parse_args(args: &[String]) -> Result<Option<MockConfig>, String> {
+    let mut config = MockConfig::default();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = |flag: &str| {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| format!("{} verwacht een waarde", flag))
+        };
+        match arg.as_str() {
+            "--mock-server" => {}
+            "--port" => {
+                let raw = value(arg)?;
+                config.port = raw
+                    .parse()
+                    .map_err(|_| format!("Ongeldige poort: {}", raw))?;
+            }
+            "--ai-delay-ms" => {
+                let raw = value(arg)?;
+                let ms = raw
+                    .parse()
+                    .map_err(|_| format!("Ongeldige waarde voor --ai-delay-ms: {}", raw))?;
+                config.ai_delay = Duration::from_millis(ms);
+            }
+            "--no-ai" => config.ai_enabled = false,
+            "--ai-stream" => config.ai_stream = true,
+            "-h" | "--help" => return Ok(None),
+            other => return Err(format!("Onbekende optie: {}", other)),
+        }
+    }
+    Ok(Some(config))
+}
+
+/// Runs the mock server in the foreground until the process is stopped.
+pub fn run(args: &[String]) -> i32 {
+    let config = match parse_args(args) {
+        Ok(Some(config)) => config,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return 0;
+        }
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, USAGE);
+            return 2;
+        }
+    };
+
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(async move {
+        let listener = match TcpListener::bind(("127.0.0.1", config.port)).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                eprintln!("Kan poort {} niet openen: {}", config.port, err);
+                return 1;
+            }
+        };
+        if let Ok(addr) = listener.local_addr() {
+            println!("Mock server luistert op ws://{}", addr);
+        }
+        serve(listener, config).await;
+        0
+    })
+}
+
+/// Starts the mock server on its own thread and returns the bound address.
+/// Pass port 0 to get a free port, which is what tests do.
+#[cfg(test)]
+pub fn spawn(config: MockConfig) -> Result<SocketAddr, String> {
+    let listener = std::net::TcpListener::bind(("127.0.0.1", config.port))
+        .map_err(|err| format!("Failed to bind mock server: {}", err))?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|err| format!("Failed to bind mock server: {}", err))?;
+    let addr = listener
+        .local_addr()
+        .map_err(|err| format!("Failed to bind mock server: {}", err))?;
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let listener = TcpListener::from_std(listener).unwrap();
+            serve(listener, config).await;
+        });
+    });
+    Ok(addr)
+}
+
+pub async fn serve(listener: TcpListener, config: MockConfig) {
+    let state = Arc::new(Mutex::new(MockState {
+        clients: HashMap::new(),
+        started_at: Instant::now(),
+        next_guest: 1,
+        connections_total: 0,
+        peak_users: 0,
+        messages_sent: 0,
+        cancelled_streams: HashSet::new(),
+    }));
+    while let Ok((stream, addr)) = listener.accept().await {
+        tokio::spawn(handle_client(stream, addr, state.clone(), config.clone()));
+    }
+}
+
+async fn handle_client(stream: TcpStream, addr: SocketAddr, state: SharedState, config: MockConfig) {
+    // The callback signature, including its error type, is fixed by tungstenite.
+    #[allow(clippy::result_large_err)]
+    let add_version = |_: &Request, mut response: Response| {
+        if let Ok(value) = HeaderValue::from_str(&mock_version()) {
+            response.headers_mut().insert(SERVER_VERSION_HEADER, value);
+        }
+        Ok(response)
+    };
+    let Ok(ws_stream) = tokio_tungstenite::accept_hdr_async(stream, add_version).await else {
+        return;
+    };
+    let (mut write, mut read) = ws_stream.split();
+    let (tx, mut rx) = unbounded_channel::<Message>();
+    let id = uuid::Uuid::new_v4().to_string();
+
+    {
+        let mut state = state.lock().unwrap();
+        let name = format!("guest-{}", state.next_guest);
+        state.next_guest += 1;
+        state.connections_total += 1;
+        state.clients.insert(
+            id.clone(),
+            MockClient {
+                name: name.clone(),
+                ip: addr.ip().to_string(),
+                rooms: BTreeSet::new(),
+                tx,
+            },
+        );
+        state.peak_users = state.peak_users.max(state.clients.len());
+        state.send_to(
+            &id,
+            &Incoming::System {
+                text: format!("Welkom bij de mock server, {}", name),
+                at: Some(now_unix_ms()),
+            },
+        );
+        state.broadcast(&Incoming::System {
+            text: format!("{} joined", name),
+            at: Some(now_unix_ms()),
+        });
+    }
+
+    let writer = tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            if write.send(message).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    while let Some(frame) = read.next().await {
+        match frame {
+            Ok(Message::Text(text)) => handle_text(&text, &id, &state, &config),
+            Ok(Message::Close(_)) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+
+    writer.abort();
+    let mut state = state.lock().unwrap();
+    if let Some(client) = state.clients.remove(&id) {
+        state.broadcast(&Incoming::System {
+            text: format!("{} left", client.name),
+            at: Some(now_unix_ms()),
+        });
+    }
+}
+
+fn handle_text(text: &str, id: &str, shared: &SharedState, config: &MockConfig) {
+    let mut state = shared.lock().unwrap();
+    let outgoing = match serde_json::from_str::<Outgoing>(text) {
+        Ok(outgoing) => outgoing,
+        Err(err) => {
+            state.send_to(
+                id,
+                &Incoming::Error {
+                    message: format!("Invalid message: {}", err),
+                    at: Some(now_unix_ms()),
+                },
+            );
+            return;
+        }
+    };
+    let Some(name) = state.clients.get(id).map(|c| c.name.clone()) else {
+        return;
+    };
+
+    match outgoing {
+        Outgoing::Chat { text, room: None } => {
+            state.messages_sent += 1;
+            state.broadcast(&Incoming::Chat {
+                from: name,
+                text,
+                room: None,
+                at: Some(now_unix_ms()),
+            });
+        }
+        Outgoing::Chat {
+            text,
+            room: Some(room),
+        } => {
+            if !state.clients[id].rooms.contains(&room) {
+                state.error(id, format!("Not in room '{}'", room));
+                return;
+            }
+            state.messages_sent += 1;
+            state.send_to_room(
+                &room,
+                &Incoming::Chat {
+                    from: name,
+                    text,
+                    room: Some(room.clone()),
+                    at: Some(now_unix_ms()),
+                },
+            );
+        }
+        Outgoing::Join { room } => {
+            if room == DEFAULT_ROOM {
+                state.error(id, format!("Everyone is in '{}' already", room));
+                return;
+            }
+            if let Some(client) = state.clients.get_mut(id) {
+                client.rooms.insert(room.clone());
+            }
+            state.send_to(
+                id,
+                &Incoming::Joined {
+                    room: room.clone(),
+                    at: Some(now_unix_ms()),
+                },
+            );
+            state.send_to_room(
+                &room,
+                &Incoming::System {
+                    text: format!("{} joined #{}", name, room),
+                    at: Some(now_unix_ms()),
+                },
+            );
+        }
+        Outgoing::Leave { room } => {
+            let left = state
+                .clients
+                .get_mut(id)
+                .is_some_and(|client| client.rooms.remove(&room));
+            if !left {
+                state.error(id, format!("Not in room '{}'", room));
+                return;
+            }
+            state.send_to(
+                id,
+                &Incoming::Left {
+                    room: room.clone(),
+                    at: Some(now_unix_ms()),
+                },
+            );
+            state.send_to_room(
+                &room,
+                &Incoming::System {
+                    text: format!("{} left #{}", name, room),
+                    at: Some(now_unix_ms()),
+                },
+            );
+        }
+        Outgoing::ListRooms => {
+            let rooms = state.rooms();
+            state.send_to(
+                id,
+                &Incoming::Rooms {
+                    rooms,
+                    at: Some(now_unix_ms()),
+                },
+            );
+        }
+        Outgoing::SetName { name: new_name } => {
+            let taken = state
+                .clients
+                .iter()
+                .any(|(other, c)| other != id && c.name.eq_ignore_ascii_case(&new_name));
+            if taken {
+                state.send_to(
+                    id,
+                    &Incoming::Error {
+                        message: format!("Name '{}' is already taken", new_name),
+                        at: Some(now_unix_ms()),
+                    },
+                );
+                return;
+            }
+            if let Some(client) = state.clients.get_mut(id) {
+                client.name = new_name.clone();
+            }
+            state.send_to(
+                id,
+                &Incoming::AckName {
+                    name: new_name.clone(),
+                    at: Some(now_unix_ms()),
+                },
+            );
+            state.broadcast(&Incoming::System {
+                text: format!("{} is now known as {}", name, new_name),
+                at: Some(now_unix_ms()),
+            });
+        }
+        Outgoing::Status => {
+            let status = state.status(config.ai_enabled);
+            state.send_to(id, &status);
+        }
+        Outgoing::ListUsers => {
+            let mut users = state
+                .clients
+                .iter()
+                .map(|(id, c)| UserInfo {
+                    id: id.clone(),
+                    name: c.name.clone(),
+                    ip: c.ip.clone(),
+                })
+                .collect::<Vec<_>>();
+            users.sort_by(|a, b| a.name.cmp(&b.name));
+            state.send_to(
+                id,
+                &Incoming::ListUsers {
+                    users,
+                    at: Some(now_unix_ms()),
+                },
+            );
+        }
+        Outgoing::Ping { token } => {
+            state.send_to(
+                id,
+                &Incoming::Pong {
+                    token,
+                    at: Some(now_unix_ms()),
+                },
+            );
+        }
+        Outgoing::Dm { to, text } => {
+            let recipient = state
+                .clients
+                .iter()
+                .find(|(_, c)| c.name.eq_ignore_ascii_case(&to))
+                .map(|(other, c)| (other.clone(), c.name.clone()));
+            let Some((recipient_id, recipient_name)) = recipient else {
+                state.send_to(
+                    id,
+                    &Incoming::Error {
+                        message: format!("User '{}' not found", to),
+                        at: Some(now_unix_ms()),
+                    },
+                );
+                return;
+            };
+            state.messages_sent += 1;
+            let dm = Incoming::Dm {
+                from: name,
+                to: recipient_name,
+                text,
+                at: Some(now_unix_ms()),
+            };
+            state.send_to(&recipient_id, &dm);
+            if recipient_id != id {
+                state.send_to(id, &dm);
+            }
+        }
+        Outgoing::Ai { .. } if !config.ai_enabled => {
+            state.send_to(
+                id,
+                &Incoming::Error {
+                    message: "AI is disabled on this server".to_string(),
+                    at: Some(now_unix_ms()),
+                },
+            );
+        }
+        Outgoing::Ai {
+            prompt,
+            thread,
+            context,
+            request_id,
+        } if config.ai_stream => {
+            let answer = MockAnswer {
+                from: name,
+                response: mock_answer(&prompt, &context),
+                prompt,
+                thread,
+                request_id,
+            };
+            tokio::spawn(stream_ai(shared.clone(), answer, config.ai_delay));
+        }
+        Outgoing::AiCancel { id: stream } => {
+            state.cancelled_streams.insert(stream);
+        }
+        Outgoing::Ai {
+            prompt,
+            thread,
+            context,
+            request_id,
+        } => {
+            let delay = config.ai_delay;
+            let clients = state
+                .clients
+                .values()
+                .map(|c| c.tx.clone())
+                .collect::<Vec<_>>();
+            tokio::spawn(async move {
+                tokio::time::sleep(delay).await;
+                let tokens = prompt.split_whitespace().count() as u32 * 2 + 8;
+                let answer = encode(&Incoming::Ai {
+                    from: name,
+                    response: mock_answer(&prompt, &context),
+                    prompt,
+                    response_ms: delay.as_millis() as u64,
+                    tokens: Some(tokens),
+                    cost: Some(f64::from(tokens) * 0.000_002),
+                    thread,
+                    request_id,
+                    at: Some(now_unix_ms()),
+                });
+                for tx in clients {
+                    let _ = tx.send(answer.clone());
+                }
+            });
+        }
+    }
+}
+
+fn mock_answer(prompt: &str, context: &[AiTurn]) -> String {
+    if context.is_empty() {
+        format!("Mock-antwoord op: {}", prompt)
+    } else {
+        format!("Mock-antwoord op: {} (vervolg op {} eerdere vragen)", prompt, context.len())
+    }
+}
+
+struct MockAnswer {
+    from: String,
+    prompt: String,
+    response: String,
+    thread: Option<String>,
+    request_id: Option<String>,
+}
+
+/// Sends the mock answer word by word as `aiChunk`s spread over `delay`,
+/// then `aiDone`. Stops early after an `aiCancel` for this stream.
+async fn stream_ai(shared: SharedState, answer: MockAnswer, delay: Duration) {
+    let stream = uuid::Uuid::new_v4().to_string();
+    let words = answer
+        .response
+        .split_inclusive(' ')
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    let step = delay / words.len().max(1) as u32;
+    let started = Instant::now();
+    let mut sent = 0;
+    let mut cancelled = false;
+    for delta in words {
+        tokio::time::sleep(step).await;
+        let state = shared.lock().unwrap();
+        if state.cancelled_streams.contains(&stream) {
+            cancelled = true;
+            break;
+        }
+        sent += 1;
+        state.broadcast(&Incoming::AiChunk {
+            id: stream.clone(),
+            from: answer.from.clone(),
+            prompt: answer.prompt.clone(),
+            delta,
+            thread: answer.thread.clone(),
+            request_id: answer.request_id.clone(),
+            at: Some(now_unix_ms()),
+        });
+    }
+    let mut state = shared.lock().unwrap();
+    state.cancelled_streams.remove(&stream);
+    let tokens = sent * 2 + 8;
+    state.broadcast(&Incoming::AiDone {
+        id: stream,
+        response_ms: started.elapsed().as_millis() as u64,
+        tokens: Some(tokens),
+        cost: Some(f64::from(tokens) * 0.000_002),
+        cancelled,
+        at: Some(now_unix_ms()),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{channel, Receiver};
+    use std::time::Duration;
+
+    use eframe::egui;
+    use tokio::sync::mpsc::UnboundedSender;
+
+    use super::{spawn, MockConfig};
+    use crate::network::{start_connection, ReconnectPolicy, UiEvent, WsCommand};
+    use crate::protocol::{AiTurn, Incoming, Outgoing};
+    use crate::settings::TlsOptions;
+
+    fn connect(ai_stream: bool) -> (UnboundedSender<WsCommand>, Receiver<UiEvent>) {
+        let addr = spawn(MockConfig {
+            port: 0,
+            ai_delay: Duration::from_millis(if ai_stream { 300 } else { 10 }),
+            ai_enabled: true,
+            ai_stream,
+        })
+        .unwrap();
+        let (ui_tx, ui_rx) = channel();
+        let ws_tx = start_connection(
+            format!("ws://{}", addr),
+            TlsOptions::default(),
+            ReconnectPolicy::default(),
+            ui_tx,
+            egui::Context::default(),
+        );
+        (ws_tx, ui_rx)
+    }
+
+    /// Next server message, skipping join/leave notices.
+    fn next_incoming(ui_rx: &Receiver<UiEvent>) -> Incoming {
+        loop {
+            match ui_rx.recv_timeout(Duration::from_secs(5)).unwrap() {
+                UiEvent::Incoming(Incoming::System { .. }) => {}
+                UiEvent::Incoming(incoming) => return incoming,
+                UiEvent::Disconnected(reason) => panic!("disconnected: {:?}", reason),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn answers_set_name_ping_ai_dm_and_rooms() {
+        let (ws_tx, ui_rx) = connect(false);
+        let next_incoming = || next_incoming(&ui_rx);
+
+        let send = |outgoing| ws_tx.send(WsCommand::Send(outgoing)).unwrap();
+        send(Outgoing::SetName {
+            name: "Tester".to_string(),
+        });
+        assert!(matches!(next_incoming(), Incoming::AckName { name, .. } if name == "Tester"));
+        send(Outgoing::Ping {
+            token: Some("abc".to_string()),
+        });
+        assert!(matches!(next_incoming(), Incoming::Pong { token: Some(t), .. } if t == "abc"));
+        send(Outgoing::Ai {
+            prompt: "hoi".to_string(),
+            thread: None,
+            context: Vec::new(),
+            request_id: Some("req-1".to_string()),
+        });
+        assert!(matches!(
+            next_incoming(),
+            Incoming::Ai { from, request_id: Some(request), .. } if from == "Tester" && request == "req-1"
+        ));
+        send(Outgoing::Ai {
+            prompt: "en nu?".to_string(),
+            thread: Some("t1".to_string()),
+            context: vec![AiTurn {
+                prompt: "hoi".to_string(),
+                response: "Mock-antwoord op: hoi".to_string(),
+            }],
+            request_id: None,
+        });
+        assert!(matches!(
+            next_incoming(),
+            Incoming::Ai { thread: Some(thread), response, .. }
+                if thread == "t1" && response.ends_with("(vervolg op 1 eerdere vragen)")
+        ));
+        send(Outgoing::Dm {
+            to: "tester".to_string(),
+            text: "geheim".to_string(),
+        });
+        assert!(matches!(next_incoming(), Incoming::Dm { to, text, .. } if to == "Tester" && text == "geheim"));
+        send(Outgoing::Dm {
+            to: "Niemand".to_string(),
+            text: "hallo?".to_string(),
+        });
+        assert!(matches!(next_incoming(), Incoming::Error { .. }));
+        send(Outgoing::Join {
+            room: "dev".to_string(),
+        });
+        assert!(matches!(next_incoming(), Incoming::Joined { room, .. } if room == "dev"));
+        send(Outgoing::Chat {
+            text: "in dev".to_string(),
+            room: Some("dev".to_string()),
+        });
+        assert!(matches!(next_incoming(), Incoming::Chat { room: Some(room), .. } if room == "dev"));
+        send(Outgoing::ListRooms);
+        let Incoming::Rooms { rooms, .. } = next_incoming() else {
+            panic!("expected rooms");
+        };
+        let rooms = rooms.into_iter().map(|r| (r.name, r.users)).collect::<Vec<_>>();
+        assert_eq!(rooms, vec![("lobby".to_string(), 1), ("dev".to_string(), 1)]);
+        send(Outgoing::Leave {
+            room: "dev".to_string(),
+        });
+        assert!(matches!(next_incoming(), Incoming::Left { room, .. } if room == "dev"));
+        let _ = ws_tx.send(WsCommand::Disconnect);
+    }
+
+    #[test]
+    fn streams_ai_chunks_until_cancelled() {
+        let (ws_tx, ui_rx) = connect(true);
+        let send = |outgoing| ws_tx.send(WsCommand::Send(outgoing)).unwrap();
+        send(Outgoing::Ai {
+            prompt: "een twee drie vier vijf zes".to_string(),
+            thread: None,
+            context: Vec::new(),
+            request_id: None,
+        });
+        let Incoming::AiChunk { id, delta, .. } = next_incoming(&ui_rx) else {
+            panic!("expected aiChunk");
+        };
+        assert_eq!(delta, "Mock-antwoord ");
+        send(Outgoing::AiCancel { id: id.clone() });
+        let done = loop {
+            match next_incoming(&ui_rx) {
+                Incoming::AiChunk { .. } => {}
+                other => break other,
+            }
+        };
+        assert!(matches!(done, Incoming::AiDone { id: done_id, cancelled: true, .. } if done_id == id));
+        let _ = ws_tx.send(WsCommand::Disconnect);
+    }
+}
@@ -1,16 +1,23 @@
 use std::io::ErrorKind;
 use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
 
 use eframe::egui;
 use futures_util::{SinkExt, StreamExt};
-use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{Connector, MaybeTlsStream, WebSocketStream};
 
-use crate::protocol::{parse_incoming_text, Incoming, IncomingParse, Outgoing};
+use crate::protocol::{now_unix_ms, parse_incoming_text, Incoming, IncomingParse, Outgoing};
+use crate::settings::TlsOptions;
 
 #[derive(Debug, Clone)]
 pub enum WsCommand {
     Send(Outgoing),
+    /// Text frame sent exactly as given, for testing how the server handles
+    /// malformed or not-yet-supported messages.
+    SendRaw(String),
     Disconnect,
 }
 
@@ -23,12 +30,66 @@ pub struct SecurityInfo {
     pub headers: Vec<(String, String)>,
 }
 
+/// A frame as shown in the raw log (`">> ..."` sent, `"<< ..."` received),
+/// stamped on the network thread so exports get accurate timings.
+#[derive(Debug, Clone)]
+pub struct RawFrame {
+    pub line: String,
+    pub wall_ms: u64,
+    pub mono: Instant,
+    // Sent through the raw composer rather than built from an `Outgoing`
+    pub hand_crafted: bool,
+}
+
+impl RawFrame {
+    pub fn new(line: String) -> Self {
+        Self {
+            line,
+            wall_ms: now_unix_ms(),
+            mono: Instant::now(),
+            hand_crafted: false,
+        }
+    }
+
+    pub fn hand_crafted(line: String) -> Self {
+        Self {
+            hand_crafted: true,
+            ..Self::new(line)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    pub enabled: bool,
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_attempts: 10,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum UiEvent {
     Connected,
     Disconnected(Option<String>),
+    Reconnecting {
+        attempt: u32,
+        max_attempts: u32,
+        delay: Duration,
+        reason: Option<String>,
+    },
     Incoming(Incoming),
-    Raw(String),
+    Raw(RawFrame),
     Security(SecurityInfo),
     Warning(String),
     Error(String),
@@ -36,6 +97,8 @@ pub enum UiEvent {
 
 pub fn start_connection(
     url: String,
+    tls: TlsOptions,
+    reconnect: ReconnectPolicy,
     ui_tx: Sender<UiEvent>,
     ctx: egui::Context,
 ) -> UnboundedSender<WsCommand> {
@@ -44,94 +107,105 @@ pub fn start_connection(
     std::thread::spawn(move || {
         let rt = tokio::runtime::Runtime::new().unwrap();
         rt.block_on(async move {
-            match tokio_tungstenite::connect_async(&url).await {
-                Ok((ws_stream, response)) => {
-                    let transport = if url.to_ascii_lowercase().starts_with("wss://") {
-                        "wss".to_string()
-                    } else {
-                        "ws".to_string()
-                    };
-                    let headers = response
-                        .headers()
-                        .iter()
-                        .map(|(k, v)| {
-                            (
-                                k.as_str().to_string(),
-                                v.to_str().unwrap_or("<non-utf8>").to_string(),
-                            )
-                        })
-                        .collect::<Vec<_>>();
-                    let _ = ui_tx.send(UiEvent::Security(SecurityInfo {
-                        url: url.clone(),
-                        transport: transport.clone(),
-                        tls: transport == "wss",
-                        http_status: Some(response.status().as_u16()),
-                        headers,
-                    }));
-                    let _ = ui_tx.send(UiEvent::Connected);
+            let connector = match tls_connector(&tls) {
+                Ok(connector) => connector,
+                Err(err) => {
+                    let _ = ui_tx.send(UiEvent::Error(err));
+                    let _ = ui_tx.send(UiEvent::Disconnected(None));
                     ctx.request_repaint();
+                    return;
+                }
+            };
+            let mut attempt: u32 = 0;
+            let mut has_connected = false;
+            loop {
+                let end = match tokio_tungstenite::connect_async_tls_with_config(
+                    &url,
+                    None,
+                    false,
+                    connector.clone(),
+                )
+                .await
+                {
+                    Ok((ws_stream, response)) => {
+                        has_connected = true;
+                        attempt = 0;
+                        let transport = if url.to_ascii_lowercase().starts_with("wss://") {
+                            "wss".to_string()
+                        } else {
+                            "ws".to_string()
+                        };
+                        let headers = response
+                            .headers()
+                            .iter()
+                            .map(|(k, v)| {
+                                (
+                                    k.as_str().to_string(),
+                                    v.to_str().unwrap_or("<non-utf8>").to_string(),
+                                )
+                            })
+                            .collect::<Vec<_>>();
+                        let _ = ui_tx.send(UiEvent::Security(SecurityInfo {
+                            url: url.clone(),
+                            transport: transport.clone(),
+                            tls: transport == "wss",
+                            http_status: Some(response.status().as_u16()),
+                            headers,
+                        }));
+                        let _ = ui_tx.send(UiEvent::Connected);
+                        ctx.request_repaint();
 
-                    let (mut write, mut read) = ws_stream.split();
-                    let ui_tx_write = ui_tx.clone();
-                    let ctx_write = ctx.clone();
-                    let write_handle = tokio::spawn(async move {
-                        while let Some(cmd) = ws_rx.recv().await {
-                            match cmd {
-                                WsCommand::Send(msg) => {
-                                    let json = serde_json::to_string(&msg).unwrap();
-                                    let _ = ui_tx_write.send(UiEvent::Raw(format!(">> {}", json)));
-                                    ctx_write.request_repaint();
-                                    if write.send(Message::Text(json.into())).await.is_err() {
-                                        break;
-                                    }
-                                }
-                                WsCommand::Disconnect => {
-                                    let _ = write.send(Message::Close(None)).await;
-                                    break;
-                                }
-                            }
-                        }
-                    });
-
-                    let mut emitted_disconnect = false;
-                    while let Some(msg) = read.next().await {
-                        match msg {
-                            Ok(Message::Text(text)) => {
-                                let _ = ui_tx.send(UiEvent::Raw(format!("<< {}", text)));
-                                match parse_incoming_text(&text) {
-                                    IncomingParse::Message(incoming) => {
-                                        let _ = ui_tx.send(UiEvent::Incoming(incoming));
-                                        ctx.request_repaint();
-                                    }
-                                    IncomingParse::Warning(warning) => {
-                                        let _ = ui_tx.send(UiEvent::Warning(warning));
-                                        ctx.request_repaint();
-                                    }
-                                }
-                            }
-                            Ok(Message::Close(_)) => break,
-                            Err(err) => {
-                                emitted_disconnect = true;
-                                let _ = ui_tx.send(UiEvent::Disconnected(Some(
-                                    describe_stream_error(&err),
-                                )));
-                                ctx.request_repaint();
-                                break;
-                            }
-                            _ => {}
-                        }
+                        run_session(ws_stream, &mut ws_rx, &ui_tx, &ctx).await
                     }
+                    Err(err) if !has_connected => {
+                        let _ = ui_tx.send(UiEvent::Error(describe_connect_error(&err)));
+                        let _ = ui_tx.send(UiEvent::Disconnected(None));
+                        ctx.request_repaint();
+                        return;
+                    }
+                    Err(err) => SessionEnd::Lost(Some(describe_connect_error(&err))),
+                };
 
-                    write_handle.abort();
-                    if !emitted_disconnect {
+                let reason = match end {
+                    SessionEnd::Requested => {
                         let _ = ui_tx.send(UiEvent::Disconnected(None));
                         ctx.request_repaint();
+                        return;
                     }
-                }
-                Err(err) => {
-                    let _ = ui_tx.send(UiEvent::Error(describe_connect_error(&err)));
-                    let _ = ui_tx.send(UiEvent::Disconnected(None));
+                    SessionEnd::Lost(reason) => reason,
+                };
+
+                attempt += 1;
+                if !reconnect.enabled || attempt > reconnect.max_attempts {
+                    let _ = ui_tx.send(UiEvent::Disconnected(reason));
                     ctx.request_repaint();
+                    return;
+                }
+
+                let delay = backoff_delay(&reconnect, attempt, fastrand::f64());
+                let _ = ui_tx.send(UiEvent::Reconnecting {
+                    attempt,
+                    max_attempts: reconnect.max_attempts,
+                    delay,
+                    reason,
+                });
+                ctx.request_repaint();
+
+                // Wait out the backoff, but stay responsive to a Disconnect from the UI.
+                let sleep = tokio::time::sleep(delay);
+                tokio::pin!(sleep);
+                loop {
+                    tokio::select! {
+                        _ = &mut sleep => break,
+                        cmd = ws_rx.recv() => match cmd {
+                            Some(WsCommand::Send(_) | WsCommand::SendRaw(_)) => {}
+                            Some(WsCommand::Disconnect) | None => {
+                                let _ = ui_tx.send(UiEvent::Disconnected(None));
+                                ctx.request_repaint();
+                                return;
+                            }
+                        },
+                    }
                 }
             }
         });
@@ -140,6 +214,92 @@ pub fn start_connection(
     ws_tx
 }
 
+/// Only builds a custom connector when a profile relaxes verification;
+/// otherwise tokio-tungstenite picks its default TLS setup.
+fn tls_connector(tls: &TlsOptions) -> Result<Option<Connector>, String> {
+    if !tls.accept_invalid_certs && !tls.accept_invalid_hostnames {
+        return Ok(None);
+    }
+    native_tls::TlsConnector::builder()
+        .danger_accept_invalid_certs(tls.accept_invalid_certs)
+        .danger_accept_invalid_hostnames(tls.accept_invalid_hostnames)
+        .build()
+        .map(|connector| Some(Connector::NativeTls(connector)))
+        .map_err(|err| format!("Failed to set up TLS: {}", err))
+}
+
+enum SessionEnd {
+    Requested,
+    Lost(Option<String>),
+}
+
+async fn run_session(
+    ws_stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    ws_rx: &mut UnboundedReceiver<WsCommand>,
+    ui_tx: &Sender<UiEvent>,
+    ctx: &egui::Context,
+) -> SessionEnd {
+    let (mut write, mut read) = ws_stream.split();
+    loop {
+        tokio::select! {
+            cmd = ws_rx.recv() => match cmd {
+                Some(WsCommand::Send(msg)) => {
+                    let json = serde_json::to_string(&msg).unwrap();
+                    let _ = ui_tx.send(UiEvent::Raw(RawFrame::new(format!(">> {}", json))));
+                    ctx.request_repaint();
+                    if let Err(err) = write.send(Message::Text(json.into())).await {
+                        return SessionEnd::Lost(Some(describe_stream_error(&err)));
+                    }
+                }
+                Some(WsCommand::SendRaw(text)) => {
+                    let _ = ui_tx.send(UiEvent::Raw(RawFrame::hand_crafted(format!(">> {}", text))));
+                    ctx.request_repaint();
+                    if let Err(err) = write.send(Message::Text(text.into())).await {
+                        return SessionEnd::Lost(Some(describe_stream_error(&err)));
+                    }
+                }
+                Some(WsCommand::Disconnect) | None => {
+                    let _ = write.send(Message::Close(None)).await;
+                    return SessionEnd::Requested;
+                }
+            },
+            msg = read.next() => match msg {
+                Some(Ok(Message::Text(text))) => {
+                    emit_incoming_text(ui_tx, &text);
+                    ctx.request_repaint();
+                }
+                Some(Ok(Message::Close(_))) | None => return SessionEnd::Lost(None),
+                Some(Err(err)) => return SessionEnd::Lost(Some(describe_stream_error(&err))),
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// Emits the events for one received text frame: the raw line, then the
+/// parsed message or a warning. Shared with session replay.
+pub fn emit_incoming_text(ui_tx: &Sender<UiEvent>, text: &str) {
+    let _ = ui_tx.send(UiEvent::Raw(RawFrame::new(format!("<< {}", text))));
+    match parse_incoming_text(text) {
+        IncomingParse::Message(incoming) => {
+            let _ = ui_tx.send(UiEvent::Incoming(incoming));
+        }
+        IncomingParse::Warning(warning) => {
+            let _ = ui_tx.send(UiEvent::Warning(warning));
+        }
+    }
+}
+
+/// Exponential backoff for reconnect `attempt` (1-based), capped at `max_delay`.
+/// `jitter` is a value in `0.0..1.0` that spreads the delay by +/-20%.
+fn backoff_delay(policy: &ReconnectPolicy, attempt: u32, jitter: f64) -> Duration {
+    let exponent = attempt.saturating_sub(1).min(16);
+    let base_ms = policy.base_delay.as_millis() as f64 * f64::from(1u32 << exponent);
+    let capped_ms = base_ms.min(policy.max_delay.as_millis() as f64);
+    let jittered_ms = capped_ms * (0.8 + 0.4 * jitter.clamp(0.0, 1.0));
+    Duration::from_millis(jittered_ms.min(policy.max_delay.as_millis() as f64) as u64)
+}
+
 fn describe_connect_error(err: &tungstenite::Error) -> String {
     match err {
         tungstenite::Error::Io(io_err) => match io_err.kind() {
@@ -180,3 +340,248 @@ fn describe_stream_error(err: &tungstenite::Error) -> String {
         _ => format!("Connection closed with error: {}", err),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::sync::mpsc::{channel, Receiver};
+    use std::time::Duration;
+
+    use eframe::egui;
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc::UnboundedSender;
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::WebSocketStream;
+
+    use super::{backoff_delay, start_connection, ReconnectPolicy, UiEvent, WsCommand};
+    use crate::protocol::Outgoing;
+    use crate::settings::TlsOptions;
+
+    /// Local server that accepts `connections` clients one after another and
+    /// runs `script` against each. Returns the `ws://` URL to connect to.
+    fn spawn_server<F, Fut>(connections: usize, script: F) -> String
+    where
+        F: Fn(WebSocketStream<TcpStream>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async move {
+                let listener = TcpListener::from_std(listener).unwrap();
+                for _ in 0..connections {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                    script(ws).await;
+                }
+            });
+        });
+        format!("ws://{}", addr)
+    }
+
+    fn connect(url: String, reconnect: ReconnectPolicy) -> (UnboundedSender<WsCommand>, Receiver<UiEvent>) {
+        let (ui_tx, ui_rx) = channel();
+        let ws_tx = start_connection(
+            url,
+            TlsOptions::default(),
+            reconnect,
+            ui_tx,
+            egui::Context::default(),
+        );
+        (ws_tx, ui_rx)
+    }
+
+    fn no_reconnect() -> ReconnectPolicy {
+        ReconnectPolicy {
+            enabled: false,
+            ..ReconnectPolicy::default()
+        }
+    }
+
+    /// Short, comparable form of an event so tests can assert whole sequences.
+    fn describe(event: &UiEvent) -> String {
+        match event {
+            UiEvent::Connected => "connected".to_string(),
+            UiEvent::Disconnected(reason) => format!("disconnected {:?}", reason),
+            UiEvent::Reconnecting { attempt, .. } => format!("reconnecting {}", attempt),
+            UiEvent::Incoming(incoming) => format!("incoming {}", incoming.type_name()),
+            UiEvent::Raw(frame) => frame.line.clone(),
+            UiEvent::Security(info) => {
+                format!("security {} {}", info.transport, info.http_status.unwrap_or(0))
+            }
+            UiEvent::Warning(text) => format!("warning {}", text),
+            UiEvent::Error(text) => format!("error {}", text),
+        }
+    }
+
+    fn next_event(rx: &Receiver<UiEvent>) -> String {
+        describe(&rx.recv_timeout(Duration::from_secs(5)).expect("no event within 5s"))
+    }
+
+    /// Collects events up to and including the terminal `Disconnected`.
+    fn events_until_disconnected(rx: &Receiver<UiEvent>) -> Vec<String> {
+        let mut events = Vec::new();
+        loop {
+            let event = next_event(rx);
+            let done = event.starts_with("disconnected");
+            events.push(event);
+            if done {
+                return events;
+            }
+        }
+    }
+
+    async fn drain(ws: &mut WebSocketStream<TcpStream>) {
+        while let Some(Ok(_)) = ws.next().await {}
+    }
+
+    #[test]
+    fn full_session_emits_events_in_order() {
+        let url = spawn_server(1, |mut ws| async move {
+            if let Some(Ok(Message::Text(_))) = ws.next().await {
+                ws.send(Message::Text(r#"{"type":"chat","from":"Bas","text":"hoi"}"#.into()))
+                    .await
+                    .unwrap();
+            }
+            drain(&mut ws).await;
+        });
+        let (ws_tx, rx) = connect(url, no_reconnect());
+
+        assert_eq!(next_event(&rx), "security ws 101");
+        assert_eq!(next_event(&rx), "connected");
+        ws_tx
+            .send(WsCommand::Send(Outgoing::Chat {
+                text: "hoi".to_string(),
+                room: None,
+            }))
+            .unwrap();
+        assert_eq!(next_event(&rx), r#">> {"type":"chat","text":"hoi"}"#);
+        assert_eq!(next_event(&rx), r#"<< {"type":"chat","from":"Bas","text":"hoi"}"#);
+        assert_eq!(next_event(&rx), "incoming chat");
+        ws_tx.send(WsCommand::Disconnect).unwrap();
+        assert_eq!(events_until_disconnected(&rx), vec!["disconnected None"]);
+    }
+
+    #[test]
+    fn refused_connection_reports_error_without_retry() {
+        let port = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let (_ws_tx, rx) = connect(format!("ws://127.0.0.1:{}", port), ReconnectPolicy::default());
+
+        let events = events_until_disconnected(&rx);
+        assert_eq!(events.len(), 2, "{:?}", events);
+        assert!(events[0].starts_with("error Connection refused"), "{:?}", events);
+        assert_eq!(events[1], "disconnected None");
+    }
+
+    #[test]
+    fn server_close_frame_ends_session() {
+        let url = spawn_server(1, |mut ws| async move {
+            ws.close(None).await.unwrap();
+            drain(&mut ws).await;
+        });
+        let (_ws_tx, rx) = connect(url, no_reconnect());
+
+        assert_eq!(
+            events_until_disconnected(&rx),
+            vec!["security ws 101", "connected", "disconnected None"]
+        );
+    }
+
+    // The attempt counter starts over after a successful reconnect; once the
+    // server is gone the single allowed retry fails and the supervisor gives up.
+    #[test]
+    fn server_close_frame_triggers_reconnect() {
+        let url = spawn_server(2, |mut ws| async move {
+            ws.close(None).await.unwrap();
+            drain(&mut ws).await;
+        });
+        let policy = ReconnectPolicy {
+            enabled: true,
+            max_attempts: 1,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(10),
+        };
+        let (_ws_tx, rx) = connect(url, policy);
+
+        assert_eq!(
+            events_until_disconnected(&rx),
+            vec![
+                "security ws 101",
+                "connected",
+                "reconnecting 1",
+                "security ws 101",
+                "connected",
+                "reconnecting 1",
+                "disconnected Some(\"Connection refused. Controleer of de server draait en poort/open host klopt.\")",
+            ]
+        );
+    }
+
+    #[test]
+    fn mid_stream_reset_reports_reason() {
+        let url = spawn_server(1, |ws| async move {
+            // Zero linger turns the drop into a TCP RST instead of a clean FIN.
+            ws.get_ref().set_linger(Some(Duration::ZERO)).unwrap();
+            drop(ws);
+        });
+        let (_ws_tx, rx) = connect(url, no_reconnect());
+
+        assert_eq!(
+            events_until_disconnected(&rx),
+            vec![
+                "security ws 101",
+                "connected",
+                "disconnected Some(\"Connection reset by peer.\")",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_frames_become_warnings_and_unknown_types_pass_through() {
+        let url = spawn_server(1, |mut ws| async move {
+            for frame in ["not json", r#"{"foo":1}"#, r#"{"type":"newFeature"}"#] {
+                ws.send(Message::Text(frame.into())).await.unwrap();
+            }
+            ws.close(None).await.unwrap();
+            drain(&mut ws).await;
+        });
+        let (_ws_tx, rx) = connect(url, no_reconnect());
+
+        assert_eq!(
+            events_until_disconnected(&rx),
+            vec![
+                "security ws 101",
+                "connected",
+                "<< not json",
+                "warning Server sent invalid JSON.",
+                r#"<< {"foo":1}"#,
+                "warning Server sent JSON without a valid 'type' field.",
+                r#"<< {"type":"newFeature"}"#,
+                "incoming newFeature",
+                "disconnected None",
+            ]
+        );
+    }
+
+    #[test]
+    fn backoff_grows_exponentially_and_caps() {
+        let policy = ReconnectPolicy::default();
+        assert_eq!(backoff_delay(&policy, 1, 0.5), Duration::from_secs(1));
+        assert_eq!(backoff_delay(&policy, 3, 0.5), Duration::from_secs(4));
+        assert_eq!(backoff_delay(&policy, 12, 0.5), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn backoff_jitter_stays_within_bounds() {
+        let policy = ReconnectPolicy::default();
+        assert_eq!(backoff_delay(&policy, 2, 0.0), Duration::from_millis(1600));
+        assert_eq!(backoff_delay(&policy, 2, 1.0), Duration::from_millis(2400));
+        assert!(backoff_delay(&policy, 20, 1.0) <= policy.max_delay);
+    }
+}
@@ -0,0 +1,221 @@
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::{now_unix_ms, Outgoing};
+use crate::settings::config_dir;
+
+const OUTBOX_FILE: &str = "outbox.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxItem {
+    pub id: String,
+    pub server_url: String,
+    /// Profile identity that queued it, see `session::identity_key`. Empty
+    /// for items queued before identities; those go to any tab on the URL.
+    #[serde(default)]
+    pub identity: String,
+    pub outgoing: Outgoing,
+    pub queued_at: u64,
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+/// Messages typed while offline. Persisted so they survive a restart and
+/// flushed in order once the matching server is connected again.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Outbox {
+    pub items: Vec<OutboxItem>,
+    // File this outbox was loaded from; `None` keeps it in memory only
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+/// Only user-authored traffic is worth queueing; status/users/ping answers
+/// would be stale by the time they are delivered.
+pub fn is_queueable(outgoing: &Outgoing) -> bool {
+    matches!(
+        outgoing,
+        Outgoing::Chat { .. } | Outgoing::SetName { .. } | Outgoing::Ai { .. } | Outgoing::Dm { .. }
+    )
+}
+
+pub fn describe_outgoing(outgoing: &Outgoing) -> String {
+    match outgoing {
+        Outgoing::Chat { text, room: None } => text.clone(),
+        Outgoing::Chat {
+            text,
+            room: Some(room),
+        } => format!("#{} {}", room, text),
+        Outgoing::SetName { name } => format!("/name {}", name),
+        Outgoing::Status => "/status".to_string(),
+        Outgoing::ListUsers => "/users".to_string(),
+        Outgoing::Ping { token } => match token {
+            Some(token) => format!("/ping {}", token),
+            None => "/ping".to_string(),
+        },
+        Outgoing::Ai { prompt, .. } => format!("/ai {}", prompt),
+        Outgoing::Dm { to, text } if to.contains(' ') => format!("/msg \"{}\" {}", to, text),
+        Outgoing::Dm { to, text } => format!("/msg {} {}", to, text),
+        Outgoing::Join { room } => format!("/join {}", room),
+        Outgoing::Leave { room } => format!("/leave {}", room),
+        Outgoing::ListRooms => "/rooms".to_string(),
+        Outgoing::AiCancel { id } => format!("aiCancel {}", id),
+    }
+}
+
+fn outbox_path() -> PathBuf {
+    match config_dir() {
+        Some(dir) => dir.join(OUTBOX_FILE),
+        None => PathBuf::from(".cybox-chat-gui-outbox.json"),
+    }
+}
+
+impl Outbox {
+    pub fn load() -> (Self, Option<String>) {
+        Self::load_from(outbox_path())
+    }
+
+    /// Reads the outbox at `path`. A file that does not parse is moved aside
+    /// to `<file>.bad` first, so the next save cannot overwrite queued
+    /// messages; the returned error says where it went.
+    fn load_from(path: PathBuf) -> (Self, Option<String>) {
+        let mut error = None;
+        let items = match fs::read_to_string(&path) {
+            Err(_) => Vec::new(),
+            Ok(raw) => match serde_json::from_str::<Outbox>(&raw) {
+                Ok(outbox) => outbox.items,
+                Err(err) => {
+                    let backup = path.with_extension("json.bad");
+                    error = Some(match fs::rename(&path, &backup) {
+                        Ok(()) => format!(
+                            "Outbox file could not be read ({}); moved to {}",
+                            err,
+                            backup.display()
+                        ),
+                        Err(rename_err) => format!(
+                            "Outbox file could not be read ({}) nor moved aside: {}",
+                            err, rename_err
+                        ),
+                    });
+                    Vec::new()
+                }
+            },
+        };
+        let outbox = Self {
+            items,
+            path: Some(path),
+        };
+        (outbox, error)
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|err| format!("Failed to create outbox directory: {}", err))?;
+        }
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|err| format!("Failed to serialize outbox: {}", err))?;
+
+        fs::write(path, json).map_err(|err| format!("Failed to write outbox file: {}", err))
+    }
+
+    pub fn push(&mut self, server_url: &str, identity: &str, outgoing: Outgoing) {
+        self.items.push(OutboxItem {
+            id: uuid::Uuid::new_v4().to_string(),
+            server_url: server_url.to_string(),
+            identity: identity.to_string(),
+            outgoing,
+            queued_at: now_unix_ms(),
+            attempts: 0,
+            last_error: None,
+        });
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<OutboxItem> {
+        let idx = self.items.iter().position(|item| item.id == id)?;
+        Some(self.items.remove(idx))
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut OutboxItem> {
+        self.items.iter_mut().find(|item| item.id == id)
+    }
+
+    pub fn pending_for<'a>(
+        &'a self,
+        server_url: &'a str,
+        identity: &'a str,
+    ) -> impl Iterator<Item = &'a OutboxItem> {
+        self.items.iter().filter(move |item| {
+            item.server_url == server_url && (item.identity == identity || item.identity.is_empty())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::Outbox;
+    use crate::protocol::Outgoing;
+
+    fn chat(text: &str) -> Outgoing {
+        Outgoing::Chat {
+            text: text.to_string(),
+            room: None,
+        }
+    }
+
+    #[test]
+    fn pending_items_keep_order_per_server() {
+        let mut outbox = Outbox::default();
+        outbox.push("ws://a", "A", chat("een"));
+        outbox.push("ws://b", "A", chat("ander"));
+        outbox.push("ws://a", "B", chat("van B"));
+        outbox.push("ws://a", "A", chat("twee"));
+        outbox.items[3].identity.clear();
+        let texts = |outbox: &Outbox| {
+            outbox
+                .pending_for("ws://a", "A")
+                .map(|item| super::describe_outgoing(&item.outgoing))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(texts(&outbox), vec!["een", "twee"]);
+
+        let first = outbox.pending_for("ws://a", "A").next().unwrap().id.clone();
+        let item = outbox.get_mut(&first).unwrap();
+        item.attempts += 1;
+        item.last_error = Some("offline".to_string());
+        assert_eq!(outbox.items[0].attempts, 1);
+        assert!(outbox.remove(&first).is_some());
+        assert!(outbox.remove(&first).is_none());
+        assert_eq!(texts(&outbox), vec!["twee"]);
+    }
+
+    #[test]
+    fn unreadable_file_is_moved_aside_and_reported() {
+        let dir = std::env::temp_dir().join(format!("cybox-outbox-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("outbox.json");
+        fs::write(&path, "{ niet json").unwrap();
+
+        let (outbox, error) = Outbox::load_from(path.clone());
+        assert!(outbox.items.is_empty());
+        assert!(error.is_some());
+        assert_eq!(fs::read_to_string(dir.join("outbox.json.bad")).unwrap(), "{ niet json");
+
+        let (mut outbox, error) = Outbox::load_from(path.clone());
+        assert!(error.is_none());
+        outbox.push("ws://a", "A", chat("hoi"));
+        outbox.save().unwrap();
+        let (reloaded, _) = Outbox::load_from(path);
+        assert_eq!(reloaded.items.len(), 1);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -2,6 +2,12 @@ use chrono::TimeZone;
 use chrono_tz::Europe::Amsterdam;
 use serde::{Deserialize, Serialize};
 
+use crate::schema::{incoming_fields, validate_incoming_text};
+
+/// Room for chat without a `room` field, which is what servers without room
+/// support send.
+pub const DEFAULT_ROOM: &str = "lobby";
+
 pub fn format_uptime(seconds: u64) -> String {
     if seconds < 60 {
         format!("{} sec", seconds)
@@ -21,6 +27,13 @@ pub fn format_at_prefix(at: Option<u64>) -> String {
     }
 }
 
+pub fn now_unix_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
 fn format_unix_ms_nl_time(unix_ms: u64) -> String {
     let secs = (unix_ms / 1000) as i64;
     let nanos = ((unix_ms % 1000) * 1_000_000) as u32;
@@ -31,11 +44,16 @@ fn format_unix_ms_nl_time(unix_ms: u64) -> String {
     }
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "type", rename_all = "camelCase")]
 pub enum Outgoing {
     #[serde(rename = "chat")]
-    Chat { text: String },
+    Chat {
+        text: String,
+        /// `None` for the default room, so servers without rooms keep working.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        room: Option<String>,
+    },
     #[serde(rename = "setName")]
     SetName { name: String },
     #[serde(rename = "status")]
@@ -45,16 +63,98 @@ pub enum Outgoing {
     #[serde(rename = "ping")]
     Ping { token: Option<String> },
     #[serde(rename = "ai")]
-    Ai { prompt: String },
+    Ai {
+        prompt: String,
+        /// Follow-up in an AI thread; the answer echoes it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        thread: Option<String>,
+        /// Earlier turns of the thread, oldest first.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        context: Vec<AiTurn>,
+        /// Client-chosen id, echoed on the answer so it can be matched.
+        #[serde(rename = "requestId", default, skip_serializing_if = "Option::is_none")]
+        request_id: Option<String>,
+    },
+    #[serde(rename = "dm")]
+    Dm { to: String, text: String },
+    #[serde(rename = "join")]
+    Join { room: String },
+    #[serde(rename = "leave")]
+    Leave { room: String },
+    #[serde(rename = "listRooms")]
+    ListRooms,
+    /// Stops a streaming AI answer; `id` is the stream id from `aiChunk`.
+    #[serde(rename = "aiCancel")]
+    AiCancel { id: String },
+}
+
+impl Outgoing {
+    /// One example of every variant, used as templates by the raw composer.
+    /// Add new variants here too.
+    pub fn templates() -> Vec<Outgoing> {
+        vec![
+            Outgoing::Chat {
+                text: "Hallo!".to_string(),
+                room: None,
+            },
+            Outgoing::SetName {
+                name: "nieuwe-naam".to_string(),
+            },
+            Outgoing::Status,
+            Outgoing::ListUsers,
+            Outgoing::Ping {
+                token: Some("raw-test".to_string()),
+            },
+            Outgoing::Ai {
+                prompt: "Wat is de hoofdstad van Nederland?".to_string(),
+                thread: None,
+                context: Vec::new(),
+                request_id: Some("req-1".to_string()),
+            },
+            Outgoing::Dm {
+                to: "Bas".to_string(),
+                text: "Hoi, alleen voor jou".to_string(),
+            },
+            Outgoing::Join {
+                room: "dev".to_string(),
+            },
+            Outgoing::Leave {
+                room: "dev".to_string(),
+            },
+            Outgoing::ListRooms,
+            Outgoing::AiCancel {
+                id: "stream-1".to_string(),
+            },
+        ]
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Outgoing::Chat { .. } => "chat",
+            Outgoing::SetName { .. } => "setName",
+            Outgoing::Status => "status",
+            Outgoing::ListUsers => "listUsers",
+            Outgoing::Ping { .. } => "ping",
+            Outgoing::Ai { .. } => "ai",
+            Outgoing::Dm { .. } => "dm",
+            Outgoing::Join { .. } => "join",
+            Outgoing::Leave { .. } => "leave",
+            Outgoing::ListRooms => "listRooms",
+            Outgoing::AiCancel { .. } => "aiCancel",
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "type", rename_all = "camelCase")]
 pub enum Incoming {
     #[serde(rename = "chat")]
     Chat {
         from: String,
         text: String,
+        /// Missing means the default room.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        room: Option<String>,
         #[serde(default)]
         at: Option<u64>,
     },
@@ -126,18 +226,142 @@ pub enum Incoming {
         response_ms: u64,
         tokens: Option<u32>,
         cost: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        thread: Option<String>,
+        /// Echo of `Outgoing::Ai::request_id`.
+        #[serde(rename = "requestId", default, skip_serializing_if = "Option::is_none")]
+        request_id: Option<String>,
+        #[serde(default)]
+        at: Option<u64>,
+    },
+    /// Direct message; the server sends it to both `to` and `from`.
+    #[serde(rename = "dm")]
+    Dm {
+        from: String,
+        to: String,
+        text: String,
+        #[serde(default)]
+        at: Option<u64>,
+    },
+    #[serde(rename = "joined")]
+    Joined {
+        room: String,
+        #[serde(default)]
+        at: Option<u64>,
+    },
+    #[serde(rename = "left")]
+    Left {
+        room: String,
+        #[serde(default)]
+        at: Option<u64>,
+    },
+    #[serde(rename = "rooms")]
+    Rooms {
+        rooms: Vec<RoomInfo>,
+        #[serde(default)]
+        at: Option<u64>,
+    },
+    /// Part of a streaming AI answer. `id` names the stream; `aiDone` with
+    /// the same id ends it.
+    #[serde(rename = "aiChunk")]
+    AiChunk {
+        id: String,
+        from: String,
+        prompt: String,
+        delta: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        thread: Option<String>,
+        #[serde(rename = "requestId", default, skip_serializing_if = "Option::is_none")]
+        request_id: Option<String>,
+        #[serde(default)]
+        at: Option<u64>,
+    },
+    #[serde(rename = "aiDone")]
+    AiDone {
+        id: String,
+        #[serde(rename = "responseMs")]
+        response_ms: u64,
+        #[serde(default)]
+        tokens: Option<u32>,
+        #[serde(default)]
+        cost: Option<f64>,
+        /// Stopped early by `aiCancel`.
+        #[serde(default)]
+        cancelled: bool,
         #[serde(default)]
         at: Option<u64>,
     },
+    /// A `type` this client does not know, kept whole so newer server
+    /// features can still be shown. Never produced by serde itself.
+    #[serde(skip)]
+    Unknown {
+        msg_type: String,
+        payload: serde_json::Value,
+    },
 }
 
-#[derive(Debug, Deserialize, Clone)]
+impl Incoming {
+    pub fn type_name(&self) -> &str {
+        match self {
+            Incoming::Chat { .. } => "chat",
+            Incoming::System { .. } => "system",
+            Incoming::AckName { .. } => "ackName",
+            Incoming::Status { .. } => "status",
+            Incoming::ListUsers { .. } => "listUsers",
+            Incoming::Error { .. } => "error",
+            Incoming::Pong { .. } => "pong",
+            Incoming::Ai { .. } => "ai",
+            Incoming::Dm { .. } => "dm",
+            Incoming::Joined { .. } => "joined",
+            Incoming::Left { .. } => "left",
+            Incoming::Rooms { .. } => "rooms",
+            Incoming::AiChunk { .. } => "aiChunk",
+            Incoming::AiDone { .. } => "aiDone",
+            Incoming::Unknown { msg_type, .. } => msg_type,
+        }
+    }
+
+    pub fn at(&self) -> Option<u64> {
+        match self {
+            Incoming::Chat { at, .. }
+            | Incoming::System { at, .. }
+            | Incoming::AckName { at, .. }
+            | Incoming::Status { at, .. }
+            | Incoming::ListUsers { at, .. }
+            | Incoming::Error { at, .. }
+            | Incoming::Pong { at, .. }
+            | Incoming::Ai { at, .. }
+            | Incoming::Dm { at, .. }
+            | Incoming::Joined { at, .. }
+            | Incoming::Left { at, .. }
+            | Incoming::Rooms { at, .. }
+            | Incoming::AiChunk { at, .. }
+            | Incoming::AiDone { at, .. } => *at,
+            Incoming::Unknown { payload, .. } => payload.get("at").and_then(|at| at.as_u64()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UserInfo {
     pub id: String,
     pub name: String,
     pub ip: String,
 }
 
+/// One earlier question and answer sent along with an AI follow-up.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AiTurn {
+    pub prompt: String,
+    pub response: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RoomInfo {
+    pub name: String,
+    pub users: usize,
+}
+
 pub enum ParsedInput {
     Empty,
     Error(String),
@@ -147,6 +371,11 @@ pub enum ParsedInput {
     ListUsers,
     Ping(Option<String>),
     Ai(String),
+    Dm { to: String, text: String },
+    Join(String),
+    /// `None` leaves the room that is open in the chat pane.
+    Leave(Option<String>),
+    ListRooms,
 }
 
 pub fn parse_user_input(input: &str) -> ParsedInput {
@@ -204,10 +433,60 @@ pub fn parse_user_input(input: &str) -> ParsedInput {
                 ParsedInput::Ai(arg.to_string())
             }
         }
+        "/msg" => parse_dm_args(arg),
+        "/join" => match parse_room_name(arg) {
+            Ok(room) => ParsedInput::Join(room),
+            Err(err) => ParsedInput::Error(err),
+        },
+        "/leave" if arg.is_empty() => ParsedInput::Leave(None),
+        "/leave" => match parse_room_name(arg) {
+            Ok(room) => ParsedInput::Leave(Some(room)),
+            Err(err) => ParsedInput::Error(err),
+        },
+        "/rooms" => ParsedInput::ListRooms,
         _ => ParsedInput::Error(format!("Unknown command: {}", cmd)),
     }
 }
 
+/// Room names are 1-32 letters, digits, `-` or `_`; a leading `#` is dropped
+/// and names are lowercased.
+fn parse_room_name(arg: &str) -> Result<String, String> {
+    let room = arg.trim().trim_start_matches('#').to_lowercase();
+    if room.is_empty() {
+        return Err("Usage: /join <room>".to_string());
+    }
+    let valid = room.chars().count() <= 32
+        && room
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(room)
+    } else {
+        Err("Kanaalnaam mag alleen letters, cijfers, - en _ bevatten (max 32).".to_string())
+    }
+}
+
+/// `/msg <user> <text>`; names with spaces can be quoted: `/msg "Bas V" hoi`.
+fn parse_dm_args(arg: &str) -> ParsedInput {
+    let split = match arg.strip_prefix('"') {
+        Some(rest) => rest.split_once('"').map(|(to, text)| (to, text.trim())),
+        None => arg.split_once(' ').map(|(to, text)| (to, text.trim())),
+    };
+    match split {
+        Some((to, text)) if !to.trim().is_empty() && !text.is_empty() => {
+            if text.chars().count() > 500 {
+                ParsedInput::Error("Message is too long (max 500 characters).".to_string())
+            } else {
+                ParsedInput::Dm {
+                    to: to.trim().to_string(),
+                    text: text.to_string(),
+                }
+            }
+        }
+        _ => ParsedInput::Error("Usage: /msg <user> <text>".to_string()),
+    }
+}
+
 pub enum IncomingParse {
     Message(Incoming),
     Warning(String),
@@ -218,20 +497,37 @@ pub fn parse_incoming_text(text: &str) -> IncomingParse {
         return IncomingParse::Message(incoming);
     }
 
-    if let Ok(value) = serde_json::from_str::<serde_json::Value>(text) {
-        if let Some(msg_type) = value.get("type").and_then(|v| v.as_str()) {
-            IncomingParse::Warning(format!("Unknown server message type: {}", msg_type))
-        } else {
-            IncomingParse::Warning("Server sent JSON without a valid 'type' field.".to_string())
-        }
-    } else {
-        IncomingParse::Warning("Server sent invalid JSON.".to_string())
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(text) else {
+        return IncomingParse::Warning("Server sent invalid JSON.".to_string());
+    };
+    let Some(msg_type) = value.get("type").and_then(|v| v.as_str()) else {
+        return IncomingParse::Warning("Server sent JSON without a valid 'type' field.".to_string());
+    };
+    if incoming_fields(msg_type).is_none() {
+        return IncomingParse::Message(Incoming::Unknown {
+            msg_type: msg_type.to_string(),
+            payload: value,
+        });
     }
+    // A known type that serde rejected: say which fields break the contract.
+    let problems = validate_incoming_text(text)
+        .iter()
+        .filter(|issue| issue.is_error())
+        .map(|issue| issue.describe())
+        .collect::<Vec<_>>();
+    let detail = if problems.is_empty() {
+        "does not match the protocol".to_string()
+    } else {
+        problems.join(", ")
+    };
+    IncomingParse::Warning(format!("Invalid '{}' message from server: {}", msg_type, detail))
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{parse_incoming_text, parse_user_input, Incoming, IncomingParse, ParsedInput};
+    use super::{
+        parse_incoming_text, parse_user_input, Incoming, IncomingParse, Outgoing, ParsedInput,
+    };
 
     #[test]
     fn parse_name_command_validation() {
@@ -246,6 +542,32 @@ mod tests {
         assert!(matches!(parsed, ParsedInput::Error(_)));
     }
 
+    #[test]
+    fn parse_msg_command_with_plain_and_quoted_names() {
+        assert!(matches!(
+            parse_user_input("/msg Bas hoi daar"),
+            ParsedInput::Dm { to, text } if to == "Bas" && text == "hoi daar"
+        ));
+        assert!(matches!(
+            parse_user_input(r#"/msg "Bas V" hoi"#),
+            ParsedInput::Dm { to, text } if to == "Bas V" && text == "hoi"
+        ));
+        assert!(matches!(parse_user_input("/msg Bas"), ParsedInput::Error(_)));
+    }
+
+    #[test]
+    fn parse_room_commands() {
+        assert!(matches!(parse_user_input("/join #Dev"), ParsedInput::Join(room) if room == "dev"));
+        assert!(matches!(parse_user_input("/join bad room"), ParsedInput::Error(_)));
+        assert!(matches!(parse_user_input("/leave"), ParsedInput::Leave(None)));
+        assert!(matches!(parse_user_input("/rooms"), ParsedInput::ListRooms));
+        let json = r#"{"type":"chat","from":"Bas","text":"hoi","room":"dev"}"#;
+        assert!(matches!(
+            parse_incoming_text(json),
+            IncomingParse::Message(Incoming::Chat { room: Some(room), .. }) if room == "dev"
+        ));
+    }
+
     #[test]
     fn parse_incoming_chat_with_at() {
         let json = r#"{"type":"chat","from":"Bas","text":"Hallo","at":1733312410000}"#;
@@ -259,12 +581,36 @@ mod tests {
     }
 
     #[test]
-    fn parse_incoming_unknown_type_warning() {
-        let json = r#"{"type":"newFeature","foo":"bar"}"#;
+    fn outgoing_templates_match_type_names() {
+        for template in Outgoing::templates() {
+            let value = serde_json::to_value(&template).unwrap();
+            assert_eq!(value["type"], template.type_name());
+        }
+    }
+
+    #[test]
+    fn parse_incoming_unknown_type_keeps_payload() {
+        let json = r#"{"type":"newFeature","foo":"bar","at":1733312410000}"#;
         let parsed = parse_incoming_text(json);
         match parsed {
+            IncomingParse::Message(incoming @ Incoming::Unknown { .. }) => {
+                assert_eq!(incoming.type_name(), "newFeature");
+                assert_eq!(incoming.at(), Some(1733312410000));
+                let Incoming::Unknown { payload, .. } = incoming else {
+                    unreachable!()
+                };
+                assert_eq!(payload["foo"], "bar");
+            }
+            _ => panic!("expected unknown message"),
+        }
+    }
+
+    #[test]
+    fn parse_incoming_known_type_reports_missing_field() {
+        let json = r#"{"type":"ai","from":"Bas","prompt":"?","response":"!"}"#;
+        match parse_incoming_text(json) {
             IncomingParse::Warning(text) => {
-                assert!(text.contains("Unknown server message type"));
+                assert_eq!(text, "Invalid 'ai' message from server: missing field 'responseMs'");
             }
             _ => panic!("expected warning"),
         }
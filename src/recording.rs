@@ -0,0 +1,242 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+use crate::export::export_file_name;
+use crate::network::{emit_incoming_text, RawFrame, UiEvent, WsCommand};
+use crate::settings::config_dir;
+
+const RECORDINGS_DIR: &str = "recordings";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    In,
+    Out,
+}
+
+/// One line of a recording file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    pub direction: Direction,
+    /// Wall-clock time in unix ms.
+    pub timestamp: u64,
+    /// Monotonic time since the recording started; replay timing uses this.
+    pub elapsed_ms: u64,
+    pub payload: String,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hand_crafted: bool,
+}
+
+/// Splits a raw log line (`">> ..."` / `"<< ..."`) into direction and payload.
+pub fn split_raw_line(line: &str) -> Option<(Direction, &str)> {
+    if let Some(payload) = line.strip_prefix(">> ") {
+        Some((Direction::Out, payload))
+    } else {
+        line.strip_prefix("<< ").map(|payload| (Direction::In, payload))
+    }
+}
+
+pub fn recordings_dir() -> PathBuf {
+    config_dir()
+        .unwrap_or_else(|| PathBuf::from(".cybox-chat-gui"))
+        .join(RECORDINGS_DIR)
+}
+
+/// Recording files in the recordings directory, newest first.
+pub fn list_recordings() -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(recordings_dir()) else {
+        return Vec::new();
+    };
+    let mut files = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+        .collect::<Vec<_>>();
+    files.sort();
+    files.reverse();
+    files
+}
+
+/// Appends every frame of a session to a JSONL file. Unlike the raw pane,
+/// nothing is dropped, so the whole session can be replayed later.
+pub struct Recorder {
+    path: PathBuf,
+    file: File,
+    started: Instant,
+    pub frames: usize,
+}
+
+impl Recorder {
+    pub fn create(server_url: &str) -> Result<Self, String> {
+        let dir = recordings_dir();
+        fs::create_dir_all(&dir)
+            .map_err(|err| format!("Failed to create recordings directory: {}", err))?;
+        let path = dir.join(export_file_name(server_url, "jsonl"));
+        Self::create_at(path)
+    }
+
+    pub fn create_at(path: PathBuf) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|err| format!("Failed to open recording: {}", err))?;
+        Ok(Self {
+            path,
+            file,
+            started: Instant::now(),
+            frames: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Records a raw frame; lines without a direction prefix are ignored.
+    pub fn record(&mut self, frame: &RawFrame) -> Result<(), String> {
+        let Some((direction, payload)) = split_raw_line(&frame.line) else {
+            return Ok(());
+        };
+        let recorded = RecordedFrame {
+            direction,
+            timestamp: frame.wall_ms,
+            elapsed_ms: frame.mono.saturating_duration_since(self.started).as_millis() as u64,
+            payload: payload.to_string(),
+            hand_crafted: frame.hand_crafted,
+        };
+        let json = serde_json::to_string(&recorded)
+            .map_err(|err| format!("Failed to serialize frame: {}", err))?;
+        writeln!(self.file, "{}", json)
+            .map_err(|err| format!("Failed to write recording: {}", err))?;
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+pub fn load_recording(path: &Path) -> Result<Vec<RecordedFrame>, String> {
+    let raw =
+        fs::read_to_string(path).map_err(|err| format!("Failed to read recording: {}", err))?;
+    raw.lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(idx, line)| {
+            serde_json::from_str::<RecordedFrame>(line)
+                .map_err(|err| format!("Invalid recording on line {}: {}", idx + 1, err))
+        })
+        .collect()
+}
+
+/// Time to wait before a frame recorded `gap_ms` after the previous one.
+/// `speed` is a multiplier; 0 replays as fast as possible.
+fn replay_delay(gap_ms: u64, speed: f32) -> Duration {
+    if speed <= 0.0 {
+        return Duration::ZERO;
+    }
+    Duration::from_secs_f64(gap_ms as f64 / 1000.0 / f64::from(speed))
+}
+
+/// Feeds recorded frames into `ui_tx` as if they came from a live socket and
+/// ends with `Disconnected`. A `Disconnect` on the returned channel stops it.
+pub fn start_replay(
+    frames: Vec<RecordedFrame>,
+    speed: f32,
+    ui_tx: Sender<UiEvent>,
+    ctx: egui::Context,
+) -> UnboundedSender<WsCommand> {
+    let (ws_tx, mut ws_rx) = unbounded_channel::<WsCommand>();
+
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        rt.block_on(async move {
+            let mut prev_elapsed = frames.first().map(|f| f.elapsed_ms).unwrap_or(0);
+            for frame in frames {
+                let delay = replay_delay(frame.elapsed_ms.saturating_sub(prev_elapsed), speed);
+                prev_elapsed = frame.elapsed_ms;
+                if !delay.is_zero() {
+                    let sleep = tokio::time::sleep(delay);
+                    tokio::pin!(sleep);
+                    loop {
+                        tokio::select! {
+                            _ = &mut sleep => break,
+                            cmd = ws_rx.recv() => match cmd {
+                                Some(WsCommand::Send(_) | WsCommand::SendRaw(_)) => {}
+                                Some(WsCommand::Disconnect) | None => {
+                                    let _ = ui_tx.send(UiEvent::Disconnected(None));
+                                    ctx.request_repaint();
+                                    return;
+                                }
+                            },
+                        }
+                    }
+                }
+                match frame.direction {
+                    Direction::In => emit_incoming_text(&ui_tx, &frame.payload),
+                    Direction::Out => {
+                        let line = format!(">> {}", frame.payload);
+                        let raw = if frame.hand_crafted {
+                            RawFrame::hand_crafted(line)
+                        } else {
+                            RawFrame::new(line)
+                        };
+                        let _ = ui_tx.send(UiEvent::Raw(raw));
+                    }
+                }
+                ctx.request_repaint();
+            }
+            let _ = ui_tx.send(UiEvent::Disconnected(None));
+            ctx.request_repaint();
+        });
+    });
+
+    ws_tx
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    use eframe::egui;
+
+    use super::{load_recording, replay_delay, start_replay, Recorder};
+    use crate::network::{RawFrame, UiEvent};
+
+    #[test]
+    fn replay_delay_scales_with_speed() {
+        assert_eq!(replay_delay(1000, 1.0), Duration::from_secs(1));
+        assert_eq!(replay_delay(1000, 4.0), Duration::from_millis(250));
+        assert_eq!(replay_delay(1000, 0.0), Duration::ZERO);
+    }
+
+    #[test]
+    fn recorded_frames_replay_through_ui_events() {
+        let path = std::env::temp_dir().join(format!("recording-{}.jsonl", uuid::Uuid::new_v4()));
+        let mut recorder = Recorder::create_at(path.clone()).unwrap();
+        for line in [
+            r#">> {"type":"status"}"#,
+            r#"<< {"type":"chat","from":"Bas","text":"hoi"}"#,
+            "no prefix",
+        ] {
+            recorder.record(&RawFrame::new(line.to_string())).unwrap();
+        }
+        let frames = load_recording(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(frames.len(), 2);
+
+        let (ui_tx, ui_rx) = channel();
+        let _ws_tx = start_replay(frames, 0.0, ui_tx, egui::Context::default());
+        let events = ui_rx.iter().collect::<Vec<_>>();
+        assert!(matches!(&events[0], UiEvent::Raw(frame) if frame.line.starts_with(">> ")));
+        assert!(matches!(&events[1], UiEvent::Raw(frame) if frame.line.starts_with("<< ")));
+        assert!(matches!(&events[2], UiEvent::Incoming(_)));
+        assert!(matches!(&events[3], UiEvent::Disconnected(None)));
+    }
+}
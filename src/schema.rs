@@ -0,0 +1,309 @@
+use serde_json::Value;
+
+/// Expected JSON shape of a field in the client contract.
+#[derive(Debug, Clone, Copy)]
+pub enum FieldKind {
+    Str,
+    Uint,
+    Number,
+    Bool,
+    /// Array of objects that each follow the given fields.
+    ObjectArray(&'static [FieldSpec]),
+}
+
+impl FieldKind {
+    fn describe(self) -> &'static str {
+        match self {
+            FieldKind::Str => "a string",
+            FieldKind::Uint => "an unsigned integer",
+            FieldKind::Number => "a number",
+            FieldKind::Bool => "a boolean",
+            FieldKind::ObjectArray(_) => "an array of objects",
+        }
+    }
+
+    fn accepts(self, value: &Value) -> bool {
+        match self {
+            FieldKind::Str => value.is_string(),
+            FieldKind::Uint => value.is_u64(),
+            FieldKind::Number => value.is_number(),
+            FieldKind::Bool => value.is_boolean(),
+            FieldKind::ObjectArray(_) => value.is_array(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FieldSpec {
+    pub name: &'static str,
+    pub kind: FieldKind,
+    /// Optional fields may also be `null`.
+    pub required: bool,
+}
+
+const fn req(name: &'static str, kind: FieldKind) -> FieldSpec {
+    FieldSpec {
+        name,
+        kind,
+        required: true,
+    }
+}
+
+const fn opt(name: &'static str, kind: FieldKind) -> FieldSpec {
+    FieldSpec {
+        name,
+        kind,
+        required: false,
+    }
+}
+
+const AT: FieldSpec = opt("at", FieldKind::Uint);
+
+const ROOM_FIELDS: &[FieldSpec] = &[req("name", FieldKind::Str), req("users", FieldKind::Uint)];
+
+const USER_FIELDS: &[FieldSpec] = &[
+    req("id", FieldKind::Str),
+    req("name", FieldKind::Str),
+    req("ip", FieldKind::Str),
+];
+
+/// Fields per incoming message type, excluding `type` itself. Mirrors
+/// `protocol::Incoming`; a test checks the two stay in sync.
+pub const INCOMING_SCHEMA: &[(&str, &[FieldSpec])] = &[
+    (
+        "chat",
+        &[
+            req("from", FieldKind::Str),
+            req("text", FieldKind::Str),
+            opt("room", FieldKind::Str),
+            AT,
+        ],
+    ),
+    ("system", &[req("text", FieldKind::Str), AT]),
+    ("ackName", &[req("name", FieldKind::Str), AT]),
+    (
+        "status",
+        &[
+            req("version", FieldKind::Str),
+            opt("rustVersion", FieldKind::Str),
+            opt("os", FieldKind::Str),
+            opt("cpuCores", FieldKind::Uint),
+            req("uptimeSeconds", FieldKind::Uint),
+            req("userCount", FieldKind::Uint),
+            opt("peakUsers", FieldKind::Uint),
+            opt("connectionsTotal", FieldKind::Uint),
+            req("messagesSent", FieldKind::Uint),
+            req("messagesPerSecond", FieldKind::Number),
+            req("memoryMb", FieldKind::Number),
+            opt("aiEnabled", FieldKind::Bool),
+            opt("aiModel", FieldKind::Str),
+            AT,
+        ],
+    ),
+    ("listUsers", &[req("users", FieldKind::ObjectArray(USER_FIELDS)), AT]),
+    ("error", &[req("message", FieldKind::Str), AT]),
+    ("pong", &[opt("token", FieldKind::Str), AT]),
+    (
+        "ai",
+        &[
+            req("from", FieldKind::Str),
+            req("prompt", FieldKind::Str),
+            req("response", FieldKind::Str),
+            req("responseMs", FieldKind::Uint),
+            opt("tokens", FieldKind::Uint),
+            opt("cost", FieldKind::Number),
+            opt("thread", FieldKind::Str),
+            opt("requestId", FieldKind::Str),
+            AT,
+        ],
+    ),
+    (
+        "dm",
+        &[
+            req("from", FieldKind::Str),
+            req("to", FieldKind::Str),
+            req("text", FieldKind::Str),
+            AT,
+        ],
+    ),
+    ("joined", &[req("room", FieldKind::Str), AT]),
+    ("left", &[req("room", FieldKind::Str), AT]),
+    ("rooms", &[req("rooms", FieldKind::ObjectArray(ROOM_FIELDS)), AT]),
+    (
+        "aiChunk",
+        &[
+            req("id", FieldKind::Str),
+            req("from", FieldKind::Str),
+            req("prompt", FieldKind::Str),
+            req("delta", FieldKind::Str),
+            opt("thread", FieldKind::Str),
+            opt("requestId", FieldKind::Str),
+            AT,
+        ],
+    ),
+    (
+        "aiDone",
+        &[
+            req("id", FieldKind::Str),
+            req("responseMs", FieldKind::Uint),
+            opt("tokens", FieldKind::Uint),
+            opt("cost", FieldKind::Number),
+            opt("cancelled", FieldKind::Bool),
+            AT,
+        ],
+    ),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaIssue {
+    InvalidJson,
+    MissingType,
+    UnknownType(String),
+    MissingField(String),
+    WrongType { field: String, expected: &'static str },
+    ExtraField(String),
+}
+
+impl SchemaIssue {
+    /// Extra fields and unknown types are tolerated by the parser; everything
+    /// else makes the frame unusable.
+    pub fn is_error(&self) -> bool {
+        !matches!(self, SchemaIssue::ExtraField(_) | SchemaIssue::UnknownType(_))
+    }
+
+    /// Whether the frame breaks the contract at all. An unknown type is a
+    /// newer server feature, not a violation.
+    pub fn is_violation(&self) -> bool {
+        !matches!(self, SchemaIssue::UnknownType(_))
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            SchemaIssue::InvalidJson => "invalid JSON".to_string(),
+            SchemaIssue::MissingType => "missing or non-string 'type' field".to_string(),
+            SchemaIssue::UnknownType(msg_type) => format!("unknown message type '{}'", msg_type),
+            SchemaIssue::MissingField(field) => format!("missing field '{}'", field),
+            SchemaIssue::WrongType { field, expected } => {
+                format!("field '{}' should be {}", field, expected)
+            }
+            SchemaIssue::ExtraField(field) => format!("unexpected field '{}'", field),
+        }
+    }
+}
+
+pub fn incoming_fields(msg_type: &str) -> Option<&'static [FieldSpec]> {
+    INCOMING_SCHEMA
+        .iter()
+        .find(|(name, _)| *name == msg_type)
+        .map(|(_, fields)| *fields)
+}
+
+/// Checks one incoming text frame against the client contract.
+pub fn validate_incoming_text(text: &str) -> Vec<SchemaIssue> {
+    let Ok(value) = serde_json::from_str::<Value>(text) else {
+        return vec![SchemaIssue::InvalidJson];
+    };
+    let Some(msg_type) = value.get("type").and_then(|t| t.as_str()) else {
+        return vec![SchemaIssue::MissingType];
+    };
+    let Some(fields) = incoming_fields(msg_type) else {
+        return vec![SchemaIssue::UnknownType(msg_type.to_string())];
+    };
+    let mut issues = Vec::new();
+    validate_object(&mut issues, "", &value, fields, &["type"]);
+    issues
+}
+
+fn validate_object(
+    issues: &mut Vec<SchemaIssue>,
+    prefix: &str,
+    value: &Value,
+    fields: &[FieldSpec],
+    ignored: &[&str],
+) {
+    let Some(map) = value.as_object() else {
+        issues.push(SchemaIssue::WrongType {
+            field: prefix.trim_end_matches('.').to_string(),
+            expected: "an object",
+        });
+        return;
+    };
+    for spec in fields {
+        let path = format!("{}{}", prefix, spec.name);
+        match map.get(spec.name) {
+            None if spec.required => issues.push(SchemaIssue::MissingField(path)),
+            None => {}
+            Some(Value::Null) if !spec.required => {}
+            Some(field) if !spec.kind.accepts(field) => issues.push(SchemaIssue::WrongType {
+                field: path,
+                expected: spec.kind.describe(),
+            }),
+            Some(field) => {
+                if let (FieldKind::ObjectArray(item_fields), Some(items)) = (spec.kind, field.as_array()) {
+                    for (idx, item) in items.iter().enumerate() {
+                        let item_prefix = format!("{}[{}].", path, idx);
+                        validate_object(issues, &item_prefix, item, item_fields, &[]);
+                    }
+                }
+            }
+        }
+    }
+    for key in map.keys() {
+        if !ignored.contains(&key.as_str()) && !fields.iter().any(|spec| spec.name == key) {
+            issues.push(SchemaIssue::ExtraField(format!("{}{}", prefix, key)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Map, Value};
+
+    use super::{validate_incoming_text, FieldKind, SchemaIssue, INCOMING_SCHEMA};
+    use crate::protocol::Incoming;
+
+    #[test]
+    fn schema_matches_incoming_enum() {
+        for (msg_type, fields) in INCOMING_SCHEMA {
+            let mut map = Map::new();
+            map.insert("type".to_string(), json!(msg_type));
+            for spec in fields.iter().filter(|spec| spec.required) {
+                let sample = match spec.kind {
+                    FieldKind::Str => json!("x"),
+                    FieldKind::Uint => json!(1),
+                    FieldKind::Number => json!(1.5),
+                    FieldKind::Bool => json!(true),
+                    FieldKind::ObjectArray(_) => json!([]),
+                };
+                map.insert(spec.name.to_string(), sample);
+            }
+            let text = Value::Object(map).to_string();
+            assert!(serde_json::from_str::<Incoming>(&text).is_ok(), "{}", text);
+            assert!(validate_incoming_text(&text).is_empty(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn reports_missing_wrong_and_extra_fields() {
+        let text = r#"{"type":"ai","from":"Bas","prompt":"?","response":"!","tokens":"12","mood":"ok"}"#;
+        assert_eq!(
+            validate_incoming_text(text),
+            vec![
+                SchemaIssue::MissingField("responseMs".to_string()),
+                SchemaIssue::WrongType {
+                    field: "tokens".to_string(),
+                    expected: "an unsigned integer",
+                },
+                SchemaIssue::ExtraField("mood".to_string()),
+            ]
+        );
+        let unknown = validate_incoming_text(r#"{"type":"poll","question":"?"}"#);
+        assert_eq!(unknown, vec![SchemaIssue::UnknownType("poll".to_string())]);
+        assert!(!unknown[0].is_error() && !unknown[0].is_violation());
+        let users = r#"{"type":"listUsers","users":[{"id":"1","name":"Bas"}]}"#;
+        assert_eq!(
+            validate_incoming_text(users),
+            vec![SchemaIssue::MissingField("users[0].ip".to_string())]
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+
+use crate::recording::Direction;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Chat,
+    System,
+    Error,
+    Ai,
+}
+
+impl LineKind {
+    pub const ALL: [LineKind; 4] = [LineKind::Chat, LineKind::System, LineKind::Error, LineKind::Ai];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LineKind::Chat => "chat",
+            LineKind::System => "system",
+            LineKind::Error => "error",
+            LineKind::Ai => "ai",
+        }
+    }
+}
+
+/// Compiled search query. Plain substrings are escaped into a regex so both
+/// modes share the same matching and highlighting code.
+pub struct Matcher {
+    regex: Regex,
+}
+
+impl Matcher {
+    /// Returns `Ok(None)` for an empty query.
+    pub fn new(query: &str, use_regex: bool, case_sensitive: bool) -> Result<Option<Self>, String> {
+        if query.is_empty() {
+            return Ok(None);
+        }
+        let pattern = if use_regex {
+            query.to_string()
+        } else {
+            regex::escape(query)
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!case_sensitive)
+            .build()
+            .map(|regex| Some(Self { regex }))
+            .map_err(|err| format!("Ongeldige regex: {}", err))
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        self.find_ranges(haystack).next().is_some()
+    }
+
+    pub fn find_ranges<'a>(&'a self, haystack: &'a str) -> impl Iterator<Item = Range<usize>> + 'a {
+        self.regex
+            .find_iter(haystack)
+            .filter(|m| !m.is_empty())
+            .map(|m| m.range())
+    }
+}
+
+pub struct SearchState {
+    pub open: bool,
+    pub query: String,
+    pub use_regex: bool,
+    pub case_sensitive: bool,
+    pub sender: String,
+    pub kinds: [bool; 4],
+    // Position within the current match list, not an index into the messages.
+    pub current: usize,
+    pub scroll_to_current: bool,
+    pub focus_requested: bool,
+}
+
+impl Default for SearchState {
+    fn default() -> Self {
+        Self {
+            open: false,
+            query: String::new(),
+            use_regex: false,
+            case_sensitive: false,
+            sender: String::new(),
+            kinds: [true; 4],
+            current: 0,
+            scroll_to_current: false,
+            focus_requested: false,
+        }
+    }
+}
+
+impl SearchState {
+    pub fn kind_enabled(&self, kind: LineKind) -> bool {
+        let idx = LineKind::ALL.iter().position(|k| *k == kind).unwrap_or(0);
+        self.kinds[idx]
+    }
+
+    /// True when anything narrows the chat pane down.
+    pub fn is_filtering(&self) -> bool {
+        self.open
+            && (!self.query.is_empty()
+                || !self.sender.trim().is_empty()
+                || self.kinds.iter().any(|enabled| !enabled))
+    }
+
+    pub fn matches_line(
+        &self,
+        matcher: Option<&Matcher>,
+        kind: LineKind,
+        sender: Option<&str>,
+        text: &str,
+    ) -> bool {
+        if !self.kind_enabled(kind) {
+            return false;
+        }
+        let sender_filter = self.sender.trim().to_lowercase();
+        if !sender_filter.is_empty()
+            && !sender
+                .map(|s| s.to_lowercase().contains(&sender_filter))
+                .unwrap_or(false)
+        {
+            return false;
+        }
+        matcher.map(|m| m.is_match(text)).unwrap_or(true)
+    }
+
+    pub fn step(&mut self, forward: bool, match_count: usize) {
+        if match_count == 0 {
+            self.current = 0;
+            return;
+        }
+        self.current = if forward {
+            (self.current + 1) % match_count
+        } else {
+            (self.current + match_count - 1) % match_count
+        };
+        self.scroll_to_current = true;
+    }
+}
+
+/// Filters for the frame list in the Raw WebSocket pane. Applied on top of
+/// the search bar, which also matches raw frames.
+pub struct FrameFilter {
+    pub show_in: bool,
+    pub show_out: bool,
+    /// JSON `type` to show; `None` shows every type.
+    pub frame_type: Option<String>,
+    /// Regex matched against the payload only, not the `>>`/`<<` prefix.
+    pub payload_query: String,
+    pub hide_auto_ping: bool,
+}
+
+impl Default for FrameFilter {
+    fn default() -> Self {
+        Self {
+            show_in: true,
+            show_out: true,
+            frame_type: None,
+            payload_query: String::new(),
+            hide_auto_ping: true,
+        }
+    }
+}
+
+impl FrameFilter {
+    pub fn payload_matcher(&self) -> Result<Option<Matcher>, String> {
+        Matcher::new(&self.payload_query, true, false)
+    }
+
+    pub fn matches_frame(
+        &self,
+        payload_matcher: Option<&Matcher>,
+        direction: Option<Direction>,
+        frame_type: Option<&str>,
+        auto_ping: bool,
+        payload: &str,
+    ) -> bool {
+        match direction {
+            Some(Direction::In) if !self.show_in => return false,
+            Some(Direction::Out) if !self.show_out => return false,
+            _ => {}
+        }
+        if self.hide_auto_ping && auto_ping {
+            return false;
+        }
+        if let Some(wanted) = &self.frame_type {
+            if frame_type != Some(wanted.as_str()) {
+                return false;
+            }
+        }
+        payload_matcher.map(|m| m.is_match(payload)).unwrap_or(true)
+    }
+}
+
+/// Frames per JSON `type`, most frequent first. Frames without a type are
+/// left out.
+pub fn frame_type_counts<'a>(types: impl Iterator<Item = Option<&'a str>>) -> Vec<(String, usize)> {
+    let mut counts = HashMap::<&str, usize>::new();
+    for frame_type in types.flatten() {
+        *counts.entry(frame_type).or_default() += 1;
+    }
+    let mut counts = counts
+        .into_iter()
+        .map(|(frame_type, count)| (frame_type.to_string(), count))
+        .collect::<Vec<_>>();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{frame_type_counts, FrameFilter, LineKind, Matcher, SearchState};
+    use crate::recording::Direction;
+
+    #[test]
+    fn substring_search_is_case_insensitive_and_literal() {
+        let matcher = Matcher::new("a.b", false, false).unwrap().unwrap();
+        assert!(matcher.is_match("xx A.B yy"));
+        assert!(!matcher.is_match("axb"));
+        assert_eq!(matcher.find_ranges("a.b a.b").collect::<Vec<_>>(), vec![0..3, 4..7]);
+    }
+
+    #[test]
+    fn invalid_regex_is_reported() {
+        assert!(Matcher::new("(", true, false).is_err());
+        assert!(Matcher::new("", true, false).unwrap().is_none());
+    }
+
+    #[test]
+    fn filters_on_sender_and_kind() {
+        let mut state = SearchState {
+            sender: "bas".to_string(),
+            ..SearchState::default()
+        };
+        assert!(state.matches_line(None, LineKind::Chat, Some("Bas"), "hoi"));
+        assert!(!state.matches_line(None, LineKind::Chat, Some("Eva"), "hoi"));
+        state.kinds[0] = false;
+        assert!(!state.matches_line(None, LineKind::Chat, Some("Bas"), "hoi"));
+    }
+
+    #[test]
+    fn frame_filter_combines_direction_type_and_payload() {
+        let mut filter = FrameFilter::default();
+        let pong = r#"{"type":"pong","token":"auto-1"}"#;
+        assert!(!filter.matches_frame(None, Some(Direction::In), Some("pong"), true, pong));
+        filter.hide_auto_ping = false;
+        assert!(filter.matches_frame(None, Some(Direction::In), Some("pong"), true, pong));
+
+        filter.show_in = false;
+        assert!(!filter.matches_frame(None, Some(Direction::In), Some("chat"), false, "{}"));
+        assert!(filter.matches_frame(None, Some(Direction::Out), Some("chat"), false, "{}"));
+
+        filter.frame_type = Some("chat".to_string());
+        filter.payload_query = "hoi|hallo".to_string();
+        let matcher = filter.payload_matcher().unwrap();
+        let hallo = r#"{"type":"chat","text":"hallo"}"#;
+        assert!(filter.matches_frame(matcher.as_ref(), Some(Direction::Out), Some("chat"), false, hallo));
+        assert!(!filter.matches_frame(matcher.as_ref(), Some(Direction::Out), Some("chat"), false, "{}"));
+        assert!(!filter.matches_frame(matcher.as_ref(), Some(Direction::Out), Some("ping"), false, hallo));
+    }
+
+    #[test]
+    fn frame_types_are_counted_by_frequency() {
+        let types = [Some("pong"), Some("chat"), None, Some("pong")];
+        assert_eq!(
+            frame_type_counts(types.into_iter()),
+            vec![("pong".to_string(), 2), ("chat".to_string(), 1)]
+        );
+    }
+}
@@ -0,0 +1,2028 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant};
+
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::ai_usage::{AiUsageLog, AiUsageRecord};
+use crate::capabilities::{version_warning, Capabilities};
+use crate::history::{HistoryPolicy, HistoryStore};
+use crate::network::{
+    start_connection, RawFrame, ReconnectPolicy, SecurityInfo, UiEvent, WsCommand,
+};
+use crate::outbox::{is_queueable, Outbox};
+use crate::protocol::{
+    format_at_prefix, format_uptime, now_unix_ms, AiTurn, Incoming, Outgoing, DEFAULT_ROOM,
+};
+use crate::recording::{load_recording, split_raw_line, start_replay, Direction, Recorder};
+use crate::schema::{validate_incoming_text, SchemaIssue};
+use crate::search::LineKind;
+use crate::settings::{ServerProfile, TlsOptions, UnknownTypeAction};
+
+const AUTO_PING_INTERVAL_SECS: u64 = 5;
+const MAX_LATENCY_SAMPLES: usize = 100;
+pub const AUTO_PING_PREFIX: &str = "auto-";
+const MAX_RAW_MESSAGES: usize = 500;
+const HISTORY_PAGE_SIZE: usize = 100;
+/// An `/ai` that got no answer or new chunk for this long is given up.
+const AI_TIMEOUT_SECS: u64 = 120;
+
+#[derive(Clone)]
+pub struct RawLine {
+    pub line: String,
+    pub payload: String,
+    pub wall_ms: u64,
+    pub mono: Instant,
+    pub hand_crafted: bool,
+    /// JSON `type` of the payload, if it has one.
+    pub frame_type: Option<String>,
+    /// Ping/pong sent by the latency graph rather than by the user.
+    pub auto_ping: bool,
+    /// Contract violations of an incoming frame; empty for outgoing frames.
+    pub schema_issues: Vec<SchemaIssue>,
+}
+
+impl RawLine {
+    pub fn direction(&self) -> Option<Direction> {
+        split_raw_line(&self.line).map(|(direction, _)| direction)
+    }
+}
+
+/// JSON `type` of a frame payload and whether it belongs to the auto-ping.
+fn classify_payload(payload: &str) -> (Option<String>, bool) {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(payload) else {
+        return (None, false);
+    };
+    let frame_type = value.get("type").and_then(|t| t.as_str()).map(str::to_string);
+    let auto_ping = matches!(frame_type.as_deref(), Some("ping" | "pong"))
+        && value
+            .get("token")
+            .and_then(|t| t.as_str())
+            .is_some_and(|t| t.starts_with(AUTO_PING_PREFIX));
+    (frame_type, auto_ping)
+}
+
+#[derive(Default, Clone)]
+pub struct Metrics {
+    pub ws_in_frames: u64,
+    pub ws_out_frames: u64,
+    pub reconnects: u64,
+    pub connect_count: u64,
+    pub last_connected_at: Option<Instant>,
+    pub error_timestamps: VecDeque<Instant>,
+    // Incoming frames that break the client contract, see `schema`
+    pub schema_violations: u64,
+}
+
+pub enum OutboxAction {
+    Retry(String),
+    Cancel(String),
+}
+
+#[derive(Clone, Copy)]
+pub struct ReconnectState {
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub retry_at: Instant,
+}
+
+/// A sent `/ai` that is not answered yet.
+#[derive(Clone, Copy)]
+pub struct PendingAi {
+    pub sent: Instant,
+    /// Send time or the last chunk; the time-out counts from here.
+    pub last_activity: Instant,
+}
+
+/// Key for history and queued messages: the profile by its stable id plus
+/// the server, so renaming the profile or the user keeps both.
+pub fn identity_key(profile_id: &str, server_url: &str) -> String {
+    format!("{} {}", profile_id, server_url.trim())
+}
+
+pub fn is_guest_name(name: &str) -> bool {
+    name.trim().to_ascii_lowercase().starts_with("guest-")
+}
+
+/// Label/value rows for a `status` response, shared by the status card and the CLI.
+pub fn status_rows(status: &Incoming) -> Vec<(String, String)> {
+    let Incoming::Status {
+        version,
+        rust_version,
+        os,
+        cpu_cores,
+        uptime_seconds,
+        user_count,
+        peak_users,
+        connections_total,
+        messages_sent,
+        messages_per_second,
+        memory_mb,
+        ai_enabled,
+        ai_model,
+        ..
+    } = status
+    else {
+        return Vec::new();
+    };
+
+    let mut rows = vec![
+        ("Version".to_string(), version.clone()),
+        ("Uptime".to_string(), format_uptime(*uptime_seconds)),
+    ];
+
+    if let Some(os_name) = os {
+        rows.push((
+            "Platform".to_string(),
+            cpu_cores
+                .map(|c| format!("{} ({} cores)", os_name, c))
+                .unwrap_or_else(|| os_name.clone()),
+        ));
+    }
+    if let Some(rust_ver) = rust_version {
+        rows.push(("Rust".to_string(), rust_ver.clone()));
+    }
+    let users_value = if let Some(peak) = peak_users {
+        format!("{} (peak: {})", user_count, peak)
+    } else {
+        user_count.to_string()
+    };
+    rows.push(("Users".to_string(), users_value));
+    if let Some(conns) = connections_total {
+        rows.push(("Connections".to_string(), conns.to_string()));
+    }
+    rows.push(("Messages".to_string(), messages_sent.to_string()));
+    rows.push((
+        "Throughput".to_string(),
+        format!("{:.2} msg/s", messages_per_second),
+    ));
+    rows.push(("Memory".to_string(), format!("{:.2} MB", memory_mb)));
+    if let Some(enabled) = ai_enabled {
+        let ai_status = if *enabled {
+            ai_model.clone().unwrap_or_else(|| "enabled".to_string())
+        } else {
+            "disabled".to_string()
+        };
+        rows.push(("AI".to_string(), ai_status));
+    }
+    rows
+}
+
+/// Local thread id for an answer the server did not put in a thread, so any
+/// answer can be replied to.
+fn new_thread_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// `ai_stats` plus the client-side round trip, when the request was matched.
+fn ai_stats_with_rtt(
+    response_ms: u64,
+    tokens: Option<u32>,
+    cost: Option<f64>,
+    rtt: Option<Duration>,
+) -> String {
+    let mut stats = ai_stats(response_ms, tokens, cost);
+    if let Some(rtt) = rtt {
+        stats.push_str(&format!(" | rtt {}ms", rtt.as_millis()));
+    }
+    stats
+}
+
+pub fn ai_stats(response_ms: u64, tokens: Option<u32>, cost: Option<f64>) -> String {
+    let mut stats_parts = vec![format!("{}ms", response_ms)];
+    if let Some(t) = tokens {
+        stats_parts.push(format!("{} tokens", t));
+    }
+    if let Some(c) = cost {
+        stats_parts.push(format!("${:.4}", c));
+    }
+    stats_parts.join(" | ")
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum ChatLine {
+    Chat {
+        from: String,
+        text: String,
+        /// `None` is the default room; older history files have no room.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        room: Option<String>,
+        at: Option<u64>,
+    },
+    System {
+        text: String,
+        at: Option<u64>,
+    },
+    Error(String),
+    Status {
+        text: String,
+        at: Option<u64>,
+    },
+    StatusCard {
+        at: Option<u64>,
+        rows: Vec<(String, String)>,
+    },
+    UsersCard {
+        at: Option<u64>,
+        users: Vec<(String, String, String)>,
+    },
+    /// Answer to `/rooms`: (name, user count) per room.
+    RoomsCard {
+        at: Option<u64>,
+        rooms: Vec<(String, usize)>,
+    },
+    Ai {
+        from: String,
+        prompt: String,
+        response: String,
+        stats: String,
+        /// Conversation this answer belongs to; the first answer of a thread
+        /// is its root. Older history files have none.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        thread: Option<String>,
+        at: Option<u64>,
+    },
+    /// AI answer that is awaited or still streaming in. Replaced by `Ai` once
+    /// it ends; only that final line is written to history.
+    AiLive {
+        /// Server stream id, known from the first `aiChunk`.
+        stream: Option<String>,
+        /// Request id of the question, matched on the answer. Only ids of
+        /// this tab's own questions are in `pending_ai`.
+        request: Option<String>,
+        from: String,
+        prompt: String,
+        response: String,
+        started_ms: u64,
+        thread: Option<String>,
+        at: Option<u64>,
+    },
+    /// Direct message; `peer` is the other user, which names the conversation.
+    Dm {
+        peer: String,
+        from: String,
+        to: String,
+        text: String,
+        at: Option<u64>,
+    },
+    /// Message of a type this client does not know yet.
+    Unknown {
+        msg_type: String,
+        payload: serde_json::Value,
+        at: Option<u64>,
+    },
+}
+
+/// Which part of the chat pane a line belongs to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conversation {
+    Room(String),
+    Dm(String),
+}
+
+impl Default for Conversation {
+    fn default() -> Self {
+        Conversation::Room(DEFAULT_ROOM.to_string())
+    }
+}
+
+impl ChatLine {
+    /// Errors show everywhere so a failed DM is not missed; chat only in its
+    /// own room and DMs only in their own conversation; other lines (system
+    /// notices, cards, AI answers) in whichever room is open.
+    pub fn belongs_to(&self, conversation: &Conversation) -> bool {
+        match (self, conversation) {
+            (ChatLine::Error(_), _) => true,
+            (ChatLine::Dm { peer, .. }, Conversation::Dm(open)) => open.eq_ignore_ascii_case(peer),
+            (ChatLine::Dm { .. }, Conversation::Room(_)) => false,
+            (ChatLine::Chat { room, .. }, Conversation::Room(open)) => {
+                room.as_deref().unwrap_or(DEFAULT_ROOM) == open
+            }
+            (_, Conversation::Room(_)) => true,
+            (_, Conversation::Dm(_)) => false,
+        }
+    }
+
+    pub fn kind(&self) -> LineKind {
+        match self {
+            ChatLine::Chat { .. } | ChatLine::Dm { .. } => LineKind::Chat,
+            ChatLine::Error(_) => LineKind::Error,
+            ChatLine::Ai { .. } | ChatLine::AiLive { .. } => LineKind::Ai,
+            ChatLine::System { .. }
+            | ChatLine::Status { .. }
+            | ChatLine::StatusCard { .. }
+            | ChatLine::UsersCard { .. }
+            | ChatLine::RoomsCard { .. }
+            | ChatLine::Unknown { .. } => LineKind::System,
+        }
+    }
+
+    pub fn sender(&self) -> Option<&str> {
+        match self {
+            ChatLine::Chat { from, .. }
+            | ChatLine::Ai { from, .. }
+            | ChatLine::AiLive { from, .. }
+            | ChatLine::Dm { from, .. } => Some(from),
+            _ => None,
+        }
+    }
+
+    pub fn search_text(&self) -> String {
+        match self {
+            ChatLine::Chat { text, .. }
+            | ChatLine::System { text, .. }
+            | ChatLine::Status { text, .. }
+            | ChatLine::Dm { text, .. }
+            | ChatLine::Error(text) => text.clone(),
+            ChatLine::StatusCard { rows, .. } => rows
+                .iter()
+                .map(|(k, v)| format!("{} {}", k, v))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            ChatLine::UsersCard { users, .. } => users
+                .iter()
+                .map(|(name, ip, id)| format!("{} {} {}", name, ip, id))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            ChatLine::RoomsCard { rooms, .. } => rooms
+                .iter()
+                .map(|(name, users)| format!("#{} {}", name, users))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            ChatLine::Ai {
+                prompt,
+                response,
+                stats,
+                ..
+            } => format!("{}\n{}\n{}", prompt, response, stats),
+            ChatLine::AiLive { prompt, response, .. } => format!("{}\n{}", prompt, response),
+            ChatLine::Unknown { msg_type, payload, .. } => format!("{} {}", msg_type, payload),
+        }
+    }
+
+    pub fn thread(&self) -> Option<&str> {
+        match self {
+            ChatLine::Ai { thread, .. } | ChatLine::AiLive { thread, .. } => thread.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Prior turns sent along with a follow-up question, newest last.
+const MAX_THREAD_CONTEXT: usize = 6;
+
+/// Everything that belongs to one connection tab: the socket channels, the
+/// chat and raw frame logs, latency samples, metrics and handshake info.
+pub struct Session {
+    // Index into the app's profile list this tab was opened from
+    pub profile: usize,
+    profile_id: String,
+    // Working copies of the profile's name, URL and preferred name
+    pub profile_name: String,
+    pub server_url: String,
+    pub preferred_username: String,
+    pub username: String,
+    pub messages: Vec<ChatLine>,
+    pub raw_messages: VecDeque<RawLine>,
+    pub selected_raw_index: Option<usize>,
+    // Second frame picked with Ctrl/Cmd+click; the inspector diffs the two
+    pub compare_raw_index: Option<usize>,
+    pub connected: bool,
+    pub reconnecting: Option<ReconnectState>,
+    // Fed from a recording instead of a socket; nothing is sent or stored
+    pub replaying: bool,
+    // Chat and AI lines that arrived while this tab was not in front
+    pub unread: usize,
+    pub active_conversation: Conversation,
+    // Joined rooms, default room first; rejoined after a reconnect
+    pub rooms: Vec<String>,
+    pub room_unread: HashMap<String, usize>,
+    // DM partners in order of first contact, for the conversation sidebar
+    pub dm_peers: Vec<String>,
+    pub dm_unread: HashMap<String, usize>,
+    // Thread the next `/ai` continues, set by Reply on an AI card
+    pub ai_reply_thread: Option<String>,
+
+    // Channel to send messages to WebSocket
+    ws_tx: Option<UnboundedSender<WsCommand>>,
+    // Channel to receive events from WebSocket thread
+    ui_rx: Option<Receiver<UiEvent>>,
+    // Full-session capture of every raw frame while recording is on
+    recorder: Option<Recorder>,
+    // On-disk scrollback for `history_identity`; older pages load as the user scrolls up
+    history: Option<HistoryStore>,
+    history_identity: String,
+    // (content height, scroll offset) captured when a page was prepended
+    history_scroll_anchor: Option<(f32, f32)>,
+    pub history_scroll_target: Option<f32>,
+    // Pending ping requests for roundtrip calculation
+    pub pending_pings: HashMap<String, Instant>,
+    // Sent `/ai` requests by request id, for round-trip time and time-outs
+    pub pending_ai: HashMap<String, PendingAi>,
+    pub latency_samples: VecDeque<f32>,
+    last_auto_ping_sent: Option<Instant>,
+    pub security_info: Option<SecurityInfo>,
+    pub capabilities: Capabilities,
+    // Answers to this tab's own `/ai` questions, for the usage panel
+    pub ai_usage: Vec<AiUsageRecord>,
+    // Unanswered `status` requests in send order; `true` marks the probe sent
+    // on connect, whose answer only updates `capabilities`
+    pending_status: VecDeque<bool>,
+    pub metrics: Metrics,
+}
+
+impl Session {
+    pub fn new(profile_idx: usize, profile: &ServerProfile) -> Self {
+        let preferred_username = if is_guest_name(&profile.username) {
+            String::new()
+        } else {
+            profile.username.clone()
+        };
+        Self {
+            profile: profile_idx,
+            profile_id: profile.id.clone(),
+            profile_name: profile.name.clone(),
+            server_url: profile.server_url.clone(),
+            preferred_username,
+            username: profile.username.clone(),
+            messages: Vec::new(),
+            raw_messages: VecDeque::new(),
+            selected_raw_index: None,
+            compare_raw_index: None,
+            connected: false,
+            reconnecting: None,
+            replaying: false,
+            unread: 0,
+            active_conversation: Conversation::default(),
+            rooms: vec![DEFAULT_ROOM.to_string()],
+            room_unread: HashMap::new(),
+            dm_peers: Vec::new(),
+            dm_unread: HashMap::new(),
+            ai_reply_thread: None,
+            ws_tx: None,
+            ui_rx: None,
+            recorder: None,
+            history: None,
+            history_identity: String::new(),
+            history_scroll_anchor: None,
+            history_scroll_target: None,
+            pending_pings: HashMap::new(),
+            pending_ai: HashMap::new(),
+            latency_samples: VecDeque::new(),
+            last_auto_ping_sent: None,
+            security_info: None,
+            capabilities: Capabilities::default(),
+            ai_usage: Vec::new(),
+            pending_status: VecDeque::new(),
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Which profile and server this tab is; history and queued messages
+    /// are kept per identity so two profiles on one server stay apart.
+    pub fn identity(&self) -> String {
+        identity_key(&self.profile_id, &self.server_url)
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.connected || self.reconnecting.is_some() || self.replaying
+    }
+
+    /// Adds a line from the server. Everything but errors and status lines
+    /// also goes to history.
+    pub fn push_line(&mut self, line: ChatLine) {
+        if !matches!(line, ChatLine::Error(_) | ChatLine::Status { .. }) {
+            self.append_history(&line);
+        }
+        if let ChatLine::Dm { peer, .. } = &line {
+            self.note_dm_peer(peer);
+        }
+        self.messages.push(line);
+    }
+
+    /// Adds a client-side line, such as connection state, that only matters
+    /// to this session and is kept out of history.
+    pub fn push_notice(&mut self, line: ChatLine) {
+        self.messages.push(line);
+    }
+
+    fn append_history(&mut self, line: &ChatLine) {
+        if let Some(history) = self.history.as_mut().filter(|_| !self.replaying) {
+            if let Err(err) = history.append(line) {
+                self.history = None;
+                self.messages.push(ChatLine::Error(format!(
+                    "{} (history disabled for this session)",
+                    err
+                )));
+            }
+        }
+    }
+
+    fn live_stream_index(&self, id: &str) -> Option<usize> {
+        self.messages.iter().position(
+            |line| matches!(line, ChatLine::AiLive { stream: Some(stream), .. } if stream == id),
+        )
+    }
+
+    /// Live AI card for stream `id` or for `request`. Answers without a
+    /// request id (older servers) fall back to the oldest card from `from`
+    /// for `prompt` that is still waiting for its first chunk.
+    fn live_ai_index(
+        &self,
+        id: Option<&str>,
+        request: Option<&str>,
+        from: &str,
+        prompt: &str,
+    ) -> Option<usize> {
+        if let Some(idx) = id.and_then(|id| self.live_stream_index(id)) {
+            return Some(idx);
+        }
+        if let Some(request) = request {
+            return self.messages.iter().position(
+                |line| matches!(line, ChatLine::AiLive { request: Some(r), .. } if r == request),
+            );
+        }
+        self.messages.iter().position(|line| {
+            matches!(
+                line,
+                ChatLine::AiLive { stream: None, from: who, prompt: asked, .. }
+                    if who.eq_ignore_ascii_case(from) && asked == prompt
+            )
+        })
+    }
+
+    /// Client-side round trip of a sent `/ai`; also ends its time-out.
+    fn finish_ai_request(&mut self, request: Option<&str>) -> Option<Duration> {
+        request
+            .and_then(|request| self.pending_ai.remove(request))
+            .map(|pending| pending.sent.elapsed())
+    }
+
+    /// Gives up on `/ai` requests that went `AI_TIMEOUT_SECS` without an
+    /// answer or a new chunk. A stalled stream is cancelled and keeps its
+    /// partial answer, marked "afgebroken"; an empty placeholder becomes an error.
+    pub fn expire_ai_requests(&mut self) {
+        let now = Instant::now();
+        let expired = self
+            .pending_ai
+            .iter()
+            .filter(|(_, pending)| {
+                now.duration_since(pending.last_activity).as_secs() >= AI_TIMEOUT_SECS
+            })
+            .map(|(request, _)| request.clone())
+            .collect::<Vec<_>>();
+        for request in expired {
+            self.pending_ai.remove(&request);
+            let live = self.messages.iter().position(
+                |line| matches!(line, ChatLine::AiLive { request: Some(r), .. } if *r == request),
+            );
+            let Some(idx) = live else {
+                continue;
+            };
+            let ChatLine::AiLive { stream, prompt, .. } = &self.messages[idx] else {
+                continue;
+            };
+            let text = match stream {
+                Some(_) => format!(
+                    "AI-antwoord op \"{}\" kreeg {}s geen nieuw deel en is afgebroken.",
+                    prompt, AI_TIMEOUT_SECS
+                ),
+                None => format!(
+                    "AI-vraag \"{}\" kreeg binnen {}s geen antwoord.",
+                    prompt, AI_TIMEOUT_SECS
+                ),
+            };
+            if let Some(id) = stream.clone() {
+                self.transmit(Outgoing::AiCancel { id });
+            }
+            self.break_off_live_ai(idx);
+            self.push_line(ChatLine::Error(text));
+        }
+    }
+
+    /// Keeps the partial answer of live card `idx` as an "afgebroken" line;
+    /// a placeholder without any text is removed.
+    fn break_off_live_ai(&mut self, idx: usize) {
+        let ChatLine::AiLive {
+            from,
+            prompt,
+            response,
+            thread,
+            at,
+            ..
+        } = &self.messages[idx]
+        else {
+            return;
+        };
+        if response.is_empty() {
+            self.messages.remove(idx);
+            return;
+        }
+        let line = ChatLine::Ai {
+            from: from.clone(),
+            prompt: prompt.clone(),
+            response: response.clone(),
+            stats: "afgebroken".to_string(),
+            thread: thread.clone(),
+            at: *at,
+        };
+        self.finish_live_ai(idx, line);
+    }
+
+    /// Replaces a live AI card with its final line, which goes to history now.
+    fn finish_live_ai(&mut self, idx: usize, line: ChatLine) {
+        self.append_history(&line);
+        self.messages[idx] = line;
+    }
+
+    /// Earlier question/answer pairs of `thread`, for a follow-up's context.
+    pub fn thread_turns(&self, thread: &str) -> Vec<AiTurn> {
+        let turns = self
+            .messages
+            .iter()
+            .filter_map(|line| match line {
+                ChatLine::Ai {
+                    prompt,
+                    response,
+                    thread: Some(t),
+                    ..
+                } if t == thread => Some(AiTurn {
+                    prompt: prompt.clone(),
+                    response: response.clone(),
+                }),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let skip = turns.len().saturating_sub(MAX_THREAD_CONTEXT);
+        turns.into_iter().skip(skip).collect()
+    }
+
+    /// `/ai` request for `prompt`; a follow-up when a thread was picked to
+    /// reply to, carrying that thread's earlier turns as context.
+    pub fn ai_request(&mut self, prompt: String) -> Outgoing {
+        let thread = self.ai_reply_thread.take();
+        let context = thread
+            .as_deref()
+            .map(|thread| self.thread_turns(thread))
+            .unwrap_or_default();
+        Outgoing::Ai {
+            prompt,
+            thread,
+            context,
+            request_id: Some(uuid::Uuid::new_v4().to_string()),
+        }
+    }
+
+    /// Prompt of the first loaded answer in `thread`.
+    pub fn thread_root_prompt(&self, thread: &str) -> Option<&str> {
+        self.messages.iter().find_map(|line| match line {
+            ChatLine::Ai { prompt, .. } | ChatLine::AiLive { prompt, .. } if line.thread() == Some(thread) => {
+                Some(prompt.as_str())
+            }
+            _ => None,
+        })
+    }
+
+    /// Follow-up line indices per thread root index, so the chat pane can
+    /// show them grouped under the original answer.
+    pub fn thread_followups(&self) -> HashMap<usize, Vec<usize>> {
+        let mut roots = HashMap::<&str, usize>::new();
+        let mut followups = HashMap::<usize, Vec<usize>>::new();
+        for (idx, line) in self.messages.iter().enumerate() {
+            let Some(thread) = line.thread() else {
+                continue;
+            };
+            match roots.get(thread) {
+                Some(root) => followups.entry(*root).or_default().push(idx),
+                None => {
+                    roots.insert(thread, idx);
+                }
+            }
+        }
+        followups
+    }
+
+    /// Ends every live AI card when the connection drops. Partial answers
+    /// are kept; placeholders without any text are removed.
+    fn abort_live_ai(&mut self) {
+        for idx in (0..self.messages.len()).rev() {
+            self.break_off_live_ai(idx);
+        }
+    }
+
+    /// Whether an answer is to a question this tab sent: its request id was
+    /// still pending here. Only without any request id (the server does not
+    /// echo them) does the sender's name decide.
+    fn is_own_ai_answer(&self, request: Option<&str>, rtt: Option<Duration>, from: &str) -> bool {
+        match (request, rtt) {
+            (_, Some(_)) => true,
+            (Some(_), None) => false,
+            (None, None) => from.eq_ignore_ascii_case(&self.username),
+        }
+    }
+
+    /// Counts an answer to an own question, in this tab and in the
+    /// persisted log behind the day and profile totals.
+    fn record_ai_usage(
+        &mut self,
+        usage: &mut AiUsageLog,
+        response_ms: u64,
+        tokens: Option<u32>,
+        cost: Option<f64>,
+    ) {
+        let record = AiUsageRecord {
+            profile_id: self.profile_id.clone(),
+            profile: self.profile_name.clone(),
+            server_url: self.server_url.clone(),
+            at: now_unix_ms(),
+            response_ms,
+            tokens,
+            cost,
+        };
+        self.ai_usage.push(record.clone());
+        if self.replaying {
+            return;
+        }
+        usage.push(record);
+        if let Err(err) = usage.save() {
+            self.push_line(ChatLine::Error(err));
+        }
+    }
+
+    fn note_dm_peer(&mut self, peer: &str) {
+        if !self.dm_peers.iter().any(|p| p.eq_ignore_ascii_case(peer)) {
+            self.dm_peers.push(peer.to_string());
+        }
+    }
+
+    /// Opens (or switches to) the DM conversation with `peer`.
+    pub fn open_dm(&mut self, peer: &str) {
+        self.note_dm_peer(peer);
+        self.select_conversation(Conversation::Dm(peer.to_string()));
+    }
+
+    pub fn select_conversation(&mut self, conversation: Conversation) {
+        match &conversation {
+            Conversation::Room(room) => self.room_unread.remove(room),
+            Conversation::Dm(peer) => self.dm_unread.remove(peer),
+        };
+        self.active_conversation = conversation;
+    }
+
+    /// Room for outgoing chat: the open room, or `None` for the default room
+    /// so servers without rooms get the same frame as before.
+    pub fn chat_room(&self) -> Option<String> {
+        match &self.active_conversation {
+            Conversation::Room(room) if room != DEFAULT_ROOM => Some(room.clone()),
+            _ => None,
+        }
+    }
+
+    /// Switches to `room`, asking the server to join it first when needed.
+    pub fn join_room(&mut self, room: String, outbox: &mut Outbox) {
+        if self.rooms.contains(&room) {
+            self.select_conversation(Conversation::Room(room));
+        } else {
+            self.send_ws(Outgoing::Join { room }, outbox);
+        }
+    }
+
+    pub fn leave_room(&mut self, room: String, outbox: &mut Outbox) {
+        if room == DEFAULT_ROOM {
+            self.push_line(ChatLine::Error(format!("#{} cannot be left.", DEFAULT_ROOM)));
+        } else if !self.rooms.contains(&room) {
+            self.push_line(ChatLine::Error(format!("Not in #{}.", room)));
+        } else {
+            self.send_ws(Outgoing::Leave { room }, outbox);
+        }
+    }
+
+    fn note_room(&mut self, room: &str) -> bool {
+        if self.rooms.iter().any(|r| r == room) {
+            return false;
+        }
+        self.rooms.push(room.to_string());
+        true
+    }
+
+    /// Drops a room locally after the server confirmed leaving it.
+    fn forget_room(&mut self, room: &str) {
+        self.rooms.retain(|r| r != room);
+        self.room_unread.remove(room);
+        if self.active_conversation == Conversation::Room(room.to_string()) {
+            self.active_conversation = Conversation::default();
+        }
+    }
+
+    /// Hides a DM conversation from the sidebar; its lines stay in history.
+    pub fn close_dm(&mut self, peer: &str) {
+        self.dm_peers.retain(|p| p != peer);
+        self.dm_unread.remove(peer);
+        if self.active_conversation == Conversation::Dm(peer.to_string()) {
+            self.active_conversation = Conversation::default();
+        }
+    }
+
+    /// (Re)opens the history file for the current server URL and shows its
+    /// most recent page. Called at startup and whenever the URL changes.
+    pub fn open_history(&mut self, policy: &HistoryPolicy) {
+        let identity = self.identity();
+        if self.history_identity == identity && self.history.is_some() {
+            return;
+        }
+        self.history = None;
+        self.history_identity = identity;
+        self.history_scroll_anchor = None;
+        self.history_scroll_target = None;
+        self.messages.clear();
+        self.rooms = vec![DEFAULT_ROOM.to_string()];
+        self.room_unread.clear();
+        self.dm_peers.clear();
+        self.dm_unread.clear();
+        self.active_conversation = Conversation::default();
+        if !policy.enabled {
+            return;
+        }
+        match HistoryStore::open(&self.history_identity, &self.server_url, policy) {
+            Ok(store) => {
+                self.history = Some(store);
+                self.load_older_history();
+            }
+            Err(err) => self.messages.push(ChatLine::Error(err)),
+        }
+    }
+
+    fn load_older_history(&mut self) -> bool {
+        let Some(history) = &mut self.history else {
+            return false;
+        };
+        match history.load_older::<ChatLine>(HISTORY_PAGE_SIZE) {
+            Ok(older) if !older.is_empty() => {
+                for line in &older {
+                    if let ChatLine::Dm { peer, .. } = line {
+                        self.note_dm_peer(peer);
+                    }
+                }
+                self.messages.splice(0..0, older);
+                true
+            }
+            Ok(_) => false,
+            Err(err) => {
+                self.history = None;
+                self.messages.push(ChatLine::Error(err));
+                false
+            }
+        }
+    }
+
+    /// Prepends an older history page once the chat pane is scrolled to the
+    /// top, then keeps the previously visible lines in place on the next frame.
+    pub fn maybe_load_history_page(&mut self, ctx: &egui::Context, content_h: f32, offset_y: f32) {
+        if let Some((prev_h, prev_offset)) = self.history_scroll_anchor.take() {
+            self.history_scroll_target = Some(prev_offset + (content_h - prev_h).max(0.0));
+            ctx.request_repaint();
+            return;
+        }
+        let has_older = self.history.as_ref().is_some_and(|h| h.has_older());
+        if has_older && offset_y <= 4.0 && self.load_older_history() {
+            self.history_scroll_anchor = Some((content_h, offset_y));
+            ctx.request_repaint();
+        }
+    }
+
+    pub fn connect(
+        &mut self,
+        tls: TlsOptions,
+        reconnect: ReconnectPolicy,
+        history_policy: &HistoryPolicy,
+        ctx: egui::Context,
+    ) {
+        self.open_history(history_policy);
+        let url = self.server_url.clone();
+        let (ui_tx, ui_rx) = channel::<UiEvent>();
+
+        self.ws_tx = Some(start_connection(url, tls, reconnect, ui_tx, ctx));
+        self.ui_rx = Some(ui_rx);
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    pub fn recording_path(&self) -> Option<&Path> {
+        self.recorder.as_ref().map(|r| r.path())
+    }
+
+    pub fn recorded_frames(&self) -> usize {
+        self.recorder.as_ref().map(|r| r.frames).unwrap_or(0)
+    }
+
+    pub fn start_recording(&mut self) {
+        match Recorder::create(&self.server_url) {
+            Ok(recorder) => {
+                self.push_notice(ChatLine::System {
+                    text: format!("Opname gestart: {}", recorder.path().display()),
+                    at: None,
+                });
+                self.recorder = Some(recorder);
+            }
+            Err(err) => self.push_line(ChatLine::Error(err)),
+        }
+    }
+
+    pub fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            self.push_notice(ChatLine::System {
+                text: format!(
+                    "Opname opgeslagen: {} ({} frames)",
+                    recorder.path().display(),
+                    recorder.frames
+                ),
+                at: None,
+            });
+        }
+    }
+
+    /// Replays a recording into this tab through the normal event pipeline.
+    /// `speed` is a multiplier; 0 plays it back as fast as possible.
+    pub fn replay(&mut self, path: &Path, speed: f32, ctx: egui::Context) {
+        let frames = match load_recording(path) {
+            Ok(frames) => frames,
+            Err(err) => {
+                self.push_line(ChatLine::Error(err));
+                return;
+            }
+        };
+        self.disconnect();
+        self.stop_recording();
+        let (ui_tx, ui_rx) = channel::<UiEvent>();
+        self.ws_tx = Some(start_replay(frames, speed, ui_tx, ctx));
+        self.ui_rx = Some(ui_rx);
+        self.replaying = true;
+        self.push_notice(ChatLine::System {
+            text: format!("Replay gestart: {}", path.display()),
+            at: None,
+        });
+    }
+
+    pub fn disconnect(&mut self) {
+        if !self.connected && self.reconnecting.is_none() && self.ws_tx.is_none() {
+            return;
+        }
+        if let Some(tx) = self.ws_tx.take() {
+            let _ = tx.send(WsCommand::Disconnect);
+        }
+        self.connected = false;
+        self.reconnecting = None;
+        self.pending_pings.clear();
+        self.pending_status.clear();
+        self.last_auto_ping_sent = None;
+        self.push_notice(ChatLine::System {
+            text: "Disconnect requested".to_string(),
+            at: None,
+        });
+    }
+
+    pub fn send_ws(&mut self, outgoing: Outgoing, outbox: &mut Outbox) {
+        if self.connected && self.transmit(outgoing.clone()) {
+            return;
+        }
+        if is_queueable(&outgoing) {
+            self.queue_outgoing(outgoing, outbox);
+        } else {
+            self.push_line(ChatLine::Error("Not connected to server.".to_string()));
+        }
+    }
+
+    /// Hands `outgoing` to the socket thread. Returns false when the
+    /// connection is gone, so callers can keep the message around.
+    fn transmit(&mut self, outgoing: Outgoing) -> bool {
+        let Some(tx) = &self.ws_tx else {
+            return false;
+        };
+        let ai_prompt = match &outgoing {
+            Outgoing::Ai {
+                prompt,
+                thread,
+                request_id,
+                ..
+            } => Some((prompt.clone(), thread.clone(), request_id.clone())),
+            _ => None,
+        };
+        let is_status = matches!(outgoing, Outgoing::Status);
+        if tx.send(WsCommand::Send(outgoing)).is_err() {
+            return false;
+        }
+        if is_status {
+            self.pending_status.push_back(false);
+        }
+        if let Some((prompt, thread, request)) = ai_prompt {
+            if let Some(request) = &request {
+                let now = Instant::now();
+                self.pending_ai.insert(
+                    request.clone(),
+                    PendingAi {
+                        sent: now,
+                        last_activity: now,
+                    },
+                );
+            }
+            // Placeholder until the first chunk or the full answer arrives
+            self.messages.push(ChatLine::AiLive {
+                stream: None,
+                request,
+                from: self.username.clone(),
+                prompt,
+                response: String::new(),
+                started_ms: now_unix_ms(),
+                thread,
+                at: None,
+            });
+        }
+        true
+    }
+
+    /// Sends hand-written text unchanged. Never queued: it may not even be JSON.
+    pub fn send_raw(&mut self, text: String) {
+        let sent = self.connected
+            && self
+                .ws_tx
+                .as_ref()
+                .is_some_and(|tx| tx.send(WsCommand::SendRaw(text)).is_ok());
+        if !sent {
+            self.push_line(ChatLine::Error("Not connected to server.".to_string()));
+        }
+    }
+
+    fn queue_outgoing(&mut self, outgoing: Outgoing, outbox: &mut Outbox) {
+        let identity = self.identity();
+        outbox.push(&self.server_url, &identity, outgoing);
+        self.persist_outbox(outbox);
+    }
+
+    fn persist_outbox(&mut self, outbox: &Outbox) {
+        if let Err(err) = outbox.save() {
+            self.push_line(ChatLine::Error(err));
+        }
+    }
+
+    fn flush_outbox(&mut self, outbox: &mut Outbox) {
+        let identity = self.identity();
+        let ids = outbox
+            .pending_for(&self.server_url, &identity)
+            .map(|item| item.id.clone())
+            .collect::<Vec<_>>();
+        for id in ids {
+            if !self.retry_outbox_item(&id, outbox) {
+                break;
+            }
+        }
+    }
+
+    fn retry_outbox_item(&mut self, id: &str, outbox: &mut Outbox) -> bool {
+        let Some(item) = outbox.get_mut(id) else {
+            return true;
+        };
+        item.attempts += 1;
+        let outgoing = item.outgoing.clone();
+        let sent = self.connected && self.transmit(outgoing);
+        if sent {
+            let _ = outbox.remove(id);
+        } else if let Some(item) = outbox.get_mut(id) {
+            item.last_error = Some("Not connected to server.".to_string());
+        }
+        self.persist_outbox(outbox);
+        sent
+    }
+
+    pub fn apply_outbox_action(&mut self, action: OutboxAction, outbox: &mut Outbox) {
+        match action {
+            OutboxAction::Retry(id) => {
+                let _ = self.retry_outbox_item(&id, outbox);
+            }
+            OutboxAction::Cancel(id) => {
+                if outbox.remove(&id).is_some() {
+                    self.persist_outbox(outbox);
+                }
+            }
+        }
+    }
+
+    pub fn process_incoming(
+        &mut self,
+        outbox: &mut Outbox,
+        unknown_types: &BTreeMap<String, UnknownTypeAction>,
+        usage: &mut AiUsageLog,
+    ) {
+        let mut events = Vec::new();
+        if let Some(rx) = &self.ui_rx {
+            while let Ok(event) = rx.try_recv() {
+                events.push(event);
+            }
+        }
+
+        for event in events {
+            match event {
+                UiEvent::Connected => {
+                    if self.metrics.connect_count > 0 {
+                        self.metrics.reconnects += 1;
+                    }
+                    self.metrics.connect_count += 1;
+                    self.metrics.last_connected_at = Some(Instant::now());
+                    self.connected = true;
+                    self.reconnecting = None;
+                    self.last_auto_ping_sent = Some(Instant::now());
+                    let identity = self.identity();
+                    let name_queued = outbox
+                        .pending_for(&self.server_url, &identity)
+                        .any(|item| matches!(item.outgoing, Outgoing::SetName { .. }));
+                    if !name_queued
+                        && !self.preferred_username.trim().is_empty()
+                        && !is_guest_name(&self.preferred_username)
+                    {
+                        self.send_ws(
+                            Outgoing::SetName {
+                                name: self.preferred_username.clone(),
+                            },
+                            outbox,
+                        );
+                    }
+                    self.push_notice(ChatLine::System {
+                        text: "Connected!".to_string(),
+                        at: None,
+                    });
+                    self.send_status_probe();
+                    for room in self.rooms.clone() {
+                        if room != DEFAULT_ROOM {
+                            self.send_ws(Outgoing::Join { room }, outbox);
+                        }
+                    }
+                    self.flush_outbox(outbox);
+                }
+                UiEvent::Disconnected(reason) => {
+                    self.connected = false;
+                    self.reconnecting = None;
+                    self.ws_tx = None;
+                    self.pending_pings.clear();
+                    self.pending_ai.clear();
+                    self.last_auto_ping_sent = None;
+                    self.pending_status.clear();
+                    self.abort_live_ai();
+                    if let Some(reason) = reason {
+                        self.push_line(ChatLine::Error(reason));
+                    }
+                    let text = if self.replaying {
+                        "Replay finished"
+                    } else {
+                        "Disconnected"
+                    };
+                    self.push_notice(ChatLine::System {
+                        text: text.to_string(),
+                        at: None,
+                    });
+                    self.replaying = false;
+                }
+                UiEvent::Reconnecting {
+                    attempt,
+                    max_attempts,
+                    delay,
+                    reason,
+                } => {
+                    self.connected = false;
+                    self.pending_pings.clear();
+                    self.pending_ai.clear();
+                    self.pending_status.clear();
+                    self.last_auto_ping_sent = None;
+                    self.reconnecting = Some(ReconnectState {
+                        attempt,
+                        max_attempts,
+                        retry_at: Instant::now() + delay,
+                    });
+                    self.abort_live_ai();
+                    if let Some(reason) = reason {
+                        self.push_line(ChatLine::Error(reason));
+                    }
+                    self.push_notice(ChatLine::System {
+                        text: format!(
+                            "Connection lost, reconnecting in {:.1}s (attempt {}/{})",
+                            delay.as_secs_f32(),
+                            attempt,
+                            max_attempts
+                        ),
+                        at: None,
+                    });
+                }
+                UiEvent::Warning(text) => {
+                    self.record_error_event();
+                    self.push_line(ChatLine::Error(text));
+                }
+                UiEvent::Error(text) => {
+                    self.record_error_event();
+                    self.push_line(ChatLine::Error(text));
+                }
+                UiEvent::Incoming(Incoming::Chat {
+                    from,
+                    text,
+                    room,
+                    at,
+                }) => {
+                    self.unread += 1;
+                    let name = room.clone().unwrap_or_else(|| DEFAULT_ROOM.to_string());
+                    self.note_room(&name);
+                    if self.active_conversation != Conversation::Room(name.clone()) {
+                        *self.room_unread.entry(name).or_default() += 1;
+                    }
+                    self.push_line(ChatLine::Chat {
+                        from,
+                        text,
+                        room,
+                        at,
+                    });
+                }
+                UiEvent::Incoming(Incoming::Joined { room, at }) => {
+                    // Rejoins after a reconnect confirm quietly
+                    if self.note_room(&room) {
+                        self.select_conversation(Conversation::Room(room.clone()));
+                        self.push_line(ChatLine::System {
+                            text: format!("Joined #{}", room),
+                            at,
+                        });
+                    }
+                }
+                UiEvent::Incoming(Incoming::Left { room, at }) => {
+                    self.forget_room(&room);
+                    self.push_line(ChatLine::System {
+                        text: format!("Left #{}", room),
+                        at,
+                    });
+                }
+                UiEvent::Incoming(Incoming::Rooms { rooms, at }) => {
+                    let rooms = rooms.into_iter().map(|r| (r.name, r.users)).collect();
+                    self.push_line(ChatLine::RoomsCard { at, rooms });
+                }
+                UiEvent::Incoming(Incoming::Dm { from, to, text, at }) => {
+                    let outgoing = from.eq_ignore_ascii_case(&self.username);
+                    let peer = if outgoing { to.clone() } else { from.clone() };
+                    if !outgoing {
+                        self.unread += 1;
+                        if !matches!(&self.active_conversation, Conversation::Dm(open) if open.eq_ignore_ascii_case(&peer)) {
+                            *self.dm_unread.entry(peer.clone()).or_default() += 1;
+                        }
+                    }
+                    self.push_line(ChatLine::Dm {
+                        peer,
+                        from,
+                        to,
+                        text,
+                        at,
+                    });
+                }
+                UiEvent::Incoming(Incoming::System { text, at }) => {
+                    self.push_line(ChatLine::System { text, at });
+                }
+                UiEvent::Incoming(Incoming::AckName { name, at }) => {
+                    self.username = name.clone();
+                    self.push_line(ChatLine::System {
+                        text: format!("Your name is now: {}", name),
+                        at,
+                    });
+                }
+                UiEvent::Incoming(status @ Incoming::Status { .. }) => {
+                    let at = status.at();
+                    if let Some(warning) = self.capabilities.apply_status(&status) {
+                        self.push_notice(ChatLine::System { text: warning, at });
+                    }
+                    // The server answers in order; an unrequested status is shown
+                    let probe = self.pending_status.pop_front().unwrap_or(false);
+                    if !probe {
+                        self.push_line(ChatLine::StatusCard {
+                            at,
+                            rows: status_rows(&status),
+                        });
+                    }
+                }
+                UiEvent::Incoming(Incoming::ListUsers { users, at }) => {
+                    let mapped = users
+                        .into_iter()
+                        .map(|u| (u.name, u.ip, u.id))
+                        .collect::<Vec<_>>();
+                    self.push_line(ChatLine::UsersCard { at, users: mapped });
+                }
+                UiEvent::Incoming(Incoming::Unknown { msg_type, payload }) => {
+                    let at = payload.get("at").and_then(|at| at.as_u64());
+                    match unknown_types.get(&msg_type).copied().unwrap_or_default() {
+                        UnknownTypeAction::Show => self.push_line(ChatLine::Unknown {
+                            msg_type,
+                            payload,
+                            at,
+                        }),
+                        UnknownTypeAction::Hide => {}
+                        UnknownTypeAction::Warn => {
+                            self.record_error_event();
+                            self.push_line(ChatLine::Error(format!(
+                                "{}Unknown server message type: {}",
+                                format_at_prefix(at),
+                                msg_type
+                            )));
+                        }
+                    }
+                }
+                UiEvent::Incoming(Incoming::Error { message, at }) => {
+                    let prefix = format_at_prefix(at);
+                    self.push_line(ChatLine::Error(format!("{}{}", prefix, message)));
+                }
+                UiEvent::Incoming(Incoming::Pong { token, at }) => {
+                    let roundtrip = token
+                        .as_ref()
+                        .and_then(|t| self.pending_pings.remove(t).map(|start| start.elapsed()));
+                    let is_auto_ping = token
+                        .as_ref()
+                        .map(|t| t.starts_with(AUTO_PING_PREFIX))
+                        .unwrap_or(false);
+                    let token_str = token
+                        .as_ref()
+                        .map(|t| format!(" (token: {}...)", &t[..8.min(t.len())]))
+                        .unwrap_or_default();
+                    if let Some(rtt) = roundtrip {
+                        let rtt_ms = (rtt.as_secs_f64() * 1000.0) as f32;
+                        if is_auto_ping {
+                            self.record_latency_sample(rtt_ms);
+                        } else {
+                            self.push_line(ChatLine::Status {
+                                text: format!(
+                                    "Pong! roundtrip: {:.2}ms{}",
+                                    rtt.as_secs_f64() * 1000.0,
+                                    token_str
+                                ),
+                                at,
+                            });
+                        }
+                    } else {
+                        if !is_auto_ping {
+                            self.push_line(ChatLine::Status {
+                                text: format!("Pong!{}", token_str),
+                                at,
+                            });
+                        }
+                    }
+                }
+                UiEvent::Incoming(Incoming::Ai {
+                    from,
+                    prompt,
+                    response,
+                    response_ms,
+                    tokens,
+                    cost,
+                    thread,
+                    request_id,
+                    at,
+                }) => {
+                    self.unread += 1;
+                    let live = self.live_ai_index(None, request_id.as_deref(), &from, &prompt);
+                    let live_request = live.and_then(|idx| match &self.messages[idx] {
+                        ChatLine::AiLive { request, .. } => request.clone(),
+                        _ => None,
+                    });
+                    let request = request_id.or(live_request);
+                    let rtt = self.finish_ai_request(request.as_deref());
+                    if self.is_own_ai_answer(request.as_deref(), rtt, &from) {
+                        self.record_ai_usage(usage, response_ms, tokens, cost);
+                    }
+                    let thread = thread
+                        .or_else(|| live.and_then(|idx| self.messages[idx].thread().map(str::to_string)))
+                        .unwrap_or_else(new_thread_id);
+                    let line = ChatLine::Ai {
+                        from,
+                        prompt,
+                        response,
+                        stats: ai_stats_with_rtt(response_ms, tokens, cost, rtt),
+                        thread: Some(thread),
+                        at,
+                    };
+                    match live {
+                        Some(idx) => self.finish_live_ai(idx, line),
+                        None => self.push_line(line),
+                    }
+                }
+                UiEvent::Incoming(Incoming::AiChunk {
+                    id,
+                    from,
+                    prompt,
+                    delta,
+                    thread,
+                    request_id,
+                    at,
+                }) => match self.live_ai_index(Some(&id), request_id.as_deref(), &from, &prompt) {
+                    Some(idx) => {
+                        if let ChatLine::AiLive {
+                            stream,
+                            request,
+                            response,
+                            thread: live_thread,
+                            at: first_at,
+                            ..
+                        } = &mut self.messages[idx]
+                        {
+                            *stream = Some(id);
+                            response.push_str(&delta);
+                            *first_at = first_at.or(at);
+                            if live_thread.is_none() {
+                                *live_thread = thread;
+                            }
+                            // Every chunk restarts the time-out
+                            if let Some(pending) =
+                                request.as_ref().and_then(|r| self.pending_ai.get_mut(r))
+                            {
+                                pending.last_activity = Instant::now();
+                            }
+                        }
+                    }
+                    None => self.messages.push(ChatLine::AiLive {
+                        stream: Some(id),
+                        request: request_id,
+                        from,
+                        prompt,
+                        response: delta,
+                        started_ms: now_unix_ms(),
+                        thread,
+                        at,
+                    }),
+                },
+                UiEvent::Incoming(Incoming::AiDone {
+                    id,
+                    response_ms,
+                    tokens,
+                    cost,
+                    cancelled,
+                    at,
+                }) => {
+                    let Some(idx) = self.live_stream_index(&id) else {
+                        continue;
+                    };
+                    if let ChatLine::AiLive {
+                        from,
+                        prompt,
+                        response,
+                        thread,
+                        request,
+                        at: first_at,
+                        ..
+                    } = self.messages[idx].clone()
+                    {
+                        let rtt = self.finish_ai_request(request.as_deref());
+                        let mut stats = ai_stats_with_rtt(response_ms, tokens, cost, rtt);
+                        if cancelled {
+                            stats.push_str(" | geannuleerd");
+                        }
+                        self.unread += 1;
+                        if self.is_own_ai_answer(request.as_deref(), rtt, &from) {
+                            self.record_ai_usage(usage, response_ms, tokens, cost);
+                        }
+                        let line = ChatLine::Ai {
+                            from,
+                            prompt,
+                            response,
+                            stats,
+                            thread: Some(thread.unwrap_or_else(new_thread_id)),
+                            at: first_at.or(at),
+                        };
+                        self.finish_live_ai(idx, line);
+                    }
+                }
+                UiEvent::Raw(frame) => {
+                    if let Some(recorder) = self.recorder.as_mut().filter(|_| !self.replaying) {
+                        if let Err(err) = recorder.record(&frame) {
+                            self.recorder = None;
+                            self.push_line(ChatLine::Error(format!("{} (recording stopped)", err)));
+                        }
+                    }
+                    if frame.line.starts_with(">> ") {
+                        self.metrics.ws_out_frames += 1;
+                    } else if frame.line.starts_with("<< ") {
+                        self.metrics.ws_in_frames += 1;
+                    }
+                    self.record_raw_line(frame);
+                }
+                UiEvent::Security(info) => {
+                    self.capabilities = Capabilities::from_headers(&info.headers);
+                    if let Some(warning) = self.capabilities.version.as_deref().and_then(version_warning) {
+                        self.push_notice(ChatLine::System {
+                            text: warning,
+                            at: None,
+                        });
+                    }
+                    self.security_info = Some(info);
+                }
+            }
+        }
+    }
+
+    fn record_error_event(&mut self) {
+        let now = Instant::now();
+        self.metrics.error_timestamps.push_back(now);
+        self.prune_old_errors(now);
+    }
+
+    pub fn prune_old_errors(&mut self, now: Instant) {
+        while let Some(oldest) = self.metrics.error_timestamps.front() {
+            if now.duration_since(*oldest) > Duration::from_secs(60) {
+                let _ = self.metrics.error_timestamps.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn record_raw_line(&mut self, frame: RawFrame) {
+        let payload = frame
+            .line
+            .strip_prefix(">> ")
+            .or_else(|| frame.line.strip_prefix("<< "))
+            .unwrap_or(&frame.line)
+            .to_string();
+        let (frame_type, auto_ping) = classify_payload(&payload);
+        let schema_issues = if frame.line.starts_with("<< ") {
+            validate_incoming_text(&payload)
+        } else {
+            Vec::new()
+        };
+        if schema_issues.iter().any(SchemaIssue::is_violation) {
+            self.metrics.schema_violations += 1;
+        }
+        self.raw_messages.push_back(RawLine {
+            line: frame.line,
+            payload,
+            wall_ms: frame.wall_ms,
+            mono: frame.mono,
+            hand_crafted: frame.hand_crafted,
+            frame_type,
+            auto_ping,
+            schema_issues,
+        });
+        while self.raw_messages.len() > MAX_RAW_MESSAGES {
+            let _ = self.raw_messages.pop_front();
+            if let Some(sel) = self.selected_raw_index {
+                self.selected_raw_index = sel.checked_sub(1);
+            }
+            if let Some(sel) = self.compare_raw_index {
+                self.compare_raw_index = sel.checked_sub(1);
+            }
+        }
+    }
+
+    fn record_latency_sample(&mut self, ms: f32) {
+        self.latency_samples.push_back(ms);
+        while self.latency_samples.len() > MAX_LATENCY_SAMPLES {
+            let _ = self.latency_samples.pop_front();
+        }
+    }
+
+    /// Asks for `status` right after connecting to learn the server's
+    /// capabilities. Only the answer to this request stays out of the chat.
+    fn send_status_probe(&mut self) {
+        if let Some(tx) = &self.ws_tx {
+            if tx.send(WsCommand::Send(Outgoing::Status)).is_ok() {
+                self.pending_status.push_back(true);
+            }
+        }
+    }
+
+    pub fn maybe_send_auto_ping(&mut self) {
+        if !self.connected {
+            return;
+        }
+        let now = Instant::now();
+        let should_ping = self
+            .last_auto_ping_sent
+            .map(|last| now.duration_since(last).as_secs() >= AUTO_PING_INTERVAL_SECS)
+            .unwrap_or(true);
+        if !should_ping {
+            return;
+        }
+
+        if let Some(tx) = &self.ws_tx {
+            let token = format!("{}{}", AUTO_PING_PREFIX, uuid::Uuid::new_v4());
+            self.pending_pings.insert(token.clone(), now);
+            let _ = tx.send(WsCommand::Send(Outgoing::Ping { token: Some(token) }));
+            self.last_auto_ping_sent = Some(now);
+        }
+    }
+
+    pub fn latency_avg_ms(&self) -> Option<f32> {
+        if self.latency_samples.is_empty() {
+            return None;
+        }
+        let sum: f32 = self.latency_samples.iter().copied().sum();
+        Some(sum / self.latency_samples.len() as f32)
+    }
+
+    pub fn latency_p95_ms(&self) -> Option<f32> {
+        if self.latency_samples.is_empty() {
+            return None;
+        }
+        let mut values = self.latency_samples.iter().copied().collect::<Vec<_>>();
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let idx = ((values.len() as f32) * 0.95).ceil() as usize;
+        values.get(idx.saturating_sub(1)).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::mpsc::{channel, Sender};
+    use std::time::{Duration, Instant};
+
+    use serde_json::json;
+
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+    use super::{ChatLine, OutboxAction, Session, AI_TIMEOUT_SECS, MAX_THREAD_CONTEXT};
+    use crate::ai_usage::AiUsageLog;
+    use crate::network::{UiEvent, WsCommand};
+    use crate::outbox::{describe_outgoing, Outbox};
+    use crate::protocol::{Incoming, Outgoing};
+    use crate::settings::ServerProfile;
+
+    struct Harness {
+        session: Session,
+        events: Sender<UiEvent>,
+        sent: UnboundedReceiver<WsCommand>,
+        outbox: Outbox,
+        usage: AiUsageLog,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            let mut session = Session::new(0, &ServerProfile::new("Test", "ws://test"));
+            session.username = "Tester".to_string();
+            let (ws_tx, sent) = unbounded_channel();
+            let (events, ui_rx) = channel();
+            session.ws_tx = Some(ws_tx);
+            session.ui_rx = Some(ui_rx);
+            Self {
+                session,
+                events,
+                sent,
+                outbox: Outbox::default(),
+                usage: AiUsageLog::default(),
+            }
+        }
+
+        fn feed(&mut self, event: UiEvent) {
+            self.events.send(event).unwrap();
+            self.session
+                .process_incoming(&mut self.outbox, &BTreeMap::new(), &mut self.usage);
+        }
+
+        /// Connects and sends `/ai prompt`; returns its request id.
+        fn ask(&mut self, prompt: &str) -> String {
+            if !self.session.connected {
+                self.feed(UiEvent::Connected);
+            }
+            let request = self.session.ai_request(prompt.to_string());
+            let Outgoing::Ai {
+                request_id: Some(id),
+                ..
+            } = &request
+            else {
+                unreachable!();
+            };
+            let id = id.clone();
+            self.session.send_ws(request, &mut self.outbox);
+            self.sent();
+            id
+        }
+
+        fn sent(&mut self) -> Vec<String> {
+            let mut sent = Vec::new();
+            while let Ok(command) = self.sent.try_recv() {
+                if let WsCommand::Send(outgoing) = command {
+                    sent.push(describe_outgoing(&outgoing));
+                }
+            }
+            sent
+        }
+    }
+
+    fn incoming(value: serde_json::Value) -> UiEvent {
+        UiEvent::Incoming(serde_json::from_value::<Incoming>(value).unwrap())
+    }
+
+    fn ai_answer(from: &str, prompt: &str, request: Option<&str>) -> UiEvent {
+        let mut answer = json!({
+            "type": "ai",
+            "from": from,
+            "prompt": prompt,
+            "response": format!("antwoord voor {}", from),
+            "responseMs": 40,
+            "tokens": 12,
+            "cost": 0.001,
+        });
+        if let Some(request) = request {
+            answer["requestId"] = json!(request);
+        }
+        incoming(answer)
+    }
+
+    fn chunk(id: &str, prompt: &str, delta: &str, request: Option<&str>) -> UiEvent {
+        let mut chunk = json!({
+            "type": "aiChunk",
+            "id": id,
+            "from": "Tester",
+            "prompt": prompt,
+            "delta": delta,
+        });
+        if let Some(request) = request {
+            chunk["requestId"] = json!(request);
+        }
+        incoming(chunk)
+    }
+
+    fn live_cards(session: &Session) -> usize {
+        session
+            .messages
+            .iter()
+            .filter(|line| matches!(line, ChatLine::AiLive { .. }))
+            .count()
+    }
+
+    /// Final AI lines as (from, response, stats).
+    fn answers(session: &Session) -> Vec<(String, String, String)> {
+        session
+            .messages
+            .iter()
+            .filter_map(|line| match line {
+                ChatLine::Ai {
+                    from,
+                    response,
+                    stats,
+                    ..
+                } => Some((from.clone(), response.clone(), stats.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn answer(prompt: &str, thread: &str) -> ChatLine {
+        ChatLine::Ai {
+            from: "Tester".to_string(),
+            prompt: prompt.to_string(),
+            response: format!("antwoord op {}", prompt),
+            stats: String::new(),
+            thread: Some(thread.to_string()),
+            at: None,
+        }
+    }
+
+    fn status_cards(session: &Session) -> usize {
+        session
+            .messages
+            .iter()
+            .filter(|line| matches!(line, ChatLine::StatusCard { .. }))
+            .count()
+    }
+
+    fn chat(text: &str) -> Outgoing {
+        Outgoing::Chat {
+            text: text.to_string(),
+            room: None,
+        }
+    }
+
+    #[test]
+    fn outbox_flushes_in_order_on_connect_and_counts_retries() {
+        let mut h = Harness::new();
+        h.session.send_ws(chat("een"), &mut h.outbox);
+        h.session.send_ws(chat("twee"), &mut h.outbox);
+        assert_eq!(h.outbox.items.len(), 2);
+
+        let id = h.outbox.items[0].id.clone();
+        h.session
+            .apply_outbox_action(OutboxAction::Retry(id), &mut h.outbox);
+        assert_eq!(h.outbox.items[0].attempts, 1);
+        assert!(h.outbox.items[0].last_error.is_some());
+        assert!(h.sent().is_empty());
+
+        h.feed(UiEvent::Connected);
+        assert_eq!(h.sent(), vec!["/status", "een", "twee"]);
+        assert!(h.outbox.items.is_empty());
+    }
+
+    #[test]
+    fn only_the_connect_probe_answer_is_hidden() {
+        let status = || {
+            incoming(json!({
+                "type": "status",
+                "version": "1.0",
+                "uptimeSeconds": 5,
+                "userCount": 2,
+                "messagesSent": 0,
+                "messagesPerSecond": 0.0,
+                "memoryMb": 1.0,
+            }))
+        };
+        let mut h = Harness::new();
+        h.feed(UiEvent::Connected);
+        h.session.send_ws(Outgoing::Status, &mut h.outbox);
+        assert_eq!(h.sent(), vec!["/status", "/status"]);
+
+        h.feed(status());
+        assert_eq!(status_cards(&h.session), 0);
+        h.feed(status());
+        assert_eq!(status_cards(&h.session), 1);
+        // Nobody asked for this one, so it is shown as well
+        h.feed(status());
+        assert_eq!(status_cards(&h.session), 2);
+    }
+
+    #[test]
+    fn followups_are_grouped_under_their_thread_root() {
+        let mut h = Harness::new();
+        h.session.messages = vec![
+            answer("eerste", "a"),
+            ChatLine::System {
+                text: "tussendoor".to_string(),
+                at: None,
+            },
+            answer("tweede", "b"),
+            answer("vervolg", "a"),
+            answer("nog eens", "a"),
+        ];
+
+        let followups = h.session.thread_followups();
+        assert_eq!(followups.get(&0), Some(&vec![3, 4]));
+        assert_eq!(followups.get(&2), None);
+        assert_eq!(h.session.thread_root_prompt("a"), Some("eerste"));
+        assert_eq!(h.session.thread_root_prompt("c"), None);
+        let prompts = h
+            .session
+            .thread_turns("a")
+            .into_iter()
+            .map(|turn| turn.prompt)
+            .collect::<Vec<_>>();
+        assert_eq!(prompts, vec!["eerste", "vervolg", "nog eens"]);
+    }
+
+    #[test]
+    fn thread_context_keeps_the_latest_turns() {
+        let mut h = Harness::new();
+        h.session.messages = (0..MAX_THREAD_CONTEXT + 2)
+            .map(|n| answer(&n.to_string(), "a"))
+            .collect();
+        let turns = h.session.thread_turns("a");
+        assert_eq!(turns.len(), MAX_THREAD_CONTEXT);
+        assert_eq!(turns[0].prompt, "2");
+        assert_eq!(turns[MAX_THREAD_CONTEXT - 1].prompt, (MAX_THREAD_CONTEXT + 1).to_string());
+    }
+
+    #[test]
+    fn followup_request_carries_thread_and_context() {
+        let mut h = Harness::new();
+        h.feed(UiEvent::Connected);
+        h.sent();
+        h.session.messages.push(answer("eerste", "a"));
+        h.session.messages.push(answer("los", "b"));
+        h.session.ai_reply_thread = Some("a".to_string());
+
+        let request = h.session.ai_request("en verder?".to_string());
+        h.session.send_ws(request, &mut h.outbox);
+        assert_eq!(h.session.ai_reply_thread, None);
+        let Ok(WsCommand::Send(Outgoing::Ai {
+            prompt,
+            thread,
+            context,
+            request_id,
+        })) = h.sent.try_recv()
+        else {
+            panic!("expected an /ai request");
+        };
+        assert_eq!(prompt, "en verder?");
+        assert_eq!(thread.as_deref(), Some("a"));
+        assert_eq!(context.len(), 1);
+        assert_eq!(context[0].prompt, "eerste");
+        assert!(h.session.pending_ai.contains_key(&request_id.unwrap()));
+        assert_eq!(h.session.messages.last().and_then(ChatLine::thread), Some("a"));
+    }
+
+    #[test]
+    fn answer_is_matched_by_request_id_not_by_name() {
+        let mut h = Harness::new();
+        let request = h.ask("hoe laat?");
+
+        // Same name and prompt from another tab: not this card, not our usage
+        h.feed(ai_answer("Tester", "hoe laat?", Some("ander-tabblad")));
+        assert_eq!(live_cards(&h.session), 1);
+        assert!(h.session.ai_usage.is_empty());
+
+        h.feed(ai_answer("Tester", "hoe laat?", Some(&request)));
+        assert_eq!(live_cards(&h.session), 0);
+        let answers = answers(&h.session);
+        assert_eq!(answers.len(), 2);
+        // The card keeps its place above the other tab's answer
+        assert!(answers[0].2.contains(" | rtt "));
+        assert!(!answers[1].2.contains("rtt"));
+        assert!(h.session.pending_ai.is_empty());
+        assert_eq!(h.session.ai_usage.len(), 1);
+        assert_eq!(h.usage.records[0].profile, "Test");
+        assert_eq!(h.usage.records[0].profile_id, h.session.profile_id);
+    }
+
+    #[test]
+    fn stream_chunks_follow_their_stream_id() {
+        let mut h = Harness::new();
+        let request = h.ask("vertel");
+        h.feed(chunk("s1", "vertel", "een ", Some(&request)));
+        // Later chunks may only carry the stream id
+        h.feed(chunk("s1", "vertel", "twee", None));
+        h.feed(incoming(json!({"type": "aiDone", "id": "s1", "responseMs": 80, "tokens": 5})));
+
+        let answers = answers(&h.session);
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0].1, "een twee");
+        assert!(answers[0].2.contains(" | rtt "));
+        assert_eq!(live_cards(&h.session), 0);
+        assert_eq!(h.session.ai_usage.len(), 1);
+    }
+
+    #[test]
+    fn answers_without_request_id_fall_back_to_sender_and_prompt() {
+        let mut h = Harness::new();
+        h.ask("wie?");
+        h.feed(ai_answer("Ander", "wie?", None));
+        assert_eq!(live_cards(&h.session), 1);
+        assert!(h.session.ai_usage.is_empty());
+
+        h.feed(ai_answer("tester", "wie?", None));
+        assert_eq!(live_cards(&h.session), 0);
+        let answers = answers(&h.session);
+        assert_eq!(answers[0].0, "tester");
+        assert!(answers[0].2.contains(" | rtt "));
+        assert_eq!(h.session.ai_usage.len(), 1);
+    }
+
+    #[test]
+    fn silent_requests_expire_and_stalled_streams_are_broken_off() {
+        let timeout = Duration::from_secs(AI_TIMEOUT_SECS + 1);
+        let mut h = Harness::new();
+        let silent = h.ask("stil");
+        let streaming = h.ask("traag");
+        h.feed(chunk("s1", "traag", "half", Some(&streaming)));
+        for pending in h.session.pending_ai.values_mut() {
+            pending.sent = Instant::now() - timeout;
+        }
+        h.session.pending_ai.get_mut(&silent).unwrap().last_activity = Instant::now() - timeout;
+
+        // The chunk restarted the stream's time-out
+        h.session.expire_ai_requests();
+        assert_eq!(live_cards(&h.session), 1);
+        assert!(h.session.pending_ai.contains_key(&streaming));
+        assert!(answers(&h.session).is_empty());
+
+        h.session.pending_ai.get_mut(&streaming).unwrap().last_activity = Instant::now() - timeout;
+        h.session.expire_ai_requests();
+        assert_eq!(live_cards(&h.session), 0);
+        assert!(h.session.pending_ai.is_empty());
+        assert_eq!(h.sent(), vec!["aiCancel s1"]);
+        let answers = answers(&h.session);
+        assert_eq!(answers.len(), 1);
+        assert_eq!((answers[0].1.as_str(), answers[0].2.as_str()), ("half", "afgebroken"));
+        let errors = h
+            .session
+            .messages
+            .iter()
+            .filter(|line| matches!(line, ChatLine::Error(_)))
+            .count();
+        assert_eq!(errors, 2);
+    }
+
+    #[test]
+    fn messages_queued_before_a_rename_are_still_flushed() {
+        let mut h = Harness::new();
+        let identity = h.session.identity();
+        h.session.send_ws(chat("een"), &mut h.outbox);
+        // `/name X` and a profile rename while offline
+        h.session.preferred_username = "X".to_string();
+        h.session.send_ws(
+            Outgoing::SetName {
+                name: "X".to_string(),
+            },
+            &mut h.outbox,
+        );
+        h.session.profile_name = "Hernoemd".to_string();
+        h.session.send_ws(chat("twee"), &mut h.outbox);
+        assert_eq!(h.session.identity(), identity);
+
+        h.feed(UiEvent::Connected);
+        let sent = h.sent();
+        let chats = sent
+            .iter()
+            .filter(|text| ["een", "twee"].contains(&text.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(chats, vec!["een", "twee"]);
+        assert!(h.outbox.items.is_empty());
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::PathBuf;
 
@@ -6,25 +7,155 @@ use serde::{Deserialize, Serialize};
 const SETTINGS_FILE: &str = "settings.json";
 const SETTINGS_DIR: &str = ".config/cybox-chat-gui";
 const LEGACY_SETTINGS_FILE: &str = ".cybox-chat-gui-settings.json";
+const DEFAULT_SERVER_URL: &str = "ws://127.0.0.1:3001";
+const DEFAULT_PROFILE_NAME: &str = "Default";
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct AppSettings {
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TlsOptions {
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+    #[serde(default)]
+    pub accept_invalid_hostnames: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerProfile {
+    /// Stable across renames; history, the outbox and AI usage are kept per
+    /// id. Older settings files have none until one is handed out on load.
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
     pub server_url: String,
+    #[serde(default)]
     pub username: String,
+    #[serde(default)]
+    pub auto_connect: bool,
+    #[serde(default)]
+    pub tls: TlsOptions,
+}
+
+impl ServerProfile {
+    pub fn new(name: &str, server_url: &str) -> Self {
+        Self {
+            id: new_profile_id(),
+            name: name.to_string(),
+            server_url: server_url.to_string(),
+            username: String::new(),
+            auto_connect: false,
+            tls: TlsOptions::default(),
+        }
+    }
+}
+
+fn new_profile_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// What to do with a server message type this client does not know yet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownTypeAction {
+    /// Neutral JSON card in the chat pane.
+    #[default]
+    Show,
+    Hide,
+    /// Error line, as before forward-compatible handling existed.
+    Warn,
+}
+
+impl UnknownTypeAction {
+    pub const ALL: [UnknownTypeAction; 3] = [
+        UnknownTypeAction::Show,
+        UnknownTypeAction::Hide,
+        UnknownTypeAction::Warn,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            UnknownTypeAction::Show => "Tonen",
+            UnknownTypeAction::Hide => "Verbergen",
+            UnknownTypeAction::Warn => "Waarschuwen",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppSettings {
+    #[serde(default)]
+    pub profiles: Vec<ServerProfile>,
+    #[serde(default)]
+    pub active_profile: String,
+    #[serde(default = "default_auto_reconnect")]
+    pub auto_reconnect: bool,
+    #[serde(default = "default_reconnect_max_attempts")]
+    pub reconnect_max_attempts: u32,
+    #[serde(default = "default_history_enabled")]
+    pub history_enabled: bool,
+    #[serde(default = "default_history_max_days")]
+    pub history_max_days: u32,
+    #[serde(default = "default_history_max_mb")]
+    pub history_max_mb: u32,
+    /// Per unknown message type; types not listed are shown.
+    #[serde(default)]
+    pub unknown_types: BTreeMap<String, UnknownTypeAction>,
+    /// Render Markdown from other users without links and headings.
+    #[serde(default = "default_markdown_safe_mode")]
+    pub markdown_safe_mode: bool,
+    /// Warn before another `/ai` once today's AI cost reaches this (USD).
+    #[serde(default)]
+    pub ai_daily_limit: Option<f64>,
 }
 
 impl Default for AppSettings {
     fn default() -> Self {
         Self {
-            server_url: "ws://127.0.0.1:3001".to_string(),
-            username: String::new(),
+            profiles: vec![ServerProfile::new(DEFAULT_PROFILE_NAME, DEFAULT_SERVER_URL)],
+            active_profile: DEFAULT_PROFILE_NAME.to_string(),
+            auto_reconnect: default_auto_reconnect(),
+            reconnect_max_attempts: default_reconnect_max_attempts(),
+            history_enabled: default_history_enabled(),
+            history_max_days: default_history_max_days(),
+            history_max_mb: default_history_max_mb(),
+            unknown_types: BTreeMap::new(),
+            markdown_safe_mode: default_markdown_safe_mode(),
+            ai_daily_limit: None,
         }
     }
 }
 
+fn default_auto_reconnect() -> bool {
+    true
+}
+
+fn default_reconnect_max_attempts() -> u32 {
+    10
+}
+
+fn default_history_enabled() -> bool {
+    true
+}
+
+fn default_history_max_days() -> u32 {
+    30
+}
+
+fn default_history_max_mb() -> u32 {
+    20
+}
+
+fn default_markdown_safe_mode() -> bool {
+    true
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    std::env::var("HOME")
+        .ok()
+        .map(|home| PathBuf::from(home).join(SETTINGS_DIR))
+}
+
 fn settings_path() -> PathBuf {
-    if let Ok(home) = std::env::var("HOME") {
-        return PathBuf::from(home).join(SETTINGS_DIR).join(SETTINGS_FILE);
+    if let Some(dir) = config_dir() {
+        return dir.join(SETTINGS_FILE);
     }
 
     PathBuf::from(".cybox-chat-gui-settings.json")
@@ -32,15 +163,54 @@ fn settings_path() -> PathBuf {
 
 pub fn load_settings() -> AppSettings {
     let path = settings_path();
-    let raw = match fs::read_to_string(&path) {
-        Ok(raw) => raw,
-        Err(_) => match fs::read_to_string(LEGACY_SETTINGS_FILE) {
-            Ok(legacy_raw) => legacy_raw,
-            Err(_) => return AppSettings::default(),
-        },
+    let raw = fs::read_to_string(&path)
+        .or_else(|_| fs::read_to_string(LEGACY_SETTINGS_FILE))
+        .unwrap_or_default();
+
+    let settings = parse_settings(&raw);
+    // Ids handed out just now must survive a restart, or history and
+    // queued messages would no longer be found
+    if settings.profiles.iter().any(|p| !raw.contains(&p.id)) {
+        let _ = save_settings(&settings);
+    }
+    settings
+}
+
+/// Fields of the pre-profile settings file, which had a single server.
+#[derive(Deserialize, Default)]
+struct LegacyServerFields {
+    server_url: Option<String>,
+    username: Option<String>,
+}
+
+fn parse_settings(raw: &str) -> AppSettings {
+    let Ok(mut settings) = serde_json::from_str::<AppSettings>(raw) else {
+        return AppSettings::default();
     };
 
-    serde_json::from_str::<AppSettings>(&raw).unwrap_or_default()
+    if settings.profiles.is_empty() {
+        let legacy = serde_json::from_str::<LegacyServerFields>(raw).unwrap_or_default();
+        let mut profile = ServerProfile::new(
+            DEFAULT_PROFILE_NAME,
+            legacy.server_url.as_deref().unwrap_or(DEFAULT_SERVER_URL),
+        );
+        profile.username = legacy.username.unwrap_or_default();
+        settings.profiles.push(profile);
+    }
+    for profile in &mut settings.profiles {
+        if profile.id.is_empty() {
+            profile.id = new_profile_id();
+        }
+    }
+    if !settings
+        .profiles
+        .iter()
+        .any(|p| p.name == settings.active_profile)
+    {
+        settings.active_profile = settings.profiles[0].name.clone();
+    }
+
+    settings
 }
 
 pub fn save_settings(settings: &AppSettings) -> Result<(), String> {
@@ -54,3 +224,29 @@ pub fn save_settings(settings: &AppSettings) -> Result<(), String> {
 
     fs::write(path, json).map_err(|err| format!("Failed to write settings file: {}", err))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::parse_settings;
+
+    #[test]
+    fn legacy_single_server_file_becomes_default_profile() {
+        let raw = r#"{"server_url":"wss://chat.cybox.io/ws","username":"Bas","auto_reconnect":false}"#;
+        let settings = parse_settings(raw);
+
+        assert_eq!(settings.profiles.len(), 1);
+        assert_eq!(settings.active_profile, "Default");
+        assert_eq!(settings.profiles[0].server_url, "wss://chat.cybox.io/ws");
+        assert_eq!(settings.profiles[0].username, "Bas");
+        assert!(!settings.auto_reconnect);
+    }
+
+    #[test]
+    fn unknown_active_profile_falls_back_to_first() {
+        let raw = r#"{"profiles":[{"name":"Staging","server_url":"wss://staging/ws"}],"active_profile":"Gone"}"#;
+        let settings = parse_settings(raw);
+
+        assert_eq!(settings.active_profile, "Staging");
+        assert!(!settings.profiles[0].id.is_empty());
+    }
+}